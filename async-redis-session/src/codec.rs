@@ -0,0 +1,118 @@
+//! # Codec
+//!
+//! Serializes the sessions stored in redis. JSON is the default; MessagePack
+//! (the `msgpack` feature) is more compact and faster to read (see
+//! `benches/codec.rs`).
+//!
+//! MessagePack records start with a format marker, records without one are
+//! JSON: the sessions written before the codec changed stay readable (and
+//! are rewritten with the new codec when they are stored again).
+//!
+//! `mp:v1:<MessagePack>`
+
+use async_session::{serde_json, Result, Session};
+
+const MSGPACK_PREFIX: &[u8] = b"mp:v1:";
+
+/// The format of the new records; records in either format are read
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Codec {
+    /// `async_session::serde_json`
+    #[default]
+    Json,
+    /// `rmp_serde`, with a format marker
+    #[cfg(feature = "msgpack")]
+    MessagePack,
+}
+
+impl Codec {
+    /// serializes the session
+    /// ```rust
+    /// # use async_redis_session::Codec;
+    /// # use async_session::Session;
+    /// let record = Codec::Json.encode(&Session::new()).unwrap();
+    /// assert!(Codec::decode(&record).is_ok());
+    /// ```
+    pub fn encode(self, session: &Session) -> Result<Vec<u8>> {
+        match self {
+            Codec::Json => Ok(serde_json::to_vec(session)?),
+            #[cfg(feature = "msgpack")]
+            Codec::MessagePack => {
+                let mut record = MSGPACK_PREFIX.to_vec();
+                record.append(&mut rmp_serde::to_vec(session)?);
+                Ok(record)
+            }
+        }
+    }
+
+    /// deserializes a record written with any codec; MessagePack records
+    /// require the `msgpack` feature
+    pub fn decode(record: &[u8]) -> Result<Session> {
+        match record.strip_prefix(MSGPACK_PREFIX) {
+            #[cfg(feature = "msgpack")]
+            Some(record) => Ok(rmp_serde::from_slice(record)?),
+            #[cfg(not(feature = "msgpack"))]
+            Some(_) => Err(async_session::Error::msg(
+                "MessagePack sessions require the msgpack feature",
+            )),
+            None => Ok(serde_json::from_slice(record)?),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn session() -> Result<Session> {
+        let mut session = Session::new();
+        session.insert("user", "google/1")?;
+        session.insert("count", 42)?;
+        session.expire_in(Duration::from_secs(60));
+        Ok(session)
+    }
+
+    #[test]
+    fn sessions_round_trip_with_either_codec() -> Result {
+        let session = session()?;
+        let codecs = vec![
+            Codec::Json,
+            #[cfg(feature = "msgpack")]
+            Codec::MessagePack,
+        ];
+        for codec in codecs {
+            let decoded = Codec::decode(&codec.encode(&session)?)?;
+            assert_eq!(session.id(), decoded.id());
+            assert_eq!(session.expiry(), decoded.expiry());
+            assert_eq!(Some(42), decoded.get::<usize>("count"));
+            assert_eq!(Some("google/1".to_string()), decoded.get::<String>("user"));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn json_records_are_read_after_switching_codec() -> Result {
+        let session = session()?;
+        let record = serde_json::to_string(&session)?;
+        assert_eq!(session.id(), Codec::decode(record.as_bytes())?.id());
+        Ok(())
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn message_pack_records_are_smaller() -> Result {
+        let session = session()?;
+        let json = Codec::Json.encode(&session)?;
+        let message_pack = Codec::MessagePack.encode(&session)?;
+        assert!(message_pack.starts_with(MSGPACK_PREFIX));
+        assert!(message_pack.len() < json.len());
+        Ok(())
+    }
+
+    #[cfg(not(feature = "msgpack"))]
+    #[test]
+    fn message_pack_records_require_the_feature() {
+        assert!(Codec::decode(b"mp:v1:\x93").is_err());
+    }
+}
@@ -0,0 +1,200 @@
+//! # KeyRing
+//!
+//! Encrypts the serialized sessions (AES-256-GCM), and the other records the
+//! application keeps next to them (e.g., tokens). The record names the key
+//! that encrypted it, so keys can be rotated: new records use the current
+//! key, and records encrypted with an older key in the ring stay readable.
+//!
+//! `enc:v1:<key id>:<base64 (nonce, ciphertext and tag)>`
+//!
+//! The redis key of the record is authenticated with it, so a record cannot
+//! be copied to another session (or key).
+
+use async_session::{base64, Result};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::fmt;
+
+const PREFIX: &[u8] = b"enc:v1:";
+
+/// The keys used to encrypt (the current key) and decrypt the sessions
+#[derive(Clone)]
+pub struct KeyRing {
+    current: u32,
+    keys: Vec<(u32, Vec<u8>)>,
+    random: SystemRandom,
+}
+
+/// What a record decrypts to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decrypted {
+    /// the record as it was before encryption
+    Record(Vec<u8>),
+    /// the record was encrypted with a key that is no longer in the ring
+    UnknownKey,
+}
+
+impl KeyRing {
+    /// a ring with the key used to encrypt new records (32 bytes)
+    /// ```rust
+    /// # use async_redis_session::KeyRing;
+    /// let ring = KeyRing::new(2, &[7; 32]).unwrap()
+    ///     .with_key(1, &[3; 32]).unwrap();
+    /// ```
+    pub fn new(id: u32, key: &[u8]) -> Result<Self> {
+        Self {
+            current: id,
+            keys: Vec::new(),
+            random: SystemRandom::new(),
+        }
+        .with_key(id, key)
+    }
+
+    /// adds a key used to decrypt records (e.g., the key before rotation)
+    pub fn with_key(mut self, id: u32, key: &[u8]) -> Result<Self> {
+        if key.len() != AES_256_GCM.key_len() {
+            return Err(async_session::Error::msg(format!(
+                "session key {} must be {} bytes",
+                id,
+                AES_256_GCM.key_len()
+            )));
+        }
+        self.keys.retain(|(other, _)| *other != id);
+        self.keys.push((id, key.to_vec()));
+        Ok(self)
+    }
+
+    fn key(&self, id: u32) -> Option<LessSafeKey> {
+        self.keys
+            .iter()
+            .find(|(other, _)| *other == id)
+            .and_then(|(_, key)| UnboundKey::new(&AES_256_GCM, key).ok())
+            .map(LessSafeKey::new)
+    }
+
+    /// encrypts the record stored at the redis key, with the current key
+    pub fn encrypt(&self, redis_key: &str, record: &[u8]) -> Result<Vec<u8>> {
+        let key = self
+            .key(self.current)
+            .ok_or_else(|| async_session::Error::msg("the current session key is invalid"))?;
+        let mut nonce = [0u8; NONCE_LEN];
+        self.random
+            .fill(&mut nonce)
+            .map_err(|_| async_session::Error::msg("failed to generate a nonce"))?;
+
+        let mut sealed = record.to_vec();
+        key.seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(redis_key.as_bytes()),
+            &mut sealed,
+        )
+        .map_err(|_| async_session::Error::msg("failed to encrypt the session"))?;
+
+        let mut payload = nonce.to_vec();
+        payload.append(&mut sealed);
+        let mut record = PREFIX.to_vec();
+        record.extend(format!("{}:", self.current).into_bytes());
+        record.extend(base64::encode_config(payload, base64::URL_SAFE_NO_PAD).into_bytes());
+        Ok(record)
+    }
+
+    /// records that are not encrypted (written before encryption was
+    /// enabled) are returned as they are
+    pub fn decrypt(&self, redis_key: &str, record: Vec<u8>) -> Result<Decrypted> {
+        let encrypted = match record.strip_prefix(PREFIX) {
+            Some(encrypted) => encrypted,
+            None => return Ok(Decrypted::Record(record)),
+        };
+        let invalid = || async_session::Error::msg("the encrypted session is invalid");
+        let encrypted = std::str::from_utf8(encrypted).map_err(|_| invalid())?;
+        let (id, payload) = encrypted.split_once(':').ok_or_else(invalid)?;
+        let id: u32 = id.parse().map_err(|_| invalid())?;
+        let key = match self.key(id) {
+            Some(key) => key,
+            None => return Ok(Decrypted::UnknownKey),
+        };
+        let payload =
+            base64::decode_config(payload, base64::URL_SAFE_NO_PAD).map_err(|_| invalid())?;
+        if payload.len() < NONCE_LEN {
+            return Err(invalid());
+        }
+        let (nonce, sealed) = payload.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| invalid())?;
+
+        let mut sealed = sealed.to_vec();
+        let session = key
+            .open_in_place(nonce, Aad::from(redis_key.as_bytes()), &mut sealed)
+            .map_err(|_| invalid())?;
+        Ok(Decrypted::Record(session.to_vec()))
+    }
+}
+
+impl fmt::Debug for KeyRing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyRing")
+            .field("current", &self.current)
+            .field(
+                "keys",
+                &self.keys.iter().map(|(id, _)| id).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(decrypted: Decrypted) -> Option<String> {
+        match decrypted {
+            Decrypted::Record(session) => String::from_utf8(session).ok(),
+            Decrypted::UnknownKey => None,
+        }
+    }
+
+    #[test]
+    fn records_are_readable_after_rotation() -> Result {
+        let before = KeyRing::new(1, &[1; 32])?;
+        let record = before.encrypt("{sessions/}id", br#"{"id":"id"}"#)?;
+        assert!(record.starts_with(b"enc:v1:1:"));
+        assert!(!String::from_utf8_lossy(&record).contains("id\""));
+
+        let after = KeyRing::new(2, &[2; 32])?.with_key(1, &[1; 32])?;
+        assert_eq!(
+            Some(r#"{"id":"id"}"#.to_string()),
+            session(after.decrypt("{sessions/}id", record.clone())?)
+        );
+        assert!(after
+            .encrypt("{sessions/}id", b"{}")?
+            .starts_with(b"enc:v1:2:"));
+
+        // the key was dropped from the ring
+        let retired = KeyRing::new(2, &[2; 32])?;
+        assert_eq!(None, session(retired.decrypt("{sessions/}id", record)?));
+        Ok(())
+    }
+
+    #[test]
+    fn records_are_bound_to_the_session_key() -> Result {
+        let ring = KeyRing::new(1, &[1; 32])?;
+        let record = ring.encrypt("{sessions/}a", b"{}")?;
+        assert!(ring.decrypt("{sessions/}b", record.clone()).is_err());
+
+        let mut tampered = record;
+        let last = tampered.len() - 1;
+        tampered[last] = if tampered[last] == b'A' { b'B' } else { b'A' };
+        assert!(ring.decrypt("{sessions/}a", tampered).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn plain_records_are_read_during_migration() -> Result {
+        let ring = KeyRing::new(1, &[1; 32])?;
+        assert_eq!(
+            Some(r#"{"id":"id"}"#.to_string()),
+            session(ring.decrypt("{sessions/}id", br#"{"id":"id"}"#.to_vec())?)
+        );
+        assert!(KeyRing::new(1, &[1; 16]).is_err());
+        Ok(())
+    }
+}
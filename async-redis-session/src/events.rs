@@ -0,0 +1,169 @@
+//! # Session events
+//!
+//! With an events channel (see [`RedisSessionStore::with_events`]) the store
+//! publishes the sessions it destroys, in the transaction that destroys
+//! them, so the services that cache the validity of a session can drop it.
+//!
+//! `{"event":"revoked","session_id":"<id>"}`
+//!
+//! 👉 `revoked` destroyed, signed out, or moved to a new id
+//! 👉 `expired` past the max lifetime; or the ttl ran out (keyspace
+//!    notifications, see [`SessionEvents`])
+//!
+//! [`RedisSessionStore::with_events`]: crate::RedisSessionStore::with_events
+
+use async_session::serde_json::{self, json, Value};
+use futures_util::StreamExt;
+use redis::aio::PubSub;
+use redis::{Client, RedisResult};
+use std::fmt;
+
+/// A session that is no longer valid
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionEvent {
+    /// destroyed before it expired
+    Revoked(String),
+    /// the session reached its ttl or its max lifetime
+    Expired(String),
+}
+
+impl SessionEvent {
+    /// the id of the session
+    pub fn session_id(&self) -> &str {
+        match self {
+            SessionEvent::Revoked(id) | SessionEvent::Expired(id) => id,
+        }
+    }
+
+    pub(crate) fn message(&self) -> String {
+        let event = match self {
+            SessionEvent::Revoked(_) => "revoked",
+            SessionEvent::Expired(_) => "expired",
+        };
+        json!({ "event": event, "session_id": self.session_id() }).to_string()
+    }
+
+    fn parse(message: &str) -> Option<Self> {
+        let message: Value = serde_json::from_str(message).ok()?;
+        let id = message.get("session_id")?.as_str()?.to_string();
+        match message.get("event")?.as_str()? {
+            "revoked" => Some(SessionEvent::Revoked(id)),
+            "expired" => Some(SessionEvent::Expired(id)),
+            _ => None,
+        }
+    }
+}
+
+/// # SessionEvents
+///
+/// Receives the events published by the stores that share the channel (see
+/// [`RedisSessionStore::subscribe`]).
+///
+/// The sessions that reach their ttl are only reported when redis publishes
+/// keyspace notifications for expired keys (`notify-keyspace-events Ex`),
+/// and the store has a prefix; the store does not change the server's
+/// configuration.
+///
+/// The subscription ends when the connection is dropped (e.g., failover):
+/// subscribe again, and consider the sessions cached until then as stale.
+///
+/// [`RedisSessionStore::subscribe`]: crate::RedisSessionStore::subscribe
+pub struct SessionEvents {
+    pubsub: PubSub,
+    channel: String,
+    expired_channel: String,
+    prefix: Option<String>,
+}
+
+impl SessionEvents {
+    pub(crate) async fn subscribe(
+        client: Client,
+        channel: &str,
+        prefix: Option<String>,
+    ) -> RedisResult<Self> {
+        let expired_channel = format!(
+            "__keyevent@{}__:expired",
+            client.get_connection_info().redis.db
+        );
+        let mut pubsub = client.get_async_connection().await?.into_pubsub();
+        pubsub.subscribe(channel).await?;
+        pubsub.subscribe(&expired_channel).await?;
+        Ok(Self {
+            pubsub,
+            channel: channel.to_string(),
+            expired_channel,
+            prefix,
+        })
+    }
+
+    /// the next event; None when the subscription has ended
+    pub async fn next(&mut self) -> Option<SessionEvent> {
+        let mut messages = self.pubsub.on_message();
+        while let Some(message) = messages.next().await {
+            let payload: String = match message.get_payload() {
+                Ok(payload) => payload,
+                Err(_) => continue,
+            };
+            let event = if message.get_channel_name() == self.channel {
+                SessionEvent::parse(&payload)
+            } else if message.get_channel_name() == self.expired_channel {
+                expired(self.prefix.as_deref(), &payload)
+            } else {
+                None
+            };
+            if event.is_some() {
+                return event;
+            }
+        }
+        None
+    }
+}
+
+/// the expired key is a session of the store (the index and the users'
+/// indexes do not expire); without a prefix the sessions cannot be told
+/// apart from the other keys of the db, so no expiry is reported
+fn expired(prefix: Option<&str>, key: &str) -> Option<SessionEvent> {
+    let id = key.strip_prefix(prefix?)?;
+    Some(SessionEvent::Expired(id.to_string()))
+}
+
+impl fmt::Debug for SessionEvents {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SessionEvents")
+            .field("channel", &self.channel)
+            .field("expired_channel", &self.expired_channel)
+            .field("prefix", &self.prefix)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_round_trip() {
+        for event in [
+            SessionEvent::Revoked("a".to_string()),
+            SessionEvent::Expired("b".to_string()),
+        ] {
+            assert_eq!(Some(event.clone()), SessionEvent::parse(&event.message()));
+        }
+        assert_eq!(
+            r#"{"event":"revoked","session_id":"a"}"#,
+            SessionEvent::Revoked("a".to_string()).message()
+        );
+        assert_eq!(None, SessionEvent::parse(r#"{"event":"stored"}"#));
+    }
+
+    #[test]
+    fn expired_keys_outside_the_prefix_are_ignored() {
+        assert_eq!(
+            Some(SessionEvent::Expired("id".to_string())),
+            expired(Some("{sessions/}"), "{sessions/}id")
+        );
+        assert_eq!(None, expired(Some("{sessions/}"), "listings/id"));
+        // the other keys of the db expire too (e.g., cached listings)
+        assert_eq!(None, expired(None, "listings/id"));
+    }
+}
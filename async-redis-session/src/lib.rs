@@ -5,8 +5,8 @@
 //! use anyhow::Result;
 //! use anyhow;
 //!
-//! # fn main() -> async_session::Result { async_std::task::block_on(async {
-//! let store = RedisSessionStore::new("redis://127.0.0.1/")?;
+//! # fn main() -> async_session::Result { tokio::runtime::Runtime::new()?.block_on(async {
+//! let store = RedisSessionStore::new("redis://127.0.0.1/")?.with_pool_size(4);
 //!
 //! let mut session = Session::new();
 //! session.insert("key", "value")?;
@@ -27,14 +27,148 @@
     unused_qualifications
 )]
 
-use async_session::{async_trait, serde_json, Result, Session, SessionStore};
-use redis::{aio::Connection, AsyncCommands, Client, IntoConnectionInfo, RedisResult};
+mod codec;
+mod encryption;
+mod events;
+mod topology;
+pub use codec::Codec;
+pub use encryption::{Decrypted, KeyRing};
+pub use events::{SessionEvent, SessionEvents};
+pub use topology::{key_slot, RedisTopology};
+
+use async_session::{async_trait, Result, Session, SessionStore};
+use redis::{
+    aio::MultiplexedConnection, Client, Cmd, ErrorKind, FromRedisValue, IntoConnectionInfo,
+    Pipeline, RedisError, RedisResult, Script,
+};
+use std::fmt;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// the sorted set of session ids scored by expiry (seconds since the epoch)
+const INDEX_KEY: &str = "__index";
+/// the number of keys requested from each SCAN, and removed by each UNLINK
+const SCAN_COUNT: usize = 500;
+/// cluster: the sessions share a hash slot, so a prefix is required
+const CLUSTER_PREFIX: &str = "sessions/";
+/// the requests sent again while the deployment fails over
+const FAILOVER_RETRIES: u32 = 5;
+/// doubled after each retry (100ms .. 1.6s)
+const FAILOVER_BACKOFF: Duration = Duration::from_millis(100);
+/// the prefix of the sorted sets of a user's session ids (scored by expiry)
+const USERS_KEY: &str = "__users/";
+/// the session data entry that names the user of the session; the session
+/// is added to the user's index when it is stored
+pub const USER_ID_KEY: &str = "__user_id";
+/// the session data entry that holds the end of the session's lifetime
+/// (seconds since the epoch), see [`RedisSessionStore::with_max_lifetime`]
+const MAX_EXPIRY_KEY: &str = "__max_expiry";
+/// removes the session from the user's index (KEYS[1]); when it was
+/// listed, deletes the session (KEYS[2]) and its expiry index entry (KEYS[3])
+const DESTROY_USER_SESSION: &str = r"
+if redis.call('ZREM', KEYS[1], ARGV[1]) == 0 then
+    return 0
+end
+redis.call('DEL', KEYS[2])
+redis.call('ZREM', KEYS[3], ARGV[1])
+return 1
+";
+/// deletes the user's index (KEYS[1]) and the live sessions it lists, in
+/// batches, with their expiry index entries (KEYS[2]); returns their ids
+///
+/// ARGV: the key prefix, now (seconds since the epoch), the batch size
+const DESTROY_USER_SESSIONS: &str = r"
+redis.call('ZREMRANGEBYSCORE', KEYS[1], '-inf', ARGV[2])
+local ids = redis.call('ZRANGE', KEYS[1], 0, -1)
+redis.call('DEL', KEYS[1])
+local batch = tonumber(ARGV[3])
+for first = 1, #ids, batch do
+    local last = math.min(first + batch - 1, #ids)
+    local keys = {}
+    for i = first, last do
+        keys[#keys + 1] = ARGV[1] .. ids[i]
+    end
+    redis.call('UNLINK', unpack(keys))
+    redis.call('ZREM', KEYS[2], unpack(ids, first, last))
+end
+return ids
+";
 
 /// # RedisSessionStore
+///
+/// Commands are sent over a bounded pool of tokio multiplexed connections
+/// (one by default, see [`RedisSessionStore::with_pool_size`]). Each
+/// connection is opened on first use, shared by concurrent requests, and
+/// replaced when it is dropped by the server.
+///
+/// The store never blocks redis with `KEYS`: sessions are counted using an
+/// index (sorted by expiry), and cleared using `SCAN` and batched `UNLINK`.
+///
+/// The connections are opened using a [`RedisTopology`]: a single node, the
+/// master reported by Sentinel, or a Cluster. The prefix is a hash tag
+/// (`{sessions/}<id>`), so the sessions and the index share a hash slot.
+/// When the master moves (`READONLY`, `MOVED`, `MASTERDOWN`, refused
+/// connections..) the topology is resolved again and the request retried.
+///
+/// Sessions expire when their ttl runs out; with a sliding expiry each load
+/// extends the ttl (without writing the session), up to the max lifetime.
+///
+/// With a [`KeyRing`] the sessions are encrypted before they are sent to
+/// redis; sessions stored before encryption was enabled are still read.
+///
+/// Sessions are serialized as JSON, or MessagePack with the `msgpack`
+/// feature (see [`RedisSessionStore::with_codec`]); records in either
+/// format are read.
+///
+/// With an events channel, the sessions that are destroyed are published
+/// (see [`SessionEvent`]), and other services can subscribe to them.
 #[derive(Clone, Debug)]
 pub struct RedisSessionStore {
-    client: Client,
+    topology: RedisTopology,
     prefix: Option<String>,
+    pool: Arc<Pool>,
+    sliding_expiry: Option<Duration>,
+    max_lifetime: Option<Duration>,
+    key_ring: Option<KeyRing>,
+    codec: Codec,
+    events: Option<String>,
+}
+
+/// The connections, used in turn
+struct Pool {
+    slots: Vec<Mutex<Option<MultiplexedConnection>>>,
+    next: AtomicUsize,
+}
+
+impl Pool {
+    fn new(size: usize) -> Self {
+        Self {
+            slots: (0..size.max(1)).map(|_| Mutex::new(None)).collect(),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    fn slot(&self) -> &Mutex<Option<MultiplexedConnection>> {
+        let i = self.next.fetch_add(1, Ordering::Relaxed) % self.slots.len();
+        &self.slots[i]
+    }
+
+    /// the connections are to a former master
+    fn clear(&self) {
+        for slot in &self.slots {
+            *slot.lock().unwrap_or_else(|err| err.into_inner()) = None;
+        }
+    }
+}
+
+impl fmt::Debug for Pool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pool")
+            .field("size", &self.slots.len())
+            .finish()
+    }
 }
 
 impl RedisSessionStore {
@@ -45,9 +179,26 @@ impl RedisSessionStore {
     /// let store = RedisSessionStore::from_client(client);
     /// ```
     pub fn from_client(client: Client) -> Self {
+        Self::from_topology(RedisTopology::node(client))
+    }
+
+    /// creates a redis store from a [`RedisTopology`] (e.g., Sentinel)
+    /// ```rust
+    /// # use async_redis_session::{RedisSessionStore, RedisTopology};
+    /// let topology = RedisTopology::open("redis+sentinel://127.0.0.1:26379/sessions").unwrap();
+    /// let store = RedisSessionStore::from_topology(topology);
+    /// ```
+    pub fn from_topology(topology: RedisTopology) -> Self {
+        let prefix = topology.is_cluster().then(|| hash_tag(CLUSTER_PREFIX));
         Self {
-            client,
-            prefix: None,
+            topology,
+            prefix,
+            pool: Arc::new(Pool::new(1)),
+            sliding_expiry: None,
+            max_lifetime: None,
+            key_ring: None,
+            codec: Codec::default(),
+            events: None,
         }
     }
 
@@ -61,7 +212,8 @@ impl RedisSessionStore {
         Ok(Self::from_client(Client::open(connection_info)?))
     }
 
-    /// sets a key prefix for this session store
+    /// sets a key prefix for this session store; the prefix is stored as a
+    /// hash tag (`{async-sessions/}`) unless it already includes one
     ///
     /// ```rust
     /// # use async_redis_session::RedisSessionStore;
@@ -75,33 +227,373 @@ impl RedisSessionStore {
     ///     .with_prefix("async-sessions/");
     /// ```
     pub fn with_prefix(mut self, prefix: impl AsRef<str>) -> Self {
-        self.prefix = Some(prefix.as_ref().to_owned());
+        self.prefix = Some(hash_tag(prefix.as_ref()));
+        self
+    }
+
+    /// sets the number of connections shared by the clones of this store
+    ///
+    /// ```rust
+    /// # use async_redis_session::RedisSessionStore;
+    /// let store = RedisSessionStore::new("redis://127.0.0.1").unwrap()
+    ///     .with_pool_size(8);
+    /// ```
+    pub fn with_pool_size(mut self, size: usize) -> Self {
+        self.pool = Arc::new(Pool::new(size));
+        self
+    }
+
+    /// extends the ttl of a session to `ttl` each time it is loaded; sessions
+    /// stored without an expiry expire after `ttl`
+    ///
+    /// ```rust
+    /// # use async_redis_session::RedisSessionStore;
+    /// # use std::time::Duration;
+    /// let store = RedisSessionStore::new("redis://127.0.0.1").unwrap()
+    ///     .with_sliding_expiry(Duration::from_secs(30 * 60));
+    /// ```
+    pub fn with_sliding_expiry(mut self, ttl: Duration) -> Self {
+        self.sliding_expiry = Some(ttl);
         self
     }
 
-    async fn ids(&self) -> Result<Vec<String>> {
-        Ok(self.connection().await?.keys(self.prefix_key("*")).await?)
+    /// sessions expire `lifetime` after they are first stored, however often
+    /// they are loaded; the end of the lifetime is stored in the session
+    ///
+    /// ```rust
+    /// # use async_redis_session::RedisSessionStore;
+    /// # use std::time::Duration;
+    /// let store = RedisSessionStore::new("redis://127.0.0.1").unwrap()
+    ///     .with_sliding_expiry(Duration::from_secs(30 * 60))
+    ///     .with_max_lifetime(Duration::from_secs(7 * 24 * 60 * 60));
+    /// ```
+    pub fn with_max_lifetime(mut self, lifetime: Duration) -> Self {
+        self.max_lifetime = Some(lifetime);
+        self
+    }
+
+    /// encrypts the sessions using the current key of the ring
+    ///
+    /// ```rust
+    /// # use async_redis_session::{KeyRing, RedisSessionStore};
+    /// let store = RedisSessionStore::new("redis://127.0.0.1").unwrap()
+    ///     .with_key_ring(KeyRing::new(1, &[7; 32]).unwrap());
+    /// ```
+    pub fn with_key_ring(mut self, key_ring: KeyRing) -> Self {
+        self.key_ring = Some(key_ring);
+        self
+    }
+
+    /// serializes the new records with the codec; the records written with
+    /// the former codec are still read
+    ///
+    /// ```rust
+    /// # use async_redis_session::{Codec, RedisSessionStore};
+    /// let store = RedisSessionStore::new("redis://127.0.0.1").unwrap()
+    ///     .with_codec(Codec::Json);
+    /// ```
+    pub fn with_codec(mut self, codec: Codec) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    /// publishes the sessions that are destroyed (revoked or expired) on
+    /// the channel; clearing the store publishes no events
+    ///
+    /// ```rust
+    /// # use async_redis_session::RedisSessionStore;
+    /// let store = RedisSessionStore::new("redis://127.0.0.1").unwrap()
+    ///     .with_events("session-events");
+    /// ```
+    pub fn with_events(mut self, channel: impl Into<String>) -> Self {
+        self.events = Some(channel.into());
+        self
+    }
+
+    /// subscribes to the events of the stores that share the channel and
+    /// the prefix (e.g., to drop the sessions cached by another service)
+    ///
+    /// ```rust,no_run
+    /// # use async_redis_session::RedisSessionStore;
+    /// # fn main() -> async_session::Result { tokio::runtime::Runtime::new()?.block_on(async {
+    /// let store = RedisSessionStore::new("redis://127.0.0.1")?.with_events("session-events");
+    /// let mut events = store.subscribe().await?;
+    /// while let Some(event) = events.next().await {
+    ///     println!("{} is no longer valid", event.session_id());
+    /// }
+    /// # Ok(()) }) }
+    /// ```
+    pub async fn subscribe(&self) -> Result<SessionEvents> {
+        let channel = self
+            .events
+            .as_ref()
+            .ok_or_else(|| async_session::Error::msg("the store has no events channel"))?;
+        // cluster: the keyspace notifications are published by the node
+        // that serves the sessions
+        let client = self.topology.client_for(&self.prefix_key("")).await?;
+        Ok(SessionEvents::subscribe(client, channel, self.prefix.clone()).await?)
     }
 
     /// returns the number of sessions in this store
+    ///
+    /// expired sessions are dropped from the index before it is counted
     pub async fn count(&self) -> Result<usize> {
-        if self.prefix.is_none() {
-            let mut connection = self.connection().await?;
-            Ok(redis::cmd("DBSIZE").query_async(&mut connection).await?)
-        } else {
-            Ok(self.ids().await?.len())
+        let index = self.prefix_key(INDEX_KEY);
+        let (count,): (usize,) = self
+            .query_pipe(
+                redis::pipe()
+                    .atomic()
+                    .cmd("ZREMRANGEBYSCORE")
+                    .arg(&index)
+                    .arg("-inf")
+                    .arg(now())
+                    .ignore()
+                    .cmd("ZCARD")
+                    .arg(&index),
+            )
+            .await?;
+        Ok(count)
+    }
+
+    /// returns the sessions of the user (see [`USER_ID_KEY`])
+    ///
+    /// expired sessions are dropped from the user's index
+    pub async fn user_sessions(&self, user_id: &str) -> Result<Vec<Session>> {
+        let index = self.user_key(user_id);
+        let (ids,): (Vec<String>,) = self
+            .query_pipe(
+                redis::pipe()
+                    .atomic()
+                    .cmd("ZREMRANGEBYSCORE")
+                    .arg(&index)
+                    .arg("-inf")
+                    .arg(now())
+                    .ignore()
+                    .cmd("ZRANGE")
+                    .arg(&index)
+                    .arg(0)
+                    .arg(-1),
+            )
+            .await?;
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let keys: Vec<String> = ids.iter().map(|id| self.prefix_key(id)).collect();
+        let records: Vec<Option<Vec<u8>>> = self.query(redis::cmd("MGET").arg(&keys)).await?;
+
+        let mut sessions = Vec::new();
+        for (key, record) in keys.iter().zip(records) {
+            if let Some(session) = record.map(|record| self.decode(key, record)).transpose()? {
+                sessions.extend(session);
+            }
+        }
+        Ok(sessions)
+    }
+
+    /// destroys one of the user's sessions; returns false when the session
+    /// is not the user's
+    ///
+    /// the session and its index entries are removed in one step (script)
+    pub async fn destroy_user_session(&self, user_id: &str, session_id: &str) -> Result<bool> {
+        let removed: usize = self
+            .eval(
+                &Script::new(DESTROY_USER_SESSION),
+                &[
+                    self.user_key(user_id),
+                    self.prefix_key(session_id),
+                    self.prefix_key(INDEX_KEY),
+                ],
+                &[session_id.to_string()],
+            )
+            .await?;
+        if removed > 0 {
+            self.publish_revoked(&[session_id.to_string()]).await?;
+        }
+        Ok(removed > 0)
+    }
+
+    /// destroys all of the user's sessions ("sign out everywhere"); returns
+    /// the number of sessions destroyed
+    ///
+    /// the index is read and the sessions removed in one step (script): a
+    /// session stored meanwhile is either destroyed or stays indexed
+    pub async fn destroy_user_sessions(&self, user_id: &str) -> Result<usize> {
+        let ids: Vec<String> = self
+            .eval(
+                &Script::new(DESTROY_USER_SESSIONS),
+                &[self.user_key(user_id), self.prefix_key(INDEX_KEY)],
+                &[
+                    self.prefix_key(""),
+                    now().to_string(),
+                    SCAN_COUNT.to_string(),
+                ],
+            )
+            .await?;
+        self.publish_revoked(&ids).await?;
+        Ok(ids.len())
+    }
+
+    async fn publish_revoked(&self, ids: &[String]) -> Result {
+        if self.events.is_some() && !ids.is_empty() {
+            let mut pipe = redis::pipe();
+            for id in ids {
+                self.publish(&mut pipe, SessionEvent::Revoked(id.clone()));
+            }
+            let _: () = self.query_pipe(&pipe).await?;
+        }
+        Ok(())
+    }
+
+    /// adds the commands that store the session (and index it) to the
+    /// pipe; returns false when the session has expired
+    fn write_session(&self, pipe: &mut Pipeline, session: &mut Session) -> Result<bool> {
+        let key = self.prefix_key(session.id());
+        let max_expiry = match self.max_lifetime {
+            Some(lifetime) => match session.get::<u64>(MAX_EXPIRY_KEY) {
+                Some(max_expiry) => Some(max_expiry),
+                None => {
+                    let max_expiry = now() + lifetime.as_secs();
+                    session.insert(MAX_EXPIRY_KEY, max_expiry)?;
+                    Some(max_expiry)
+                }
+            },
+            None => session.get::<u64>(MAX_EXPIRY_KEY),
+        };
+        let expires_in = session
+            .expires_in()
+            .or(self.sliding_expiry)
+            .map(|ttl| ttl.as_secs())
+            .into_iter()
+            .chain(max_expiry.map(|max_expiry| max_expiry.saturating_sub(now())))
+            .min();
+        if expires_in == Some(0) {
+            return Ok(false);
+        }
+        let string = self.encode(&key, session)?;
+        let score = expires_in.map_or_else(|| "+inf".to_string(), |ttl| (now() + ttl).to_string());
+
+        match expires_in {
+            None => pipe.cmd("SET").arg(&key).arg(string).ignore(),
+            Some(ttl) => pipe
+                .cmd("SETEX")
+                .arg(&key)
+                .arg(ttl as usize)
+                .arg(string)
+                .ignore(),
+        };
+        // the expired sessions are dropped from the index as it is written,
+        // so it does not grow when the store is not counted
+        let index = self.prefix_key(INDEX_KEY);
+        pipe.cmd("ZREMRANGEBYSCORE")
+            .arg(&index)
+            .arg("-inf")
+            .arg(now())
+            .ignore()
+            .cmd("ZADD")
+            .arg(&index)
+            .arg(&score)
+            .arg(session.id())
+            .ignore();
+        if let Some(user_id) = session.get::<String>(USER_ID_KEY) {
+            let index = self.user_key(&user_id);
+            pipe.cmd("ZREMRANGEBYSCORE")
+                .arg(&index)
+                .arg("-inf")
+                .arg(now())
+                .ignore()
+                .cmd("ZADD")
+                .arg(&index)
+                .arg(&score)
+                .arg(session.id())
+                .ignore();
+        }
+        Ok(true)
+    }
+
+    /// moves the session to a new id (e.g., once the user is authenticated,
+    /// to prevent session fixation); the old id is removed in the same
+    /// transaction. Returns the cookie value of the new id.
+    pub async fn regenerate(&self, mut session: Session) -> Result<Option<String>> {
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        self.delete_session(&mut pipe, &session);
+        self.publish(&mut pipe, SessionEvent::Revoked(session.id().to_string()));
+
+        session.regenerate();
+        let stored = self.write_session(&mut pipe, &mut session)?;
+        let _: () = self.query_pipe(&pipe).await?;
+
+        Ok(stored.then(|| session.into_cookie_value()).flatten())
+    }
+
+    /// adds the commands that delete the session (and its index entries)
+    fn delete_session(&self, pipe: &mut Pipeline, session: &Session) {
+        pipe.cmd("DEL")
+            .arg(self.prefix_key(session.id()))
+            .ignore()
+            .cmd("ZREM")
+            .arg(self.prefix_key(INDEX_KEY))
+            .arg(session.id())
+            .ignore();
+        if let Some(user_id) = session.get::<String>(USER_ID_KEY) {
+            pipe.cmd("ZREM")
+                .arg(self.user_key(&user_id))
+                .arg(session.id())
+                .ignore();
+        }
+    }
+
+    /// destroys the session; the event is published in the transaction
+    async fn destroy(&self, session: Session, event: fn(String) -> SessionEvent) -> Result {
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        self.delete_session(&mut pipe, &session);
+        self.publish(&mut pipe, event(session.id().to_string()));
+        let _: () = self.query_pipe(&pipe).await?;
+        Ok(())
+    }
+
+    /// adds the event to the pipe when the store has an events channel
+    fn publish(&self, pipe: &mut Pipeline, event: SessionEvent) {
+        if let Some(channel) = &self.events {
+            pipe.cmd("PUBLISH")
+                .arg(channel)
+                .arg(event.message())
+                .ignore();
         }
     }
 
+    fn user_key(&self, user_id: &str) -> String {
+        self.prefix_key(format!("{}{}", USERS_KEY, user_id))
+    }
+
     #[cfg(test)]
     async fn ttl_for_session(&self, session: &Session) -> Result<usize> {
         Ok(self
-            .connection()
-            .await?
-            .ttl(self.prefix_key(session.id()))
+            .query(redis::cmd("TTL").arg(self.prefix_key(session.id())))
             .await?)
     }
 
+    /// a record encrypted with a key that left the ring is no session
+    fn decode(&self, key: &str, record: Vec<u8>) -> Result<Option<Session>> {
+        let record = match &self.key_ring {
+            Some(key_ring) => match key_ring.decrypt(key, record)? {
+                Decrypted::Record(record) => record,
+                Decrypted::UnknownKey => return Ok(None),
+            },
+            None => record,
+        };
+        Ok(Some(Codec::decode(&record)?))
+    }
+
+    fn encode(&self, key: &str, session: &Session) -> Result<Vec<u8>> {
+        let record = self.codec.encode(session)?;
+        match &self.key_ring {
+            Some(key_ring) => key_ring.encrypt(key, &record),
+            None => Ok(record),
+        }
+    }
+
     fn prefix_key(&self, key: impl AsRef<str>) -> String {
         if let Some(ref prefix) = self.prefix {
             format!("{}{}", prefix, key.as_ref())
@@ -110,72 +602,285 @@ impl RedisSessionStore {
         }
     }
 
-    async fn connection(&self) -> RedisResult<Connection> {
-        self.client.get_async_std_connection().await
+    /// a connection from the pool; opened when the slot is empty
+    async fn connection(
+        &self,
+        slot: &Mutex<Option<MultiplexedConnection>>,
+    ) -> RedisResult<MultiplexedConnection> {
+        let pooled = slot.lock().unwrap_or_else(|err| err.into_inner()).clone();
+        match pooled {
+            Some(connection) => Ok(connection),
+            None => self.reconnect(slot).await,
+        }
+    }
+
+    /// all of the keys share the prefix, so the connection is opened to the
+    /// node serving the prefix
+    async fn reconnect(
+        &self,
+        slot: &Mutex<Option<MultiplexedConnection>>,
+    ) -> RedisResult<MultiplexedConnection> {
+        let connection = self
+            .topology
+            .get_multiplexed_tokio_connection_for(&self.prefix_key(""))
+            .await?;
+        *slot.lock().unwrap_or_else(|err| err.into_inner()) = Some(connection.clone());
+        Ok(connection)
+    }
+
+    async fn query<T: FromRedisValue>(&self, cmd: &Cmd) -> RedisResult<T> {
+        self.send(|mut connection| async move { cmd.query_async(&mut connection).await })
+            .await
+    }
+
+    async fn query_pipe<T: FromRedisValue>(&self, pipe: &Pipeline) -> RedisResult<T> {
+        self.send(|mut connection| async move { pipe.query_async(&mut connection).await })
+            .await
+    }
+
+    /// the keys share the prefix (the hash slot with Cluster)
+    async fn eval<T: FromRedisValue>(
+        &self,
+        script: &Script,
+        keys: &[String],
+        args: &[String],
+    ) -> RedisResult<T> {
+        self.send(|mut connection| async move {
+            let mut invocation = script.prepare_invoke();
+            for key in keys {
+                invocation.key(key);
+            }
+            for arg in args {
+                invocation.arg(arg);
+            }
+            invocation.invoke_async(&mut connection).await
+        })
+        .await
+    }
+
+    /// sends the request; a dropped connection is replaced and the request
+    /// sent once more. During a failover the topology is resolved again, the
+    /// pool emptied, and the request retried with a backoff.
+    async fn send<T, F, Fut>(&self, request: F) -> RedisResult<T>
+    where
+        F: Fn(MultiplexedConnection) -> Fut,
+        Fut: Future<Output = RedisResult<T>>,
+    {
+        let slot = self.pool.slot();
+        let mut result = match self.connection(slot).await {
+            Ok(connection) => request(connection).await,
+            Err(err) => Err(err),
+        };
+        for attempt in 0..FAILOVER_RETRIES {
+            match &result {
+                // the server closed the connection (e.g., timeout, restart)
+                Err(err) if attempt == 0 && is_dropped(err) => {}
+                Err(err) if is_dropped(err) || is_failover(err) => {
+                    self.topology.invalidate();
+                    self.pool.clear();
+                    tokio::time::sleep(FAILOVER_BACKOFF * 2u32.pow(attempt)).await;
+                }
+                _ => return result,
+            }
+            result = match self.reconnect(slot).await {
+                Ok(connection) => request(connection).await,
+                Err(err) => Err(err),
+            };
+        }
+        result
+    }
+}
+
+/// the prefix as a hash tag; a prefix that includes a tag is kept
+fn hash_tag(prefix: &str) -> String {
+    let tagged = prefix
+        .find('{')
+        .and_then(|open| prefix[open + 1..].find('}'))
+        .is_some_and(|len| len > 0);
+    if tagged {
+        prefix.to_string()
+    } else {
+        format!("{{{}}}", prefix)
     }
 }
 
+fn is_dropped(err: &RedisError) -> bool {
+    err.is_connection_dropped() || err.is_io_error()
+}
+
+/// the request was sent to a node that no longer serves the keys
+fn is_failover(err: &RedisError) -> bool {
+    err.is_connection_refusal()
+        || matches!(
+            err.kind(),
+            ErrorKind::ReadOnly
+                | ErrorKind::Moved
+                | ErrorKind::TryAgain
+                | ErrorKind::ClusterDown
+                | ErrorKind::MasterDown
+                | ErrorKind::BusyLoadingError
+        )
+        || matches!(err.code(), Some("READONLY" | "MASTERDOWN" | "LOADING"))
+}
+
+/// seconds since the epoch
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs())
+        .unwrap_or_default()
+}
+
+/// the prefix as a SCAN pattern (glob characters are escaped)
+fn escape_pattern(prefix: &str) -> String {
+    prefix
+        .chars()
+        .flat_map(|c| match c {
+            '*' | '?' | '[' | ']' | '\\' => vec!['\\', c],
+            c => vec![c],
+        })
+        .collect()
+}
+
 #[async_trait]
 impl SessionStore for RedisSessionStore {
     async fn load_session(&self, cookie_value: String) -> Result<Option<Session>> {
         let id = Session::id_from_cookie_value(&cookie_value)?;
-        let mut connection = self.connection().await?;
-        let record: Option<String> = connection.get(self.prefix_key(id)).await?;
-        match record {
-            Some(value) => Ok(serde_json::from_str(&value)?),
-            None => Ok(None),
-        }
-    }
-
-    async fn store_session(&self, session: Session) -> Result<Option<String>> {
-        let id = self.prefix_key(session.id());
-        let string = serde_json::to_string(&session)?;
+        let key = self.prefix_key(&id);
+        let ttl = match self.sliding_expiry {
+            None => {
+                let record: Option<Vec<u8>> = self.query(redis::cmd("GET").arg(&key)).await?;
+                return match record {
+                    Some(record) => self.decode(&key, record),
+                    None => Ok(None),
+                };
+            }
+            Some(ttl) => ttl.as_secs().max(1),
+        };
 
-        let mut connection = self.connection().await?;
+        // the session is not written; ZADD XX leaves destroyed sessions out
+        let (record,): (Option<Vec<u8>>,) = self
+            .query_pipe(
+                redis::pipe()
+                    .atomic()
+                    .cmd("GET")
+                    .arg(&key)
+                    .cmd("EXPIRE")
+                    .arg(&key)
+                    .arg(ttl)
+                    .ignore()
+                    .cmd("ZADD")
+                    .arg(self.prefix_key(INDEX_KEY))
+                    .arg("XX")
+                    .arg(now() + ttl)
+                    .arg(&id)
+                    .ignore(),
+            )
+            .await?;
+        let mut session = match record {
+            Some(record) => match self.decode(&key, record)? {
+                Some(session) => session,
+                None => return Ok(None),
+            },
+            None => return Ok(None),
+        };
 
-        match session.expires_in() {
-            None => {
-                let result: () = connection.set(id, string).await?;
-                result
+        let ttl = match session.get::<u64>(MAX_EXPIRY_KEY) {
+            Some(max_expiry) if max_expiry <= now() => {
+                self.destroy(session, SessionEvent::Expired).await?;
+                return Ok(None);
             }
-            Some(expiry) => {
-                let result: () = connection
-                    .set_ex(id, string, expiry.as_secs() as usize)
+            // the lifetime ends before the ttl
+            Some(max_expiry) if max_expiry < now() + ttl => {
+                let _: () = self
+                    .query_pipe(
+                        redis::pipe()
+                            .atomic()
+                            .cmd("EXPIREAT")
+                            .arg(&key)
+                            .arg(max_expiry)
+                            .ignore()
+                            .cmd("ZADD")
+                            .arg(self.prefix_key(INDEX_KEY))
+                            .arg("XX")
+                            .arg(max_expiry)
+                            .arg(&id)
+                            .ignore(),
+                    )
                     .await?;
-                result
+                max_expiry - now()
             }
+            _ => ttl,
         };
+        if let Some(user_id) = session.get::<String>(USER_ID_KEY) {
+            let _: () = self
+                .query(
+                    redis::cmd("ZADD")
+                        .arg(self.user_key(&user_id))
+                        .arg("XX")
+                        .arg(now() + ttl)
+                        .arg(&id),
+                )
+                .await?;
+        }
+        session.expire_in(Duration::from_secs(ttl));
+        Ok(Some(session))
+    }
+
+    async fn store_session(&self, mut session: Session) -> Result<Option<String>> {
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        if !self.write_session(&mut pipe, &mut session)? {
+            self.destroy(session, SessionEvent::Expired).await?;
+            return Ok(None);
+        }
+        let _: () = self.query_pipe(&pipe).await?;
 
         Ok(session.into_cookie_value())
     }
 
     async fn destroy_session(&self, session: Session) -> Result {
-        let mut connection = self.connection().await?;
-        let key = self.prefix_key(session.id());
-        let _: () = connection.del(key).await?;
-        Ok(())
+        self.destroy(session, SessionEvent::Revoked).await
     }
 
     async fn clear_store(&self) -> Result {
-        let mut connection = self.connection().await?;
-
-        if self.prefix.is_none() {
-            let _: () = redis::cmd("FLUSHDB").query_async(&mut connection).await?;
-        } else {
-            let ids = self.ids().await?;
-            if !ids.is_empty() {
-                let _: () = connection.del(ids).await?;
+        let prefix = match &self.prefix {
+            Some(prefix) => prefix,
+            None => {
+                let _: () = self.query(redis::cmd("FLUSHDB").arg("ASYNC")).await?;
+                return Ok(());
+            }
+        };
+        // the index is removed with the sessions (it shares the prefix)
+        let pattern = format!("{}*", escape_pattern(prefix));
+        let mut cursor: u64 = 0;
+        loop {
+            let (next, keys): (u64, Vec<String>) = self
+                .query(
+                    redis::cmd("SCAN")
+                        .arg(cursor)
+                        .arg("MATCH")
+                        .arg(&pattern)
+                        .arg("COUNT")
+                        .arg(SCAN_COUNT),
+                )
+                .await?;
+            for batch in keys.chunks(SCAN_COUNT) {
+                let _: () = self.query(redis::cmd("UNLINK").arg(batch)).await?;
             }
+            if next == 0 {
+                return Ok(());
+            }
+            cursor = next;
         }
-        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use async_std::task;
     use std::time::Duration;
+    use tokio::time::sleep;
 
     async fn test_store() -> RedisSessionStore {
         let store = RedisSessionStore::new("redis://127.0.0.1").unwrap();
@@ -183,7 +888,7 @@ mod tests {
         store
     }
 
-    #[async_std::test]
+    #[tokio::test]
     async fn creating_a_new_session_with_no_expiry() -> Result {
         let store = test_store().await;
         let mut session = Session::new();
@@ -199,7 +904,7 @@ mod tests {
         Ok(())
     }
 
-    #[async_std::test]
+    #[tokio::test]
     async fn updating_a_session() -> Result {
         let store = test_store().await;
         let mut session = Session::new();
@@ -218,7 +923,8 @@ mod tests {
         Ok(())
     }
 
-    #[async_std::test]
+    #[tokio::test]
+    #[allow(clippy::clone_on_copy)]
     async fn updating_a_session_extending_expiry() -> Result {
         let store = test_store().await;
         let mut session = Session::new();
@@ -242,13 +948,13 @@ mod tests {
 
         assert_eq!(1, store.count().await.unwrap());
 
-        task::sleep(Duration::from_secs(10)).await;
+        sleep(Duration::from_secs(10)).await;
         assert_eq!(0, store.count().await.unwrap());
 
         Ok(())
     }
 
-    #[async_std::test]
+    #[tokio::test]
     async fn creating_a_new_session_with_expiry() -> Result {
         let store = test_store().await;
         let mut session = Session::new();
@@ -266,13 +972,13 @@ mod tests {
 
         assert!(!loaded_session.is_expired());
 
-        task::sleep(Duration::from_secs(2)).await;
+        sleep(Duration::from_secs(2)).await;
         assert_eq!(None, store.load_session(cookie_value).await?);
 
         Ok(())
     }
 
-    #[async_std::test]
+    #[tokio::test]
     async fn destroying_a_single_session() -> Result {
         let store = test_store().await;
         for _ in 0..3i8 {
@@ -291,7 +997,7 @@ mod tests {
         Ok(())
     }
 
-    #[async_std::test]
+    #[tokio::test]
     async fn clearing_the_whole_store() -> Result {
         let store = test_store().await;
         for _ in 0..3i8 {
@@ -305,7 +1011,7 @@ mod tests {
         Ok(())
     }
 
-    #[async_std::test]
+    #[tokio::test]
     async fn prefixes() -> Result {
         test_store().await; // clear the db
 
@@ -345,4 +1051,339 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn pooled_connections_are_shared_by_concurrent_requests() -> Result {
+        test_store().await; // clear the db
+
+        let store = RedisSessionStore::new("redis://127.0.0.1")?
+            .with_prefix("pooled/")
+            .with_pool_size(4);
+        store.clear_store().await?;
+
+        let cookies = futures::future::try_join_all((0..16i8).map(|i| {
+            let store = store.clone();
+            async move {
+                let mut session = Session::new();
+                session.insert("i", i)?;
+                store.store_session(session).await
+            }
+        }))
+        .await?;
+        assert_eq!(16, store.count().await?);
+
+        for (i, cookie) in cookies.into_iter().enumerate() {
+            let session = store.load_session(cookie.unwrap()).await?.unwrap();
+            assert_eq!(i as i8, session.get::<i8>("i").unwrap());
+        }
+
+        let clients: String = store.query(redis::cmd("CLIENT").arg("LIST")).await?;
+        assert!(clients.lines().count() >= 4);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reconnecting_after_the_connection_is_dropped() -> Result {
+        let store = RedisSessionStore::new("redis://127.0.0.1")?.with_prefix("reconnect/");
+        store.clear_store().await?;
+        let cookie = store.store_session(Session::new()).await?.unwrap();
+
+        // the server closes the pooled connection
+        let id: i64 = store.query(redis::cmd("CLIENT").arg("ID")).await?;
+        let mut admin = Client::open("redis://127.0.0.1")?
+            .get_multiplexed_tokio_connection()
+            .await?;
+        let _: () = redis::cmd("CLIENT")
+            .arg("KILL")
+            .arg("ID")
+            .arg(id)
+            .query_async(&mut admin)
+            .await?;
+
+        assert!(store.load_session(cookie).await?.is_some());
+        let reconnected: i64 = store.query(redis::cmd("CLIENT").arg("ID")).await?;
+        assert_ne!(id, reconnected);
+        Ok(())
+    }
+
+    #[test]
+    fn prefixes_are_hash_tags() -> Result {
+        let store = RedisSessionStore::new("redis://127.0.0.1")?.with_prefix("sessions/");
+        assert_eq!("{sessions/}id", store.prefix_key("id"));
+        assert_eq!(key_slot(b"{sessions/}id"), key_slot(b"{sessions/}__index"));
+
+        let store = RedisSessionStore::new("redis://127.0.0.1")?.with_prefix("app:{tenant}:");
+        assert_eq!("app:{tenant}:id", store.prefix_key("id"));
+
+        let cluster = RedisTopology::open("redis+cluster://127.0.0.1:7000")?;
+        let store = RedisSessionStore::from_topology(cluster);
+        assert_eq!("{sessions/}id", store.prefix_key("id"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn counting_drops_expired_sessions_from_the_index() -> Result {
+        let store = RedisSessionStore::new("redis://127.0.0.1")?.with_prefix("counting/");
+        store.clear_store().await?;
+
+        let mut session = Session::new();
+        session.expire_in(Duration::from_secs(1));
+        store.store_session(session).await?;
+        store.store_session(Session::new()).await?;
+        assert_eq!(2, store.count().await?);
+
+        sleep(Duration::from_secs(2)).await;
+        assert_eq!(1, store.count().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn storing_drops_expired_sessions_from_the_index() -> Result {
+        let store = RedisSessionStore::new("redis://127.0.0.1")?.with_prefix("pruning/");
+        store.clear_store().await?;
+
+        let mut session = Session::new();
+        session.expire_in(Duration::from_secs(1));
+        store.store_session(session).await?;
+        sleep(Duration::from_secs(2)).await;
+        store.store_session(Session::new()).await?;
+
+        // without counting
+        let indexed: usize = store
+            .query(redis::cmd("ZCARD").arg(store.prefix_key(INDEX_KEY)))
+            .await?;
+        assert_eq!(1, indexed);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn loading_extends_a_sliding_expiry() -> Result {
+        let store = RedisSessionStore::new("redis://127.0.0.1")?
+            .with_prefix("sliding/")
+            .with_sliding_expiry(Duration::from_secs(3));
+        store.clear_store().await?;
+
+        let cookie = store.store_session(Session::new()).await?.unwrap();
+        for _ in 0..3i8 {
+            sleep(Duration::from_secs(2)).await;
+            let session = store.load_session(cookie.clone()).await?.unwrap();
+            assert!(store.ttl_for_session(&session).await? > 1);
+            assert!(session.expires_in().unwrap() > Duration::from_secs(1));
+        }
+        assert_eq!(1, store.count().await?);
+
+        sleep(Duration::from_secs(4)).await;
+        assert_eq!(None, store.load_session(cookie).await?);
+        assert_eq!(0, store.count().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn sessions_expire_after_the_max_lifetime() -> Result {
+        let store = RedisSessionStore::new("redis://127.0.0.1")?
+            .with_prefix("lifetime/")
+            .with_sliding_expiry(Duration::from_secs(60))
+            .with_max_lifetime(Duration::from_secs(3));
+        store.clear_store().await?;
+
+        let cookie = store.store_session(Session::new()).await?.unwrap();
+        let session = store.load_session(cookie.clone()).await?.unwrap();
+        assert!(session.get::<u64>(MAX_EXPIRY_KEY).is_some());
+        assert!(store.ttl_for_session(&session).await? <= 3);
+
+        // storing the session again does not extend the lifetime
+        sleep(Duration::from_secs(2)).await;
+        store.store_session(session).await?;
+        sleep(Duration::from_secs(2)).await;
+        assert_eq!(None, store.load_session(cookie).await?);
+        assert_eq!(0, store.count().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn sessions_are_encrypted_at_rest() -> Result {
+        let plain = RedisSessionStore::new("redis://127.0.0.1")?.with_prefix("encrypted/");
+        plain.clear_store().await?;
+        let mut session = Session::new();
+        session.insert("pkce_verifier", "secret")?;
+        let legacy = plain.store_session(session).await?.unwrap();
+
+        let store = plain
+            .clone()
+            .with_key_ring(KeyRing::new(1, &[1; 32])?.with_key(0, &[0; 32])?);
+        let mut session = Session::new();
+        session.insert("pkce_verifier", "secret")?;
+        let id = session.id().to_string();
+        let cookie = store.store_session(session).await?.unwrap();
+
+        let record: String = store
+            .query(redis::cmd("GET").arg(store.prefix_key(&id)))
+            .await?;
+        assert!(record.starts_with("enc:v1:1:"));
+        assert!(!record.contains("secret"));
+        let session = store.load_session(cookie.clone()).await?.unwrap();
+        assert_eq!("secret", session.get::<String>("pkce_verifier").unwrap());
+
+        // stored before encryption was enabled
+        assert!(store.load_session(legacy).await?.is_some());
+        // the key was dropped from the ring
+        let rotated = plain.with_key_ring(KeyRing::new(2, &[2; 32])?);
+        assert_eq!(None, rotated.load_session(cookie).await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn signing_a_user_out_everywhere() -> Result {
+        let store = RedisSessionStore::new("redis://127.0.0.1")?.with_prefix("users/");
+        store.clear_store().await?;
+
+        let mut ids = Vec::new();
+        let mut cookies = Vec::new();
+        for _ in 0..3i8 {
+            let mut session = Session::new();
+            session.insert(USER_ID_KEY, "google/1")?;
+            ids.push(session.id().to_string());
+            cookies.push(store.store_session(session).await?.unwrap());
+        }
+        let mut other = Session::new();
+        other.insert(USER_ID_KEY, "github/2")?;
+        let other = store.store_session(other).await?.unwrap();
+        store.store_session(Session::new()).await?;
+
+        let mut listed: Vec<String> = store
+            .user_sessions("google/1")
+            .await?
+            .iter()
+            .map(|session| session.id().to_string())
+            .collect();
+        listed.sort();
+        let mut sorted = ids.clone();
+        sorted.sort();
+        assert_eq!(sorted, listed);
+
+        // revoking one session
+        assert!(!store.destroy_user_session("github/2", &ids[0]).await?);
+        assert!(store.destroy_user_session("google/1", &ids[0]).await?);
+        assert_eq!(2, store.user_sessions("google/1").await?.len());
+        assert_eq!(4, store.count().await?);
+
+        // destroying a session removes it from the user's index
+        let session = store.load_session(cookies[2].clone()).await?.unwrap();
+        store.destroy_session(session).await?;
+        assert_eq!(1, store.user_sessions("google/1").await?.len());
+
+        assert_eq!(1, store.destroy_user_sessions("google/1").await?);
+        assert_eq!(0, store.destroy_user_sessions("google/1").await?);
+        assert!(store.user_sessions("google/1").await?.is_empty());
+        for cookie in cookies {
+            assert_eq!(None, store.load_session(cookie).await?);
+        }
+        assert!(store.load_session(other).await?.is_some());
+        assert_eq!(2, store.count().await?);
+        Ok(())
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[tokio::test]
+    async fn switching_to_message_pack() -> Result {
+        let json = RedisSessionStore::new("redis://127.0.0.1")?.with_prefix("codec/");
+        json.clear_store().await?;
+        let mut session = Session::new();
+        session.insert("key", "value")?;
+        let legacy = json.store_session(session).await?.unwrap();
+
+        let store = json.clone().with_codec(Codec::MessagePack);
+        let mut session = Session::new();
+        session.insert("key", "value")?;
+        let id = session.id().to_string();
+        let cookie = store.store_session(session).await?.unwrap();
+
+        let record: Vec<u8> = store
+            .query(redis::cmd("GET").arg(store.prefix_key(&id)))
+            .await?;
+        assert!(record.starts_with(b"mp:v1:"));
+        for cookie in [legacy, cookie] {
+            let session = store.load_session(cookie).await?.unwrap();
+            assert_eq!("value", session.get::<String>("key").unwrap());
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn destroyed_sessions_are_published() -> Result {
+        let store = RedisSessionStore::new("redis://127.0.0.1")?
+            .with_prefix("events/")
+            .with_events("test-session-events");
+        store.clear_store().await?;
+        let mut events = store.subscribe().await?;
+
+        let cookie = store.store_session(Session::new()).await?.unwrap();
+        let session = store.load_session(cookie).await?.unwrap();
+        let id = session.id().to_string();
+        store.destroy_session(session).await?;
+        assert_eq!(Some(SessionEvent::Revoked(id)), events.next().await);
+
+        let mut session = Session::new();
+        session.insert(USER_ID_KEY, "google/1")?;
+        let cookie = store.store_session(session).await?.unwrap();
+        let session = store.load_session(cookie).await?.unwrap();
+        let id = session.id().to_string();
+        let regenerated = store.regenerate(session).await?.unwrap();
+        assert_eq!(Some(SessionEvent::Revoked(id)), events.next().await);
+
+        let session = store.load_session(regenerated).await?.unwrap();
+        assert_eq!(1, store.destroy_user_sessions("google/1").await?);
+        assert_eq!(
+            Some(SessionEvent::Revoked(session.id().to_string())),
+            events.next().await
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn regenerating_moves_the_session_to_a_new_id() -> Result {
+        let store = RedisSessionStore::new("redis://127.0.0.1")?.with_prefix("regenerate/");
+        store.clear_store().await?;
+
+        let mut session = Session::new();
+        session.insert("key", "value")?;
+        session.insert(USER_ID_KEY, "google/1")?;
+        let old_id = session.id().to_string();
+        let cookie_value = store.store_session(session).await?.unwrap();
+
+        let session = store.load_session(cookie_value.clone()).await?.unwrap();
+        let regenerated = store.regenerate(session).await?.unwrap();
+        assert_ne!(cookie_value, regenerated);
+        assert!(store.load_session(cookie_value).await?.is_none());
+
+        let session = store.load_session(regenerated).await?.unwrap();
+        assert_ne!(old_id, session.id());
+        assert_eq!(Some("value".to_string()), session.get::<String>("key"));
+        assert_eq!(1, store.count().await?);
+
+        let listed = store.user_sessions("google/1").await?;
+        assert_eq!(1, listed.len());
+        assert_eq!(session.id(), listed[0].id());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn clearing_more_sessions_than_one_scan_returns() -> Result {
+        let store = RedisSessionStore::new("redis://127.0.0.1")?.with_prefix("scan?/");
+        let other = RedisSessionStore::new("redis://127.0.0.1")?.with_prefix("scanx/");
+        store.clear_store().await?;
+        other.clear_store().await?;
+
+        for _ in 0..(SCAN_COUNT * 3) {
+            store.store_session(Session::new()).await?;
+        }
+        other.store_session(Session::new()).await?;
+        assert_eq!(SCAN_COUNT * 3, store.count().await?);
+
+        store.clear_store().await?;
+        assert_eq!(0, store.count().await?);
+        // the glob characters in the prefix only match themselves
+        assert_eq!(1, other.count().await?);
+        Ok(())
+    }
 }
@@ -0,0 +1,627 @@
+//! # RedisTopology
+//!
+//! Where commands are sent: a single node, the master reported by Sentinel,
+//! or the Cluster node serving the key's hash slot.
+//!
+//! | url | mode |
+//! |-----|------|
+//! | `redis://[user:pass@]host[:port][/db]` | single node |
+//! | `redis+sentinel://[user:pass@]host:26379,host:26379/master_name[/db]` | Sentinel |
+//! | `redis+cluster://[user:pass@]host:7000,host:7001` | Cluster |
+//!
+//! The credentials authenticate with the data nodes (not with the sentinels).
+//! The master (Sentinel) and the slot owners (Cluster) are resolved on first
+//! use and cached; [`RedisTopology::invalidate`] drops them after a failover.
+//!
+//! [`RedisTopology::query_for`] sends commands over a multiplexed connection
+//! per node, shared by the clones of the topology, with the retries of the
+//! session store (a dropped connection is replaced, a failover resolved).
+
+use redis::{
+    aio::MultiplexedConnection, Client, Cmd, ConnectionAddr, ConnectionInfo, ErrorKind,
+    FromRedisValue, IntoConnectionInfo, Pipeline, RedisConnectionInfo, RedisError, RedisResult,
+    Value,
+};
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use crate::{is_dropped, is_failover, FAILOVER_BACKOFF, FAILOVER_RETRIES};
+
+const SENTINEL_SCHEME: &str = "redis+sentinel://";
+const CLUSTER_SCHEME: &str = "redis+cluster://";
+/// the number of hash slots in a Redis Cluster
+const SLOTS: u16 = 16384;
+
+/// The redis deployment used by the stores; clones share the resolved nodes
+#[derive(Clone, Debug)]
+pub struct RedisTopology {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    mode: Mode,
+    resolved: Mutex<Resolved>,
+    connections: Mutex<Connections>,
+}
+
+/// A connection per node, opened on first use
+#[derive(Default)]
+struct Connections(Vec<(ConnectionAddr, MultiplexedConnection)>);
+
+impl fmt::Debug for Connections {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.0.iter().map(|(node, _)| node))
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+enum Mode {
+    Node(Client),
+    Sentinel {
+        sentinels: Vec<ConnectionAddr>,
+        master_name: String,
+        redis: RedisConnectionInfo,
+    },
+    Cluster {
+        nodes: Vec<ConnectionAddr>,
+        redis: RedisConnectionInfo,
+    },
+}
+
+/// The nodes discovered since the last failover
+#[derive(Debug, Default)]
+struct Resolved {
+    master: Option<Client>,
+    /// (first slot, last slot, owner)
+    slots: Vec<(u16, u16, Client)>,
+}
+
+impl RedisTopology {
+    /// parses a `redis://`, `redis+sentinel://` or `redis+cluster://` url
+    /// ```rust
+    /// # use async_redis_session::RedisTopology;
+    /// let topology = RedisTopology::open("redis+sentinel://10.0.0.1:26379,10.0.0.2:26379/sessions").unwrap();
+    /// assert!(!topology.is_cluster());
+    /// ```
+    pub fn open(url: &str) -> RedisResult<Self> {
+        if let Some(rest) = url.strip_prefix(SENTINEL_SCHEME) {
+            let (redis, hosts, path) = parse_hosts(rest)?;
+            let mut path = path.split('/').filter(|part| !part.is_empty());
+            let master_name = path
+                .next()
+                .ok_or_else(|| invalid_url("redis+sentinel:// requires the name of the master"))?;
+            let redis = match path.next() {
+                Some(db) => RedisConnectionInfo {
+                    db: db
+                        .parse()
+                        .map_err(|_| invalid_url("invalid database number"))?,
+                    ..redis
+                },
+                None => redis,
+            };
+            Ok(Self::sentinel(hosts, master_name, redis))
+        } else if let Some(rest) = url.strip_prefix(CLUSTER_SCHEME) {
+            let (redis, hosts, path) = parse_hosts(rest)?;
+            if !path.trim_matches('/').is_empty() {
+                return Err(invalid_url("redis+cluster:// only uses database 0"));
+            }
+            Ok(Self::cluster(hosts, redis))
+        } else {
+            Ok(Self::node(Client::open(url)?))
+        }
+    }
+
+    /// a single node
+    pub fn node(client: Client) -> Self {
+        Self::with_mode(Mode::Node(client))
+    }
+
+    /// the master named `master_name`, as reported by the first sentinel
+    /// that answers
+    pub fn sentinel(
+        sentinels: Vec<ConnectionAddr>,
+        master_name: impl Into<String>,
+        redis: RedisConnectionInfo,
+    ) -> Self {
+        Self::with_mode(Mode::Sentinel {
+            sentinels,
+            master_name: master_name.into(),
+            redis,
+        })
+    }
+
+    /// a cluster, discovered from any of the seed `nodes`
+    pub fn cluster(nodes: Vec<ConnectionAddr>, redis: RedisConnectionInfo) -> Self {
+        Self::with_mode(Mode::Cluster { nodes, redis })
+    }
+
+    fn with_mode(mode: Mode) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                mode,
+                resolved: Mutex::new(Resolved::default()),
+                connections: Mutex::new(Connections::default()),
+            }),
+        }
+    }
+
+    /// commands must only use keys that share a hash slot
+    pub fn is_cluster(&self) -> bool {
+        matches!(self.inner.mode, Mode::Cluster { .. })
+    }
+
+    /// a new connection to the node that serves `key`
+    pub async fn get_multiplexed_tokio_connection_for(
+        &self,
+        key: &str,
+    ) -> RedisResult<MultiplexedConnection> {
+        self.client_for(key)
+            .await?
+            .get_multiplexed_tokio_connection()
+            .await
+    }
+
+    /// the client of the node that serves `key`
+    pub async fn client_for(&self, key: &str) -> RedisResult<Client> {
+        match &self.inner.mode {
+            Mode::Node(client) => Ok(client.clone()),
+            Mode::Sentinel { .. } => {
+                let cached = self.resolved().master.clone();
+                match cached {
+                    Some(master) => Ok(master),
+                    None => {
+                        let master = self.discover_master().await?;
+                        self.resolved().master = Some(master.clone());
+                        Ok(master)
+                    }
+                }
+            }
+            Mode::Cluster { .. } => {
+                let slot = key_slot(key.as_bytes());
+                let owner = self.slot_owner(slot);
+                match owner {
+                    Some(owner) => Ok(owner),
+                    None => {
+                        let slots = self.discover_slots().await?;
+                        self.resolved().slots = slots;
+                        self.slot_owner(slot).ok_or_else(|| {
+                            RedisError::from((
+                                ErrorKind::ClusterDown,
+                                "No node serves the hash slot",
+                                slot.to_string(),
+                            ))
+                        })
+                    }
+                }
+            }
+        }
+    }
+
+    /// sends the command over the connection shared for the node that
+    /// serves `key` (see [`RedisTopology::send_for`])
+    pub async fn query_for<T: FromRedisValue>(&self, key: &str, cmd: &Cmd) -> RedisResult<T> {
+        self.send_for(key, |mut connection| async move {
+            cmd.query_async(&mut connection).await
+        })
+        .await
+    }
+
+    /// sends the pipeline over the connection shared for the node that
+    /// serves `key`; the keys of the pipeline must share its hash slot
+    pub async fn query_pipe_for<T: FromRedisValue>(
+        &self,
+        key: &str,
+        pipe: &Pipeline,
+    ) -> RedisResult<T> {
+        self.send_for(key, |mut connection| async move {
+            pipe.query_async(&mut connection).await
+        })
+        .await
+    }
+
+    /// sends the request over the connection shared for the node that serves
+    /// `key`; a dropped connection is replaced and the request sent once
+    /// more. During a failover the topology is resolved again and the
+    /// request retried with a backoff.
+    pub async fn send_for<T, F, Fut>(&self, key: &str, request: F) -> RedisResult<T>
+    where
+        F: Fn(MultiplexedConnection) -> Fut,
+        Fut: Future<Output = RedisResult<T>>,
+    {
+        let mut result = match self.connection_for(key).await {
+            Ok(connection) => request(connection).await,
+            Err(err) => Err(err),
+        };
+        for attempt in 0..FAILOVER_RETRIES {
+            match &result {
+                // the server closed the connection (e.g., timeout, restart)
+                Err(err) if attempt == 0 && is_dropped(err) => {}
+                Err(err) if is_dropped(err) || is_failover(err) => {
+                    self.invalidate();
+                    tokio::time::sleep(FAILOVER_BACKOFF * 2u32.pow(attempt)).await;
+                }
+                _ => return result,
+            }
+            result = match self.reconnect_for(key).await {
+                Ok(connection) => request(connection).await,
+                Err(err) => Err(err),
+            };
+        }
+        result
+    }
+
+    /// the shared connection to the node that serves `key`
+    async fn connection_for(&self, key: &str) -> RedisResult<MultiplexedConnection> {
+        let client = self.client_for(key).await?;
+        let addr = &client.get_connection_info().addr;
+        let pooled = self
+            .connections()
+            .0
+            .iter()
+            .find(|(node, _)| node == addr)
+            .map(|(_, connection)| connection.clone());
+        match pooled {
+            Some(connection) => Ok(connection),
+            None => self.open_connection(&client).await,
+        }
+    }
+
+    /// replaces the shared connection to the node that serves `key`
+    async fn reconnect_for(&self, key: &str) -> RedisResult<MultiplexedConnection> {
+        let client = self.client_for(key).await?;
+        self.open_connection(&client).await
+    }
+
+    async fn open_connection(&self, client: &Client) -> RedisResult<MultiplexedConnection> {
+        let connection = client.get_multiplexed_tokio_connection().await?;
+        let addr = &client.get_connection_info().addr;
+        let mut connections = self.connections();
+        connections.0.retain(|(node, _)| node != addr);
+        connections.0.push((addr.clone(), connection.clone()));
+        Ok(connection)
+    }
+
+    /// forgets the master and the slot owners, and closes the shared
+    /// connections; the next connection resolves them again (e.g., after a
+    /// failover or a `MOVED` reply)
+    pub fn invalidate(&self) {
+        *self.resolved() = Resolved::default();
+        self.connections().0.clear();
+    }
+
+    fn connections(&self) -> std::sync::MutexGuard<'_, Connections> {
+        self.inner
+            .connections
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn resolved(&self) -> std::sync::MutexGuard<'_, Resolved> {
+        self.inner
+            .resolved
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn slot_owner(&self, slot: u16) -> Option<Client> {
+        self.resolved()
+            .slots
+            .iter()
+            .find(|(first, last, _)| (*first..=*last).contains(&slot))
+            .map(|(_, _, owner)| owner.clone())
+    }
+
+    /// asks each sentinel in turn; a reported master that is not (yet) a
+    /// master is skipped
+    async fn discover_master(&self) -> RedisResult<Client> {
+        let (sentinels, master_name, redis) = match &self.inner.mode {
+            Mode::Sentinel {
+                sentinels,
+                master_name,
+                redis,
+            } => (sentinels, master_name, redis),
+            _ => unreachable!("the master is only discovered using Sentinel"),
+        };
+        let mut last_error = None;
+        for sentinel in sentinels {
+            let found: RedisResult<Option<Client>> = async {
+                let mut connection = open(sentinel.clone(), RedisConnectionInfo::default())?
+                    .get_multiplexed_tokio_connection()
+                    .await?;
+                let addr: Option<(String, u16)> = redis::cmd("SENTINEL")
+                    .arg("get-master-addr-by-name")
+                    .arg(master_name)
+                    .query_async(&mut connection)
+                    .await?;
+                let (host, port) = match addr {
+                    Some(addr) => addr,
+                    None => return Ok(None),
+                };
+                let master = open(ConnectionAddr::Tcp(host, port), redis.clone())?;
+                let role: Value = redis::cmd("ROLE")
+                    .query_async(&mut master.get_multiplexed_tokio_connection().await?)
+                    .await?;
+                Ok(is_master(&role).then_some(master))
+            }
+            .await;
+            match found {
+                Ok(Some(master)) => return Ok(master),
+                Ok(None) => {}
+                Err(err) => last_error = Some(err),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| {
+            RedisError::from((
+                ErrorKind::MasterDown,
+                "No sentinel reports a master",
+                master_name.clone(),
+            ))
+        }))
+    }
+
+    /// `CLUSTER SLOTS` from the first seed node that answers
+    async fn discover_slots(&self) -> RedisResult<Vec<(u16, u16, Client)>> {
+        let (nodes, redis) = match &self.inner.mode {
+            Mode::Cluster { nodes, redis } => (nodes, redis),
+            _ => unreachable!("the slots are only discovered in a cluster"),
+        };
+        let mut last_error = None;
+        for node in nodes {
+            let found: RedisResult<Vec<(u16, u16, Client)>> = async {
+                let mut connection = open(node.clone(), redis.clone())?
+                    .get_multiplexed_tokio_connection()
+                    .await?;
+                let slots: Value = redis::cmd("CLUSTER")
+                    .arg("SLOTS")
+                    .query_async(&mut connection)
+                    .await?;
+                parse_slots(&slots, node)?
+                    .into_iter()
+                    .map(|(first, last, addr)| Ok((first, last, open(addr, redis.clone())?)))
+                    .collect()
+            }
+            .await;
+            match found {
+                Ok(slots) if !slots.is_empty() => return Ok(slots),
+                Ok(_) => {}
+                Err(err) => last_error = Some(err),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| {
+            RedisError::from((ErrorKind::ClusterDown, "No node reports the hash slots"))
+        }))
+    }
+}
+
+impl From<Client> for RedisTopology {
+    fn from(client: Client) -> Self {
+        Self::node(client)
+    }
+}
+
+/// the hash slot of a key: CRC16 (XMODEM) of the hash tag when the key has
+/// one (the text between the first `{` and the next `}`), of the key otherwise
+pub fn key_slot(key: &[u8]) -> u16 {
+    let hashed = key
+        .iter()
+        .position(|b| *b == b'{')
+        .and_then(|open| {
+            key[open + 1..]
+                .iter()
+                .position(|b| *b == b'}')
+                .filter(|len| *len > 0)
+                .map(|len| &key[open + 1..open + 1 + len])
+        })
+        .unwrap_or(key);
+    crc16(hashed) % SLOTS
+}
+
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0u16, |crc, byte| {
+        (0..8).fold(crc ^ (u16::from(*byte) << 8), |crc, _| {
+            if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+fn open(addr: ConnectionAddr, redis: RedisConnectionInfo) -> RedisResult<Client> {
+    Client::open(ConnectionInfo { addr, redis })
+}
+
+/// `[user:pass@]host:port,host:port[/path]`: the credentials and the hosts
+/// are parsed by redis (one `redis://` url per host)
+fn parse_hosts(rest: &str) -> RedisResult<(RedisConnectionInfo, Vec<ConnectionAddr>, &str)> {
+    let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+    let (userinfo, hosts) = match authority.rsplit_once('@') {
+        Some((userinfo, hosts)) => (Some(userinfo), hosts),
+        None => (None, authority),
+    };
+    let redis = match userinfo {
+        Some(userinfo) => {
+            format!("redis://{}@localhost", userinfo)
+                .into_connection_info()?
+                .redis
+        }
+        None => RedisConnectionInfo::default(),
+    };
+    let hosts = hosts
+        .split(',')
+        .filter(|host| !host.is_empty())
+        .map(|host| Ok(format!("redis://{}", host).into_connection_info()?.addr))
+        .collect::<RedisResult<Vec<_>>>()?;
+    if hosts.is_empty() {
+        return Err(invalid_url("at least one host is required"));
+    }
+    Ok((redis, hosts, path))
+}
+
+/// `[[first, last, [host, port, id], replicas..], ..]`; an empty host is the
+/// node that was asked
+fn parse_slots(
+    value: &Value,
+    asked: &ConnectionAddr,
+) -> RedisResult<Vec<(u16, u16, ConnectionAddr)>> {
+    let unexpected = || RedisError::from((ErrorKind::TypeError, "Unexpected CLUSTER SLOTS reply"));
+    let ranges = match value {
+        Value::Bulk(ranges) => ranges,
+        _ => return Err(unexpected()),
+    };
+    ranges
+        .iter()
+        .map(|range| match range {
+            Value::Bulk(range) => match &range[..] {
+                [Value::Int(first), Value::Int(last), Value::Bulk(master), ..] => {
+                    let port = match master.get(1) {
+                        Some(Value::Int(port)) => *port as u16,
+                        _ => return Err(unexpected()),
+                    };
+                    let addr = match (master.first(), asked) {
+                        (Some(Value::Data(host)), _) if !host.is_empty() => {
+                            ConnectionAddr::Tcp(String::from_utf8_lossy(host).into_owned(), port)
+                        }
+                        (_, ConnectionAddr::Tcp(host, _)) => {
+                            ConnectionAddr::Tcp(host.clone(), port)
+                        }
+                        _ => return Err(unexpected()),
+                    };
+                    Ok((*first as u16, *last as u16, addr))
+                }
+                _ => Err(unexpected()),
+            },
+            _ => Err(unexpected()),
+        })
+        .collect()
+}
+
+/// `ROLE` replies `["master", ..]` on a master
+fn is_master(role: &Value) -> bool {
+    matches!(role, Value::Bulk(items) if matches!(items.first(), Some(Value::Data(role)) if role == b"master"))
+}
+
+fn invalid_url(detail: &'static str) -> RedisError {
+    RedisError::from((
+        ErrorKind::InvalidClientConfig,
+        "Invalid redis url",
+        detail.to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_slots_match_redis() {
+        // CLUSTER KEYSLOT
+        assert_eq!(12182, key_slot(b"foo"));
+        assert_eq!(key_slot(b"user1000"), key_slot(b"{user1000}.following"));
+        assert_eq!(key_slot(b"{sessions/}a"), key_slot(b"{sessions/}b"));
+        // an empty tag hashes the whole key
+        assert_eq!(key_slot(b"{}foo"), crc16(b"{}foo") % SLOTS);
+    }
+
+    #[test]
+    fn parsing_sentinel_urls() -> RedisResult<()> {
+        let topology =
+            RedisTopology::open("redis+sentinel://:secret@10.0.0.1:26379,10.0.0.2/sessions/2")?;
+        match &topology.inner.mode {
+            Mode::Sentinel {
+                sentinels,
+                master_name,
+                redis,
+            } => {
+                assert_eq!(
+                    sentinels,
+                    &vec![
+                        ConnectionAddr::Tcp("10.0.0.1".into(), 26379),
+                        ConnectionAddr::Tcp("10.0.0.2".into(), 6379),
+                    ]
+                );
+                assert_eq!(master_name, "sessions");
+                assert_eq!(redis.db, 2);
+                assert_eq!(redis.password.as_deref(), Some("secret"));
+            }
+            mode => panic!("expected Sentinel, found {:?}", mode),
+        }
+        assert!(RedisTopology::open("redis+sentinel://10.0.0.1:26379").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn parsing_cluster_urls() -> RedisResult<()> {
+        let topology = RedisTopology::open("redis+cluster://10.0.0.1:7000,10.0.0.2:7001")?;
+        assert!(topology.is_cluster());
+        assert!(RedisTopology::open("redis+cluster://10.0.0.1:7000/1").is_err());
+        assert!(!RedisTopology::open("redis://127.0.0.1/1")?.is_cluster());
+        Ok(())
+    }
+
+    #[test]
+    fn parsing_cluster_slots() -> RedisResult<()> {
+        let node = |host: &str, port| {
+            Value::Bulk(vec![
+                Value::Data(host.into()),
+                Value::Int(port),
+                Value::Data(b"id".to_vec()),
+            ])
+        };
+        let reply = Value::Bulk(vec![
+            Value::Bulk(vec![
+                Value::Int(0),
+                Value::Int(8191),
+                node("10.0.0.1", 7000),
+                node("10.0.0.3", 7002),
+            ]),
+            Value::Bulk(vec![Value::Int(8192), Value::Int(16383), node("", 7001)]),
+        ]);
+        let asked = ConnectionAddr::Tcp("10.0.0.2".into(), 7001);
+        assert_eq!(
+            parse_slots(&reply, &asked)?,
+            vec![
+                (0, 8191, ConnectionAddr::Tcp("10.0.0.1".into(), 7000)),
+                (8192, 16383, ConnectionAddr::Tcp("10.0.0.2".into(), 7001)),
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn clones_share_a_connection_that_is_replaced_when_dropped() -> RedisResult<()> {
+        let topology = RedisTopology::open("redis://127.0.0.1")?;
+        let clone = topology.clone();
+        let client_id = || {
+            let mut cmd = redis::cmd("CLIENT");
+            cmd.arg("ID");
+            cmd
+        };
+        let id: i64 = topology.query_for("a", &client_id()).await?;
+        assert_eq!(id, clone.query_for::<i64>("b", &client_id()).await?);
+
+        // the server closes the shared connection
+        let mut admin = Client::open("redis://127.0.0.1")?
+            .get_multiplexed_tokio_connection()
+            .await?;
+        let _: () = redis::cmd("CLIENT")
+            .arg("KILL")
+            .arg("ID")
+            .arg(id)
+            .query_async(&mut admin)
+            .await?;
+
+        let reconnected: i64 = clone.query_for("b", &client_id()).await?;
+        assert_ne!(id, reconnected);
+        assert_eq!(
+            reconnected,
+            topology.query_for::<i64>("a", &client_id()).await?
+        );
+        Ok(())
+    }
+}
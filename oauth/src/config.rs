@@ -13,8 +13,10 @@ use std::{env, fmt};
 
 use crate::errors::AuthError;
 use crate::models::drive_provider::DriveProvider;
+use crate::models::file_filter::FileFilters;
 use crate::models::oauth_provider::OauthProvider;
 use crate::models::project_id::ProjectId;
+use crate::models::session_store::SessionBackend;
 //
 //
 // Models:
@@ -22,6 +24,8 @@ use crate::models::project_id::ProjectId;
 // 👉 Settings
 //      👉 Options
 //      👉 OauthServers
+//      👉 DriveServers
+//      👉 FileFilters (optional)
 //
 // let oauth_servers = &config_get()?.oauth_servers;
 // let options = &config_get()?.options;
@@ -49,6 +53,8 @@ pub struct Settings {
     pub options: Options,
     pub oauth_servers: OauthServers,
     pub drive_servers: DriveServers,
+    #[serde(default)]
+    pub file_filters: FileFilters,
 }
 // create from file
 impl Settings {
@@ -97,9 +103,31 @@ pub struct Options {
     pub port: u16,
     //
     pub root_dir: String,
-    //
+    /// 👉 `redis://host[:port][/db]` a single node
+    /// 👉 `redis+sentinel://host:26379,host:26379/master_name[/db]` Sentinel
+    /// 👉 `redis+cluster://host:7000,host:7001` Cluster
     pub redis_db: Secret<String>,
+    /// where the auth sessions are stored: redis, memory or sqlite
+    #[clap(long = "session-backend", default_value = "redis")]
+    #[serde(default = "default_session_backend")]
+    pub session_backend: SessionBackend,
+    /// connections shared by the session store
     pub redis_pool_size: u16,
+    /// the sqlite session backend: `sqlite://<path>?mode=rwc`
+    #[clap(long = "session-db", default_value = "sqlite://sessions.db?mode=rwc")]
+    #[serde(default = "default_session_db")]
+    pub session_db: String,
+    /// encrypts the sessions and the drive refresh tokens: `id:key,id:key`
+    /// (base64, 32 bytes); the first key encrypts, the others only decrypt
+    /// (rotation). Empty: not encrypted
+    #[clap(long = "session-keys", default_value = "")]
+    #[serde(default = "default_session_keys")]
+    pub session_keys: Secret<String>,
+    /// redis channel where the destroyed sessions are published (revoked,
+    /// expired), see async_redis_session::SessionEvent. Empty: not published
+    #[clap(long = "session-events-channel", default_value = "session-events")]
+    #[serde(default = "default_session_events_channel")]
+    pub session_events_channel: String,
 
     pub tnc_authorized_endpoint: String,
     pub tnc_authorized_drive_endpoint: String,
@@ -107,6 +135,67 @@ pub struct Options {
     pub tnc_app_endpoint: String,
     pub tnc_drive_token_endpoint: String,
     pub tnc_filesystem_endpoint: String,
+
+    /// public address of the webhook routes (providers append /<provider>)
+    #[clap(long = "webhook-endpoint", default_value = "")]
+    #[serde(default)]
+    pub webhook_endpoint: String,
+    /// redis channel where drive change events are published
+    #[clap(long = "drive-events-channel", default_value = "drive-events")]
+    #[serde(default = "default_drive_events_channel")]
+    pub drive_events_channel: String,
+    /// seconds a drive listing is cached; 0 disables the cache
+    #[clap(long = "listing-cache-ttl", default_value = "60")]
+    #[serde(default = "default_listing_cache_ttl")]
+    pub listing_cache_ttl: usize,
+    /// seconds an inferred schema is cached (keyed by content hash); 0 disables
+    #[clap(long = "schema-cache-ttl", default_value = "86400")]
+    #[serde(default = "default_schema_cache_ttl")]
+    pub schema_cache_ttl: usize,
+
+    /// seconds to establish a connection with a drive (or tnc) server
+    #[clap(long = "http-connect-timeout", default_value = "5")]
+    #[serde(default = "default_http_connect_timeout")]
+    pub http_connect_timeout: u64,
+    /// seconds to complete a request, including reading the response; for
+    /// a streaming body (uploads), seconds to wait for each chunk
+    #[clap(long = "http-timeout", default_value = "60")]
+    #[serde(default = "default_http_timeout")]
+    pub http_timeout: u64,
+    /// retries when throttled (429) or unavailable; see HttpClient
+    #[clap(long = "http-max-retries", default_value = "3")]
+    #[serde(default = "default_http_max_retries")]
+    pub http_max_retries: u32,
+}
+fn default_drive_events_channel() -> String {
+    "drive-events".to_string()
+}
+fn default_session_backend() -> SessionBackend {
+    SessionBackend::Redis
+}
+fn default_session_db() -> String {
+    "sqlite://sessions.db?mode=rwc".to_string()
+}
+fn default_session_keys() -> Secret<String> {
+    Secret::new(String::new())
+}
+fn default_session_events_channel() -> String {
+    "session-events".to_string()
+}
+fn default_listing_cache_ttl() -> usize {
+    60
+}
+fn default_schema_cache_ttl() -> usize {
+    86400
+}
+fn default_http_connect_timeout() -> u64 {
+    5
+}
+fn default_http_timeout() -> u64 {
+    60
+}
+fn default_http_max_retries() -> u32 {
+    3
 }
 
 //------------------------------------------------------------------------------
@@ -154,6 +243,9 @@ pub(crate) struct FilesRequest {
     pub query_ls: String,
     pub query_read: Option<String>,
     pub json_body_ls: Option<String>,
+    // when the provider hosts file content separately (e.g., DropBox)
+    #[serde(default)]
+    pub content_server: Option<String>,
 }
 pub type DriveServers = HashMap<DriveProvider, DriveServer>;
 
@@ -239,6 +331,22 @@ pub fn tnc_drive_token_endpoint() -> Result<Uri, AuthError> {
 
     Ok(endpoint)
 }
+pub fn webhook_endpoint(drive_provider: &DriveProvider) -> Result<Uri, AuthError> {
+    let prefix: &String = &config_get()?.options.webhook_endpoint;
+    if prefix.is_empty() {
+        return Err(AuthError::ConfigError(
+            "webhook_endpoint is not configured".into(),
+        ));
+    }
+    let endpoint = format!("{}/{}", prefix, drive_provider.to_path());
+
+    let endpoint = Uri::try_from(endpoint).map_err(|err| {
+        let message = format!("Webhook endpoint uri failed\n{:?}", &err);
+        AuthError::InvalidUrl(message.into()).trace()
+    })?;
+
+    Ok(endpoint)
+}
 ///
 /// Takes ownership of project_id to promote limited reuse
 ///
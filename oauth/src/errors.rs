@@ -2,7 +2,6 @@ use axum::extract::Json;
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
 // use oauth2::RequestTokenError;
-use serde_json;
 use serde_json::json;
 use std::fmt;
 // use std::convert::From;
@@ -57,6 +56,31 @@ pub enum AuthError {
     ProjectIdError(Message),
     #[error("{:?}", .0)]
     MissingQuery(Message),
+    #[error("{:?}", .0)]
+    StoreError(Message),
+    #[error("{:?}", .0)]
+    UnsupportedMedia(Message),
+    /// the drive token could not be refreshed; hosts the url that restarts consent
+    #[error("{:?}", .0)]
+    ReconnectRequired(Message),
+    /// errors reported by a drive provider (see DriveError)
+    #[error("{:?}", .0)]
+    DriveNotFound(Message),
+    #[error("{:?}", .0)]
+    DriveForbidden(Message),
+    #[error("{:?}", .0)]
+    DriveConflict(Message),
+    #[error("{:?}", .0)]
+    DriveQuotaExceeded(Message),
+    #[error("{:?}", .0)]
+    DriveRateLimited(Message),
+    #[error("{:?}", .0)]
+    DriveError(Message),
+    #[error("{:?}", .0)]
+    SessionNotFound(Message),
+    /// the feature requires another session backend (e.g., redis)
+    #[error("{:?}", .0)]
+    UnsupportedBackend(Message),
 }
 
 /// Modeled after reqwest Error
@@ -136,6 +160,58 @@ impl IntoResponse for AuthError {
                 (StatusCode::UNAUTHORIZED, "Failed to retrieve token", msg)
             }
             AuthError::Unauthorized(msg) => (StatusCode::UNAUTHORIZED, "Missing credentials", msg),
+            AuthError::StoreError(msg) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to access the store",
+                msg,
+            ),
+            AuthError::UnsupportedMedia(msg) => (
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "The file format is not supported",
+                msg,
+            ),
+            AuthError::DriveNotFound(msg) => {
+                (StatusCode::NOT_FOUND, "The drive item was not found", msg)
+            }
+            AuthError::DriveConflict(msg) => (
+                StatusCode::CONFLICT,
+                "The drive has an item with the same name",
+                msg,
+            ),
+            AuthError::DriveForbidden(msg) => (
+                StatusCode::FORBIDDEN,
+                "The drive denied access to the item",
+                msg,
+            ),
+            AuthError::DriveQuotaExceeded(msg) => (
+                StatusCode::INSUFFICIENT_STORAGE,
+                "The drive storage quota is exceeded",
+                msg,
+            ),
+            AuthError::DriveRateLimited(msg) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                "The drive is limiting requests; retry later",
+                msg,
+            ),
+            AuthError::DriveError(msg) => {
+                (StatusCode::BAD_GATEWAY, "The drive request failed", msg)
+            }
+            AuthError::SessionNotFound(msg) => {
+                (StatusCode::NOT_FOUND, "The session was not found", msg)
+            }
+            AuthError::UnsupportedBackend(msg) => (
+                StatusCode::NOT_IMPLEMENTED,
+                "Not supported by the session backend",
+                msg,
+            ),
+            AuthError::ReconnectRequired(reconnect_url) => {
+                let body = Json(json!({
+                    "error": "Reconnect required",
+                    "message": "The drive authorization is no longer valid",
+                    "reconnect_url": reconnect_url,
+                }));
+                return (StatusCode::UNAUTHORIZED, body).into_response();
+            }
         };
         let body = Json(json!({
             "error": error,
@@ -1,4 +1,3 @@
-use async_redis_session::RedisSessionStore;
 use axum::extract::{Extension, Path};
 use axum::response::Redirect;
 use http::HeaderMap;
@@ -8,6 +7,7 @@ use crate::errors::AuthError;
 use crate::handlers::shared;
 use crate::models::oauth_clients::{OauthClient, OauthClients};
 use crate::models::oauth_provider::OauthProvider;
+use crate::models::session_store::AuthStore;
 
 use axum_macros::debug_handler;
 ///
@@ -19,7 +19,7 @@ use axum_macros::debug_handler;
 #[debug_handler]
 pub async fn handle(
     Path(oauth_provider): Path<OauthProvider>,
-    Extension(auth_store): Extension<RedisSessionStore>,
+    Extension(auth_store): Extension<AuthStore>,
     Extension(clients): Extension<OauthClients>,
 ) -> Result<(HeaderMap, Redirect), AuthError> {
     if let Some(OauthClient { client, scope, .. }) = clients.get(&oauth_provider) {
@@ -48,9 +48,14 @@ pub async fn handle(
         // Finalize the URL + CSRF state
         let (auth_url, csrf_state) = auth_req.url();
 
-        let headers =
-            shared::set_session(HeaderMap::new(), auth_store, pkce_code_verifier, csrf_state)
-                .await?;
+        let headers = shared::set_session(
+            HeaderMap::new(),
+            auth_store,
+            pkce_code_verifier,
+            csrf_state,
+            None,
+        )
+        .await?;
 
         tracing::debug!("\n📖🍪 headers: {:?}\n", headers);
 
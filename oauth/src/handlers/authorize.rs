@@ -1,5 +1,4 @@
-use async_redis_session::RedisSessionStore;
-use axum::extract::{Extension, Path};
+use axum::extract::{Extension, Path, TypedHeader};
 use axum::response::Redirect;
 use http::HeaderMap;
 use oauth2::{CsrfToken, PkceCodeChallenge, Scope};
@@ -9,6 +8,7 @@ use crate::handlers::shared;
 use crate::models::drive_clients::{DriveClient, DriveClients};
 use crate::models::drive_provider::DriveProvider;
 use crate::models::project_id::ProjectId;
+use crate::models::session_store::AuthStore;
 
 use axum_macros::debug_handler;
 ///
@@ -24,8 +24,9 @@ use axum_macros::debug_handler;
 #[debug_handler]
 pub async fn handle(
     Path((drive_provider, project_id)): Path<(DriveProvider, ProjectId)>,
-    Extension(auth_store): Extension<RedisSessionStore>,
+    Extension(auth_store): Extension<AuthStore>,
     Extension(clients): Extension<DriveClients>,
+    cookies: Option<TypedHeader<headers::Cookie>>,
 ) -> Result<(HeaderMap, Redirect), AuthError> {
     if let Some(DriveClient { client, scopes, .. }) = clients.get(&drive_provider) {
         //
@@ -60,9 +61,19 @@ pub async fn handle(
             .add_extra_param("refresh_token_key", "refresh_access") // optional naming
             .url();
 
-        let headers =
-            shared::set_session(HeaderMap::new(), auth_store, pkce_code_verifier, csrf_state)
-                .await?;
+        // 🔐 the drive is owned by the signed-in user (see DriveAccess)
+        let user_id = match &cookies {
+            Some(TypedHeader(cookies)) => shared::session_user(cookies, &auth_store).await?,
+            None => None,
+        };
+        let headers = shared::set_session(
+            HeaderMap::new(),
+            auth_store,
+            pkce_code_verifier,
+            csrf_state,
+            user_id,
+        )
+        .await?;
 
         tracing::debug!("\n🍪 headers: {:?}\n", headers);
         tracing::debug!("\n>>> auth_url: {:?}\n", &auth_url);
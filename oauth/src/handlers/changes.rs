@@ -0,0 +1,106 @@
+use axum::extract::{Extension, Path, Query};
+use axum::Json;
+use serde::Deserialize;
+
+use crate::errors::AuthError;
+use crate::handlers::drive_api::{self, AuthDriveToken, DriveAccess, DriveCaller};
+use crate::models::changes::{Changes, SyncStart};
+use crate::models::drive_clients::{DriveClient, DriveClients};
+use crate::models::drive_cursor::{DriveCursor, DriveCursors};
+use crate::models::drive_provider::DriveProvider;
+use crate::models::http_client::HttpClient;
+use crate::models::project_id::ProjectId;
+use crate::models::refresh_tokens::RefreshTokens;
+
+#[derive(Debug, Deserialize)]
+pub struct ChangesParams {
+    /// a cursor returned by a previous request; defaults to the stored cursor
+    /// (the stored cursor is not advanced when a cursor is sent)
+    since: Option<String>,
+}
+///
+/// 🔗 changes endpoint (delta sync)
+/// The files added, modified and deleted since the last sync of the project's drive.
+///
+/// The first request starts tracking from "now" (no changes). The cursor is
+/// stored in redis per (project, provider) for the next refresh; it is only
+/// advanced once the changes are retrieved, and not when the request sends
+/// its own cursor (`since`). The time of the sync is kept until the last
+/// page (`has_more` false) is retrieved.
+///
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn handle(
+    Path((drive_provider, project_id)): Path<(DriveProvider, ProjectId)>,
+    Query(token): Query<AuthDriveToken>,
+    caller: DriveCaller,
+    Query(params): Query<ChangesParams>,
+    Extension(clients): Extension<DriveClients>,
+    Extension(http): Extension<HttpClient>,
+    Extension(tokens): Extension<RefreshTokens>,
+    Extension(cursors): Extension<DriveCursors>,
+) -> Result<Json<Changes>, AuthError> {
+    if let Some(drive_client @ DriveClient { files_request, .. }) = clients.get(&drive_provider) {
+        let access_token = DriveAccess::new(
+            token,
+            drive_client,
+            &tokens,
+            &project_id,
+            &drive_provider,
+            &caller,
+        );
+        let stored = cursors.get(&project_id, &drive_provider).await?;
+
+        let start = match SyncStart::new(params.since, stored) {
+            Some(start) => start,
+            None => {
+                let cursor =
+                    drive_api::start_cursor(&http, &drive_provider, files_request, &access_token)
+                        .await?;
+                cursors
+                    .set(
+                        &project_id,
+                        &drive_provider,
+                        &DriveCursor::new(cursor.clone()),
+                    )
+                    .await?;
+                tracing::debug!("\n🔄 Started tracking changes: {}\n", &drive_provider);
+                return Ok(Json(Changes::new(
+                    drive_provider.into(),
+                    Vec::new(),
+                    None,
+                    cursor,
+                    false,
+                )));
+            }
+        };
+
+        let (changes, cursor, has_more) = drive_api::changes(
+            &http,
+            &drive_provider,
+            files_request,
+            &access_token,
+            &start.cursor,
+        )
+        .await?;
+
+        let changes = Changes::new(
+            drive_provider.clone().into(),
+            changes,
+            start.synced_at,
+            cursor,
+            has_more,
+        );
+        tracing::debug!("\n🔄 Changes:\n{:#?}\n", &changes);
+
+        if let Some(stored) = start.stored {
+            cursors
+                .set(&project_id, &drive_provider, &changes.next_cursor(&stored))
+                .await?;
+        }
+        Ok(Json(changes))
+    } else {
+        Err(AuthError::UnsupportedProvider(
+            (&("Auth client not found")).into(),
+        ))
+    }
+}
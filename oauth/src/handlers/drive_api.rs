@@ -0,0 +1,1518 @@
+///
+/// Shared support for the requests made to the drive providers' apis
+/// (protected resources).
+///
+/// 🔖 The configured `drive_server` hosts the provider api; the paths
+///    used here are relative to that host.
+///
+use axum::async_trait;
+use axum::extract::{FromRequest, RequestParts};
+use axum::http::header::{ACCEPT, AUTHORIZATION, CONTENT_RANGE, CONTENT_TYPE, RANGE};
+use bytes::Bytes;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use headers::HeaderMapExt;
+use http::{HeaderValue, Method, Version};
+use oauth2::basic::BasicClient;
+use oauth2::{AccessToken, RefreshToken};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use url::Url;
+use uuid::Uuid;
+
+use crate::config::webhook_endpoint;
+use crate::errors::AuthError;
+use crate::handlers::shared;
+use crate::models::changes::{Change, RawChangesGoogle, RawDeltaMSGraph, RawListFolderDropBox};
+use crate::models::drive_clients::{DriveClient, FilesRequest};
+use crate::models::drive_error::{DriveError, DriveErrorKind};
+use crate::models::drive_provider::DriveProvider;
+use crate::models::drives::{
+    Drive, DriveKind, RawAccountDropBox, RawDrivesGoogle, RawDrivesMSGraph,
+    RawSharedFoldersDropBox, RawSitesMSGraph, GOOGLE_MY_DRIVE,
+};
+use crate::models::file_filter::FileFilter;
+use crate::models::files::{
+    drop_box, google, ms_graph, File, FilesBuilder, RawFileDropBox, RawFileGoogle, RawFileMSGraph,
+    RawFiles, RawSearchDropBox,
+};
+use crate::models::http_client::HttpClient;
+use crate::models::project_id::ProjectId;
+use crate::models::refresh_tokens::{RefreshTokens, StoredToken};
+use crate::models::session_store::AuthStore;
+use crate::models::webhooks::Subscription;
+use crate::utils::deserialize_number_from_string;
+
+/// Fields requested from Google for each file
+pub(crate) const GOOGLE_FILE_FIELDS: &str = "id,name,mimeType,size,createdTime,modifiedTime,parents,webViewLink,md5Checksum,sha1Checksum,sha256Checksum,headRevisionId";
+/// DropBox hosts the file content on a separate server (see content_server)
+pub(crate) const DROPBOX_CONTENT_SERVER: &str = "https://content.dropboxapi.com";
+/// Number of files requested per page
+pub(crate) const PAGE_SIZE: u16 = 100;
+/// Limits the number of pages of changes retrieved in one request
+const MAX_CHANGE_PAGES: u16 = 20;
+/// Limits the number of SharePoint sites for which the libraries are listed
+const MAX_SITES: u16 = 50;
+/// Seconds; the maximum for a changes channel is one week
+const GOOGLE_CHANNEL_TTL: i64 = 7 * 24 * 60 * 60;
+/// Minutes; the maximum for a driveItem subscription is 42300
+const MSGRAPH_SUBSCRIPTION_TTL: i64 = 29 * 24 * 60;
+/// Returns the access token refreshed for the caller (see DriveAccess)
+pub(crate) const REFRESHED_TOKEN_HEADER: &str = "x-drive-access-token";
+
+#[derive(Debug, Deserialize)]
+pub struct AuthDriveToken {
+    access_token: String,
+}
+impl AuthDriveToken {
+    /// identifies the token without keeping it (see ListingCache)
+    pub fn digest(&self) -> String {
+        hex::encode(Sha256::digest(self.access_token.as_bytes()))
+    }
+}
+impl From<AuthDriveToken> for AccessToken {
+    fn from(AuthDriveToken { access_token }: AuthDriveToken) -> AccessToken {
+        AccessToken::new(access_token)
+    }
+}
+/* -------------------------------------------------------------------------------- */
+///
+/// The access token refreshed while handling the request; returned to the
+/// client in the REFRESHED_TOKEN_HEADER (see middleware::drive_token)
+///
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RefreshedToken(Arc<Mutex<Option<String>>>);
+impl RefreshedToken {
+    fn set(&self, access_token: &str) {
+        *self.0.lock().unwrap() = Some(access_token.to_string());
+    }
+    pub fn take(&self) -> Option<String> {
+        self.0.lock().unwrap().take()
+    }
+}
+///
+/// 🙂 The user that sends the drive request (the user bound to the auth
+/// session), when signed in
+///
+#[derive(Debug, Clone, Default)]
+pub(crate) struct DriveCaller {
+    user_id: Option<String>,
+    refreshed: RefreshedToken,
+}
+impl DriveCaller {
+    ///
+    /// The caller authorized the drive (see drive_authorized)
+    ///
+    fn owns(&self, stored: &StoredToken) -> bool {
+        stored.owner.is_some() && stored.owner == self.user_id
+    }
+}
+#[async_trait]
+impl<B: Send> FromRequest<B> for DriveCaller {
+    type Rejection = AuthError;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let extensions = req.extensions();
+        let refreshed = extensions
+            .and_then(|extensions| extensions.get::<RefreshedToken>())
+            .cloned()
+            .unwrap_or_default();
+        let store = extensions
+            .and_then(|extensions| extensions.get::<AuthStore>())
+            .cloned()
+            .ok_or_else(|| AuthError::InternalError("Missing the auth store".into()))?;
+        let user_id = match req
+            .headers()
+            .and_then(|headers| headers.typed_get::<headers::Cookie>())
+        {
+            Some(cookies) => shared::session_user(&cookies, &store).await?,
+            None => None,
+        };
+        Ok(DriveCaller { user_id, refreshed })
+    }
+}
+/* -------------------------------------------------------------------------------- */
+///
+/// 🔑 The access token used for the drive requests
+///
+/// When the provider responds 401, the token is refreshed once using the
+/// stored refresh token (see RefreshTokens) and the request is sent again.
+/// When that fails, the request fails with ReconnectRequired.
+///
+/// 🔐 Only the owner of the stored token (the user that authorized the
+///    drive) gets it refreshed; the token sent by anyone else is simply
+///    rejected. The refreshed token is returned to the caller.
+///
+#[derive(Debug)]
+pub(crate) struct DriveAccess {
+    access_token: Mutex<AccessToken>,
+    refreshed: AtomicBool,
+    client: BasicClient,
+    tokens: RefreshTokens,
+    project_id: ProjectId,
+    drive_provider: DriveProvider,
+    caller: DriveCaller,
+}
+impl DriveAccess {
+    pub fn new(
+        token: AuthDriveToken,
+        drive_client: &DriveClient,
+        tokens: &RefreshTokens,
+        project_id: &ProjectId,
+        drive_provider: &DriveProvider,
+        caller: &DriveCaller,
+    ) -> Self {
+        DriveAccess {
+            access_token: Mutex::new(token.into()),
+            refreshed: AtomicBool::new(false),
+            client: drive_client.client.clone(),
+            tokens: tokens.clone(),
+            project_id: project_id.clone(),
+            drive_provider: drive_provider.clone(),
+            caller: caller.clone(),
+        }
+    }
+    ///
+    /// ⏰ The access of the owner of the stored token, for the requests the
+    /// service sends on its own (e.g., renewing the subscriptions)
+    ///
+    pub fn for_owner(
+        stored: StoredToken,
+        drive_client: &DriveClient,
+        tokens: &RefreshTokens,
+        project_id: &ProjectId,
+        drive_provider: &DriveProvider,
+    ) -> Self {
+        let caller = DriveCaller {
+            user_id: stored.owner.clone(),
+            refreshed: RefreshedToken::default(),
+        };
+        let token = AuthDriveToken {
+            access_token: stored.access_token,
+        };
+        DriveAccess::new(
+            token,
+            drive_client,
+            tokens,
+            project_id,
+            drive_provider,
+            &caller,
+        )
+    }
+    fn bearer(&self) -> String {
+        let access_token = self.access_token.lock().unwrap();
+        format!("Bearer {}", access_token.secret())
+    }
+    ///
+    /// The drive endpoint that restarts consent
+    ///
+    fn reconnect_required(&self) -> AuthError {
+        AuthError::ReconnectRequired(
+            format!(
+                "/drive/{}/{}",
+                self.drive_provider.to_path(),
+                self.project_id
+            )
+            .into(),
+        )
+    }
+    ///
+    /// 🔄 Replace the access token, once
+    ///
+    /// A token refreshed by a concurrent request is used as-is; the refresh
+    /// token may only be valid once (MSGraph rotates it).
+    ///
+    async fn refresh(&self, http: &HttpClient) -> Result<(), AuthError> {
+        if self.refreshed.swap(true, Ordering::SeqCst) {
+            return Err(self.reconnect_required());
+        }
+        let used = self.access_token.lock().unwrap().secret().clone();
+        let stored = self
+            .tokens
+            .get(&self.project_id, &self.drive_provider)
+            .await?
+            .ok_or_else(|| self.reconnect_required())?;
+        if !self.caller.owns(&stored) {
+            return Err(self.reconnect_required());
+        }
+
+        let token = if stored.access_token != used && stored.is_current() {
+            stored
+        } else {
+            let token_response = self
+                .client
+                .exchange_refresh_token(&RefreshToken::new(stored.refresh_token.clone()))
+                .request_async(|request| http.oauth2(request))
+                .await
+                .map_err(|err| {
+                    tracing::debug!("\n🔑 refresh failed:\n{:#?}\n", err);
+                    self.reconnect_required()
+                })?;
+            let token = StoredToken::new(
+                &token_response,
+                Some(stored.refresh_token),
+                stored.owner.clone(),
+            )
+            .ok_or_else(|| self.reconnect_required())?;
+            self.tokens
+                .set(&self.project_id, &self.drive_provider, &token)
+                .await?;
+            token
+        };
+        tracing::debug!("\n🔑 refreshed the {} token\n", &self.drive_provider);
+
+        self.caller.refreshed.set(&token.access_token);
+        *self.access_token.lock().unwrap() = AccessToken::new(token.access_token);
+        Ok(())
+    }
+}
+/* -------------------------------------------------------------------------------- */
+///
+/// 📬 Request with the headers expected by all of the drive apis
+///
+pub(crate) fn request(
+    http: &HttpClient,
+    method: Method,
+    url: impl AsRef<str>,
+    access_token: &DriveAccess,
+) -> reqwest::RequestBuilder {
+    http.request(method, url)
+        .header(AUTHORIZATION, access_token.bearer())
+        .header(ACCEPT, "application/json")
+        .version(Version::HTTP_11)
+}
+/* -------------------------------------------------------------------------------- */
+///
+/// 📥 Send the request; a non-success status is returned as an AuthError
+///
+/// Throttled and failed idempotent requests are retried (see HttpClient).
+///
+/// 401: the token is refreshed and the request sent again (see DriveAccess).
+/// A streaming body cannot be sent again; the client resends the request.
+///
+pub(crate) async fn send(
+    http: &HttpClient,
+    access_token: &DriveAccess,
+    request: reqwest::RequestBuilder,
+) -> Result<reqwest::Response, AuthError> {
+    send_with(http, access_token, request, false).await
+}
+///
+/// 📥 Send a request that does not change anything (e.g., the DropBox POST
+/// list_folder, get_metadata, search); retried as an idempotent request
+///
+pub(crate) async fn send_read_only(
+    http: &HttpClient,
+    access_token: &DriveAccess,
+    request: reqwest::RequestBuilder,
+) -> Result<reqwest::Response, AuthError> {
+    send_with(http, access_token, request, true).await
+}
+async fn send_with(
+    http: &HttpClient,
+    access_token: &DriveAccess,
+    request: reqwest::RequestBuilder,
+    read_only: bool,
+) -> Result<reqwest::Response, AuthError> {
+    tracing::debug!("\n📬 request:\n{:#?}\n", &request);
+
+    let retry = request.try_clone();
+    let response = if read_only {
+        http.send_read_only(request).await
+    } else {
+        http.send(request).await
+    }
+    .map_err(|err| AuthError::InvalidResponse(err.into()))?;
+
+    tracing::debug!("\n📥 response:\n{:#?}\n", &response);
+
+    if response.status() != reqwest::StatusCode::UNAUTHORIZED {
+        return status(response, access_token).await;
+    }
+    access_token.refresh(http).await?;
+    let mut request = match retry {
+        Some(retry) => retry
+            .build()
+            .map_err(|err| AuthError::InternalError(err.to_string().into()))?,
+        None => {
+            let message = "Drive token refreshed; resend the request".to_string();
+            return Err(AuthError::Unauthorized(message.into()));
+        }
+    };
+    // pre-authorized urls (e.g., upload sessions) are sent without the token
+    if request.headers().contains_key(AUTHORIZATION) {
+        let bearer = HeaderValue::from_str(&access_token.bearer())
+            .map_err(|err| AuthError::InvalidHeaderValue(err.to_string().into()))?;
+        request.headers_mut().insert(AUTHORIZATION, bearer);
+    }
+    let response = if read_only {
+        http.execute_read_only(request).await
+    } else {
+        http.execute(request).await
+    }
+    .map_err(|err| AuthError::InvalidResponse(err.into()))?;
+
+    match response.status() {
+        reqwest::StatusCode::UNAUTHORIZED => Err(access_token.reconnect_required()),
+        _ => status(response, access_token).await,
+    }
+}
+///
+/// A non-success status is returned as an AuthError that reflects the
+/// provider's error (see DriveError); a revoked token requires the user to
+/// reconnect the drive.
+///
+async fn status(
+    response: reqwest::Response,
+    access_token: &DriveAccess,
+) -> Result<reqwest::Response, AuthError> {
+    // 308 Resume Incomplete: Google accepted a chunk of an upload session
+    if response.status().is_success()
+        || response.status() == reqwest::StatusCode::PERMANENT_REDIRECT
+    {
+        return Ok(response);
+    }
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    let error = DriveError::parse(status, &body);
+    tracing::debug!("\n❌ drive error ({}):\n{:#?}\n", error.kind(), &error);
+
+    match error.kind() {
+        DriveErrorKind::TokenRevoked => Err(access_token.reconnect_required()),
+        _ => Err(error.into()),
+    }
+}
+///
+/// Extract the data from the response body
+///
+pub(crate) async fn parse<T: DeserializeOwned>(
+    response: reqwest::Response,
+) -> Result<T, AuthError> {
+    response.json::<T>().await.map_err(|err| {
+        let message = format!("Unexpected drive data: {}", err);
+        AuthError::JsonParsingError(message.into())
+    })
+}
+/* -------------------------------------------------------------------------------- */
+///
+/// 🗄️ Metadata for a single file
+///
+/// * Google: GET files/{id}
+/// * MSGraph: GET me/drive/items/{id}, drives/{drive_id}/items/{id}
+/// * DropBox: POST files/get_metadata
+///
+/// `drive_id` is a shared drive (see `list_drives`); None for the personal drive.
+///
+pub(crate) async fn get_file(
+    http: &HttpClient,
+    drive_provider: &DriveProvider,
+    files_request: &FilesRequest,
+    access_token: &DriveAccess,
+    file_id: &str,
+    drive_id: Option<&str>,
+) -> Result<File, AuthError> {
+    let FilesRequest {
+        drive_server,
+        query_read,
+        ..
+    } = files_request;
+
+    let file: File = match drive_provider {
+        DriveProvider::Google => {
+            let url = format!(
+                "{host}/drive/v3/files/{file_id}{query}",
+                host = drive_server,
+                file_id = file_id,
+                query = query_read
+                    .clone()
+                    .unwrap_or_else(|| format!("?fields={}", GOOGLE_FILE_FIELDS))
+            );
+            // file ids are unique across drives
+            let response = send(
+                http,
+                access_token,
+                request(http, Method::GET, url, access_token)
+                    .query(&[("supportsAllDrives", "true")]),
+            )
+            .await?;
+            parse::<RawFileGoogle>(response).await?.into()
+        }
+        DriveProvider::MSGraph => {
+            let url = format!(
+                "{host}/v1.0/{drive}/items/{file_id}{query}",
+                host = drive_server,
+                drive = ms_graph_drive(drive_id),
+                file_id = file_id,
+                query = query_read.as_deref().unwrap_or_default()
+            );
+            let response = send(
+                http,
+                access_token,
+                request(http, Method::GET, url, access_token),
+            )
+            .await?;
+            parse::<RawFileMSGraph>(response).await?.into()
+        }
+        DriveProvider::DropBox => {
+            let url = format!("{host}/2/files/get_metadata", host = drive_server);
+            let response = send_read_only(
+                http,
+                access_token,
+                with_drop_box_root(request(http, Method::POST, url, access_token), drive_id)
+                    .header(CONTENT_TYPE, "application/json")
+                    .json(&json!({ "path": file_id })),
+            )
+            .await?;
+            parse::<RawFileDropBox>(response).await?.into()
+        }
+        _ => return Err(AuthError::InternalError("Unsupported drive type".into())),
+    };
+
+    tracing::debug!("\n🎉 File:\n{:#?}\n", &file);
+    Ok(file)
+}
+///
+/// 📥 The first `limit` bytes of the file content
+///
+/// * Google: GET files/{id}?alt=media; GET files/{id}/export for Google
+///   Workspace files (`export` is the mime type)
+/// * MSGraph: GET items/{id}/content
+/// * DropBox: POST files/download (content server)
+///
+/// A Range header limits the bytes sent by the provider (not for exports).
+/// Returns the content and whether the file is larger than the content.
+///
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn download(
+    http: &HttpClient,
+    drive_provider: &DriveProvider,
+    files_request: &FilesRequest,
+    access_token: &DriveAccess,
+    file_id: &str,
+    drive_id: Option<&str>,
+    export: Option<&str>,
+    limit: usize,
+) -> Result<(Bytes, bool), AuthError> {
+    let request = content_request(
+        http,
+        drive_provider,
+        files_request,
+        access_token,
+        file_id,
+        drive_id,
+        export,
+        &format!("bytes=0-{}", limit - 1),
+    )?;
+    let mut response = send_read_only(http, access_token, request).await?;
+
+    // bytes 0-{end}/{size}
+    let size = response
+        .headers()
+        .get(CONTENT_RANGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.rsplit('/').next())
+        .and_then(|size| size.parse::<usize>().ok())
+        .or_else(|| response.content_length().map(|size| size as usize));
+
+    // the provider may ignore the range; stop reading at the limit
+    let mut content = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|err| AuthError::InvalidResponse(err.into()))?
+    {
+        content.extend_from_slice(&chunk);
+        if content.len() > limit {
+            break;
+        }
+    }
+    let truncated = content.len() > limit || size.map_or(false, |size| size > content.len());
+    content.truncate(limit);
+
+    Ok((Bytes::from(content), truncated))
+}
+///
+/// 📥 The bytes `start..=end` of the file content (e.g., the footer of a file)
+///
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn download_range(
+    http: &HttpClient,
+    drive_provider: &DriveProvider,
+    files_request: &FilesRequest,
+    access_token: &DriveAccess,
+    file_id: &str,
+    drive_id: Option<&str>,
+    start: u64,
+    end: u64,
+) -> Result<Bytes, AuthError> {
+    let request = content_request(
+        http,
+        drive_provider,
+        files_request,
+        access_token,
+        file_id,
+        drive_id,
+        None,
+        &format!("bytes={}-{}", start, end),
+    )?;
+    let response = send_read_only(http, access_token, request).await?;
+    let status = response.status();
+    let content = response
+        .bytes()
+        .await
+        .map_err(|err| AuthError::InvalidResponse(err.into()))?;
+
+    // the provider may ignore the range and send the whole file
+    let length = (end - start + 1) as usize;
+    if status == reqwest::StatusCode::PARTIAL_CONTENT && content.len() == length {
+        Ok(content)
+    } else if status == reqwest::StatusCode::OK && content.len() > end as usize {
+        Ok(content.slice(start as usize..=end as usize))
+    } else {
+        Err(AuthError::InvalidResponse(
+            "The drive did not return the requested range".into(),
+        ))
+    }
+}
+///
+/// The request for the file content, limited to `range` (not for exports)
+///
+#[allow(clippy::too_many_arguments)]
+fn content_request(
+    http: &HttpClient,
+    drive_provider: &DriveProvider,
+    files_request: &FilesRequest,
+    access_token: &DriveAccess,
+    file_id: &str,
+    drive_id: Option<&str>,
+    export: Option<&str>,
+    range: &str,
+) -> Result<reqwest::RequestBuilder, AuthError> {
+    let FilesRequest {
+        drive_server,
+        content_server,
+        ..
+    } = files_request;
+
+    let request = match (drive_provider, export) {
+        (DriveProvider::Google, Some(mime_type)) => request(
+            http,
+            Method::GET,
+            format!(
+                "{host}/drive/v3/files/{file_id}/export",
+                host = drive_server,
+                file_id = file_id
+            ),
+            access_token,
+        )
+        .query(&[("mimeType", mime_type)]),
+        (DriveProvider::Google, None) => request(
+            http,
+            Method::GET,
+            format!(
+                "{host}/drive/v3/files/{file_id}",
+                host = drive_server,
+                file_id = file_id
+            ),
+            access_token,
+        )
+        .query(&[("alt", "media"), ("supportsAllDrives", "true")])
+        .header(RANGE, range),
+        (DriveProvider::MSGraph, _) => request(
+            http,
+            Method::GET,
+            format!(
+                "{host}/v1.0/{drive}/items/{file_id}/content",
+                host = drive_server,
+                drive = ms_graph_drive(drive_id),
+                file_id = file_id
+            ),
+            access_token,
+        )
+        .header(RANGE, range),
+        (DriveProvider::DropBox, _) => with_drop_box_root(
+            request(
+                http,
+                Method::POST,
+                format!(
+                    "{host}/2/files/download",
+                    host = content_server.as_deref().unwrap_or(DROPBOX_CONTENT_SERVER)
+                ),
+                access_token,
+            ),
+            drive_id,
+        )
+        .header("Dropbox-API-Arg", ascii_json(&json!({ "path": file_id })))
+        .header(RANGE, range),
+        _ => return Err(AuthError::InternalError("Unsupported drive type".into())),
+    };
+    Ok(request)
+}
+/* -------------------------------------------------------------------------------- */
+///
+/// 🗂️ The drives available to the user, the personal drive first
+///
+/// * Google: My Drive, GET drives
+/// * MSGraph: GET me/drives, GET sites?search=* then sites/{id}/drives
+/// * DropBox: POST users/get_current_account, POST sharing/list_folders
+///
+pub(crate) async fn list_drives(
+    http: &HttpClient,
+    drive_provider: &DriveProvider,
+    files_request: &FilesRequest,
+    access_token: &DriveAccess,
+) -> Result<Vec<Drive>, AuthError> {
+    let FilesRequest { drive_server, .. } = files_request;
+
+    match drive_provider {
+        DriveProvider::Google => {
+            let mut drives = vec![Drive {
+                id: GOOGLE_MY_DRIVE.to_string(),
+                name: "My Drive".to_string(),
+                kind: DriveKind::Personal,
+                site: None,
+            }];
+            let url = format!("{host}/drive/v3/drives", host = drive_server);
+            let page_size = PAGE_SIZE.to_string();
+            let mut page_token: Option<String> = None;
+            loop {
+                let mut query = vec![("pageSize", page_size.as_str())];
+                if let Some(page_token) = &page_token {
+                    query.push(("pageToken", page_token.as_str()));
+                }
+                let response = send(
+                    http,
+                    access_token,
+                    request(http, Method::GET, &url, access_token).query(&query),
+                )
+                .await?;
+                let page = parse::<RawDrivesGoogle>(response).await?;
+                drives.extend(page.drives.into_iter().map(Drive::from));
+                match page.next_page_token {
+                    Some(next) => page_token = Some(next),
+                    None => break,
+                }
+            }
+            Ok(drives)
+        }
+        DriveProvider::MSGraph => {
+            let url = format!("{host}/v1.0/me/drives", host = drive_server);
+            let response = send(
+                http,
+                access_token,
+                request(http, Method::GET, url, access_token),
+            )
+            .await?;
+            let mut drives: Vec<Drive> = parse::<RawDrivesMSGraph>(response)
+                .await?
+                .value
+                .into_iter()
+                .map(|drive| drive.into_drive(None))
+                .collect();
+
+            // personal accounts do not host SharePoint sites
+            let url = format!(
+                "{host}/v1.0/sites?search=*&$top={top}",
+                host = drive_server,
+                top = MAX_SITES
+            );
+            let sites = match send(
+                http,
+                access_token,
+                request(http, Method::GET, url, access_token),
+            )
+            .await
+            {
+                Ok(response) => parse::<RawSitesMSGraph>(response).await?.value,
+                Err(AuthError::Unauthorized(message)) => {
+                    return Err(AuthError::Unauthorized(message))
+                }
+                Err(err) => {
+                    tracing::debug!("\n🗂️ SharePoint sites unavailable: {}\n", err);
+                    Vec::new()
+                }
+            };
+            let libraries =
+                futures::future::try_join_all(sites.into_iter().map(|site| async move {
+                    let url = format!(
+                        "{host}/v1.0/sites/{id}/drives",
+                        host = drive_server,
+                        id = site.id
+                    );
+                    let response = send(
+                        http,
+                        access_token,
+                        request(http, Method::GET, url, access_token),
+                    )
+                    .await?;
+                    Ok::<_, AuthError>(
+                        parse::<RawDrivesMSGraph>(response)
+                            .await?
+                            .value
+                            .into_iter()
+                            .map(|drive| drive.into_drive(Some(site.display_name.clone())))
+                            .collect::<Vec<Drive>>(),
+                    )
+                }))
+                .await?;
+            drives.extend(libraries.into_iter().flatten());
+            Ok(drives)
+        }
+        DriveProvider::DropBox => {
+            let url = format!("{host}/2/users/get_current_account", host = drive_server);
+            let response = send_read_only(
+                http,
+                access_token,
+                request(http, Method::POST, url, access_token),
+            )
+            .await?;
+            let mut drives: Vec<Drive> = parse::<RawAccountDropBox>(response).await?.into();
+
+            let url = format!("{host}/2/sharing/list_folders", host = drive_server);
+            let response = send_read_only(
+                http,
+                access_token,
+                request(http, Method::POST, url, access_token)
+                    .header(CONTENT_TYPE, "application/json")
+                    .json(&json!({ "limit": PAGE_SIZE })),
+            )
+            .await?;
+            drives.extend(
+                parse::<RawSharedFoldersDropBox>(response)
+                    .await?
+                    .entries
+                    .into_iter()
+                    .map(Drive::from),
+            );
+            Ok(drives)
+        }
+        _ => Err(AuthError::InternalError("Unsupported drive type".into())),
+    }
+}
+///
+/// 🗂️ List the root of a drive other than the personal drive
+///
+/// * Google: GET files with driveId and corpora=drive
+/// * MSGraph: GET drives/{drive_id}/root/children
+/// * DropBox: POST files/list_folder with the drive as the path root
+///
+/// `google_clause` is added to the Google query (see FileFilter).
+///
+pub(crate) fn list_drive_request(
+    http: &HttpClient,
+    drive_provider: &DriveProvider,
+    files_request: &FilesRequest,
+    access_token: &DriveAccess,
+    drive_id: &str,
+    google_clause: Option<String>,
+) -> Result<reqwest::RequestBuilder, AuthError> {
+    let FilesRequest { drive_server, .. } = files_request;
+
+    match drive_provider {
+        DriveProvider::Google => {
+            let mut q = format!(
+                "'{}' in parents and trashed = false",
+                escape_google(drive_id)
+            );
+            if let Some(clause) = google_clause {
+                q = format!("{} and {}", q, clause);
+            }
+            let fields = format!("nextPageToken,files({})", GOOGLE_FILE_FIELDS);
+            let page_size = PAGE_SIZE.to_string();
+            let url = format!("{host}/drive/v3/files", host = drive_server);
+            Ok(request(http, Method::GET, url, access_token).query(&[
+                ("q", q.as_str()),
+                ("driveId", drive_id),
+                ("corpora", "drive"),
+                ("includeItemsFromAllDrives", "true"),
+                ("supportsAllDrives", "true"),
+                ("pageSize", page_size.as_str()),
+                ("fields", fields.as_str()),
+            ]))
+        }
+        DriveProvider::MSGraph => Ok(request(
+            http,
+            Method::GET,
+            format!(
+                "{host}/v1.0/{drive}/root/children?$top={top}",
+                host = drive_server,
+                drive = ms_graph_drive(Some(drive_id)),
+                top = PAGE_SIZE
+            ),
+            access_token,
+        )),
+        DriveProvider::DropBox => Ok(with_drop_box_root(
+            request(
+                http,
+                Method::POST,
+                format!("{host}/2/files/list_folder", host = drive_server),
+                access_token,
+            ),
+            Some(drive_id),
+        )
+        .header(CONTENT_TYPE, "application/json")
+        .json(&json!({ "path": "", "limit": PAGE_SIZE }))),
+        _ => Err(AuthError::InternalError("Unsupported drive type".into())),
+    }
+}
+///
+/// 📑 The page that follows a listing
+///
+#[derive(Debug, Default, Deserialize)]
+pub struct PageParams {
+    /// the next_page value from the previous response
+    pub page: Option<String>,
+}
+///
+/// 📁 The folder listed; the root when not specified
+///
+#[derive(Debug, Default, Deserialize)]
+pub struct FolderParams {
+    /// e.g., /reports (sent as the `{path}` of the configured body)
+    pub path: Option<String>,
+}
+impl FolderParams {
+    /// The path as DropBox expects it: "" for the root, no trailing slash
+    pub fn path(&self) -> &str {
+        self.path
+            .as_deref()
+            .map_or("", |path| path.trim_end_matches('/'))
+    }
+}
+///
+/// 📑 Request the page that follows a listing
+///
+/// * Google: the listing request with the pageToken
+/// * MSGraph: GET the nextLink
+/// * DropBox: POST files/list_folder/continue
+///
+pub(crate) fn with_page(
+    http: &HttpClient,
+    drive_provider: &DriveProvider,
+    files_request: &FilesRequest,
+    access_token: &DriveAccess,
+    drive_id: Option<&str>,
+    listing: reqwest::RequestBuilder,
+    page: &str,
+) -> Result<reqwest::RequestBuilder, AuthError> {
+    let FilesRequest { drive_server, .. } = files_request;
+
+    match drive_provider {
+        DriveProvider::Google => Ok(listing.query(&[("pageToken", page)])),
+        // the nextLink is a complete url; only follow it to the drive server
+        DriveProvider::MSGraph if hosted_by(drive_server, page) => {
+            Ok(request(http, Method::GET, page, access_token))
+        }
+        DriveProvider::MSGraph => Err(AuthError::InvalidUrl(
+            "Listing page is not hosted by the drive server".into(),
+        )),
+        DriveProvider::DropBox => Ok(with_drop_box_root(
+            request(
+                http,
+                Method::POST,
+                format!("{host}/2/files/list_folder/continue", host = drive_server),
+                access_token,
+            ),
+            drive_id,
+        )
+        .header(CONTENT_TYPE, "application/json")
+        .json(&json!({ "cursor": page }))),
+        _ => Err(AuthError::InternalError("Unsupported drive type".into())),
+    }
+}
+///
+/// 🔐 The link (e.g., a nextLink sent back by the client) is hosted by the
+/// drive server: same scheme, host and port, so the access token is not
+/// sent elsewhere
+///
+fn hosted_by(drive_server: &str, link: &str) -> bool {
+    match (Url::parse(drive_server), Url::parse(link)) {
+        (Ok(server), Ok(link)) => {
+            server.scheme() == link.scheme()
+                && server.host_str().is_some()
+                && server.host_str() == link.host_str()
+                && server.port_or_known_default() == link.port_or_known_default()
+        }
+        _ => false,
+    }
+}
+///
+/// The MSGraph path to a drive; the personal drive when None
+///
+fn ms_graph_drive(drive_id: Option<&str>) -> String {
+    match drive_id {
+        Some(drive_id) => format!("drives/{}", utf8_percent_encode(drive_id, NON_ALPHANUMERIC)),
+        None => "me/drive".to_string(),
+    }
+}
+///
+/// DropBox addresses a team space or shared folder using its namespace id
+///
+fn with_drop_box_root(
+    request: reqwest::RequestBuilder,
+    drive_id: Option<&str>,
+) -> reqwest::RequestBuilder {
+    match drive_id {
+        Some(namespace_id) => request.header(
+            "Dropbox-API-Path-Root",
+            json!({ ".tag": "namespace_id", "namespace_id": namespace_id }).to_string(),
+        ),
+        None => request,
+    }
+}
+/* -------------------------------------------------------------------------------- */
+///
+/// 🔎 Search parameters common to all of the drive providers
+///
+#[derive(Debug, Deserialize)]
+pub struct SearchParams {
+    /// text to match in the file name (and content where supported)
+    pub q: String,
+    /// restrict the results to a mime type
+    pub mime: Option<String>,
+    /// the next_page value from the previous response
+    pub page: Option<String>,
+}
+///
+/// 🔎 Search for files by name
+///
+/// * Google: GET files?q=name contains '...'
+/// * MSGraph: GET me/drive/root/search(q='...'); the mime type is applied to the results
+/// * DropBox: POST files/search_v2, files/search/continue_v2
+///
+pub(crate) async fn search(
+    http: &HttpClient,
+    drive_provider: &DriveProvider,
+    files_request: &FilesRequest,
+    access_token: &DriveAccess,
+    params: &SearchParams,
+    filter: Option<&FileFilter>,
+) -> Result<FilesBuilder, AuthError> {
+    let FilesRequest { drive_server, .. } = files_request;
+
+    let files_builder = match drive_provider {
+        DriveProvider::Google => {
+            let mut q = format!(
+                "name contains '{}' and trashed = false",
+                escape_google(&params.q)
+            );
+            if let Some(mime) = &params.mime {
+                q = format!("{} and mimeType = '{}'", q, escape_google(mime));
+            }
+            if let Some(filter) = filter {
+                q = format!("{} and {}", q, filter.google_clause());
+            }
+            let page_size = PAGE_SIZE.to_string();
+            let fields = format!("nextPageToken,files({})", GOOGLE_FILE_FIELDS);
+            let mut query = vec![
+                ("q", q.as_str()),
+                ("pageSize", page_size.as_str()),
+                ("fields", fields.as_str()),
+            ];
+            if let Some(page) = &params.page {
+                query.push(("pageToken", page.as_str()));
+            }
+            let url = format!("{host}/drive/v3/files", host = drive_server);
+            let response = send(
+                http,
+                access_token,
+                request(http, Method::GET, url, access_token).query(&query),
+            )
+            .await?;
+            google(parse::<RawFiles<RawFileGoogle>>(response).await?)
+        }
+        DriveProvider::MSGraph => {
+            let url = match &params.page {
+                // the nextLink is a complete url; only follow it to the drive server
+                Some(next_link) if hosted_by(drive_server, next_link) => next_link.clone(),
+                Some(_) => {
+                    return Err(AuthError::InvalidUrl(
+                        "Search page is not hosted by the drive server".into(),
+                    ))
+                }
+                None => format!(
+                    "{host}/v1.0/me/drive/root/search(q='{q}')?$top={top}",
+                    host = drive_server,
+                    q = utf8_percent_encode(&params.q.replace('\'', "''"), NON_ALPHANUMERIC),
+                    top = PAGE_SIZE
+                ),
+            };
+            let response = send(
+                http,
+                access_token,
+                request(http, Method::GET, url, access_token),
+            )
+            .await?;
+            ms_graph(parse::<RawFiles<RawFileMSGraph>>(response).await?)
+        }
+        DriveProvider::DropBox => {
+            let request = match &params.page {
+                Some(cursor) => request(
+                    http,
+                    Method::POST,
+                    format!("{host}/2/files/search/continue_v2", host = drive_server),
+                    access_token,
+                )
+                .json(&json!({ "cursor": cursor })),
+                None => {
+                    let mut options = json!({ "max_results": PAGE_SIZE });
+                    if let Some(extension) = params.mime.as_deref().and_then(extension_for_mime) {
+                        options["file_extensions"] = json!([extension]);
+                    } else if let Some(filter) = filter {
+                        options["file_extensions"] = json!(filter.extensions);
+                    }
+                    request(
+                        http,
+                        Method::POST,
+                        format!("{host}/2/files/search_v2", host = drive_server),
+                        access_token,
+                    )
+                    .json(&json!({ "query": params.q, "options": options }))
+                }
+            };
+            let response = send_read_only(
+                http,
+                access_token,
+                request.header(CONTENT_TYPE, "application/json"),
+            )
+            .await?;
+            drop_box(parse::<RawSearchDropBox>(response).await?.into())
+        }
+        _ => return Err(AuthError::InternalError("Unsupported drive type".into())),
+    };
+
+    Ok(files_builder)
+}
+///
+/// Add a clause to the `q` parameter of a Google files request
+///
+pub(crate) fn with_google_clause(url: &str, clause: &str) -> Result<String, AuthError> {
+    let mut url = Url::parse(url).map_err(|err| AuthError::InvalidUrl(err.to_string().into()))?;
+    let mut pairs: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+    match pairs.iter_mut().find(|(key, _)| key == "q") {
+        Some((_, q)) => *q = format!("({}) and {}", q, clause),
+        None => pairs.push(("q".to_string(), clause.to_string())),
+    }
+    url.query_pairs_mut().clear().extend_pairs(pairs);
+    Ok(url.to_string())
+}
+///
+/// Escape a literal used in a Google query string
+///
+fn escape_google(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\'', "\\'")
+}
+///
+/// DropBox filters search results by extension rather than mime type
+///
+pub(crate) fn extension_for_mime(mime: &str) -> Option<&'static str> {
+    match mime {
+        "text/csv" => Some("csv"),
+        "text/tab-separated-values" => Some("tsv"),
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet" => Some("xlsx"),
+        "application/vnd.ms-excel" => Some("xls"),
+        "application/json" => Some("json"),
+        "application/pdf" => Some("pdf"),
+        "text/plain" => Some("txt"),
+        _ => None,
+    }
+}
+/* -------------------------------------------------------------------------------- */
+///
+/// 🔄 The cursor from which to track the changes that follow (now)
+///
+/// * Google: GET changes/startPageToken
+/// * MSGraph: GET me/drive/root/delta?token=latest
+/// * DropBox: POST files/list_folder/get_latest_cursor
+///
+pub(crate) async fn start_cursor(
+    http: &HttpClient,
+    drive_provider: &DriveProvider,
+    files_request: &FilesRequest,
+    access_token: &DriveAccess,
+) -> Result<String, AuthError> {
+    let FilesRequest { drive_server, .. } = files_request;
+
+    match drive_provider {
+        DriveProvider::Google => {
+            #[derive(Deserialize)]
+            struct StartPageToken {
+                #[serde(rename = "startPageToken")]
+                start_page_token: String,
+            }
+            let url = format!(
+                "{host}/drive/v3/changes/startPageToken",
+                host = drive_server
+            );
+            let response = send(
+                http,
+                access_token,
+                request(http, Method::GET, url, access_token),
+            )
+            .await?;
+            Ok(parse::<StartPageToken>(response).await?.start_page_token)
+        }
+        DriveProvider::MSGraph => {
+            let url = format!(
+                "{host}/v1.0/me/drive/root/delta?token=latest",
+                host = drive_server
+            );
+            let response = send(
+                http,
+                access_token,
+                request(http, Method::GET, url, access_token),
+            )
+            .await?;
+            parse::<RawDeltaMSGraph>(response)
+                .await?
+                .delta_link
+                .ok_or_else(|| AuthError::MissingProperty("MSGraph deltaLink".into()))
+        }
+        DriveProvider::DropBox => {
+            #[derive(Deserialize)]
+            struct LatestCursor {
+                cursor: String,
+            }
+            let url = format!(
+                "{host}/2/files/list_folder/get_latest_cursor",
+                host = drive_server
+            );
+            let response = send_read_only(
+                http,
+                access_token,
+                request(http, Method::POST, url, access_token)
+                    .header(CONTENT_TYPE, "application/json")
+                    .json(&json!({ "path": "", "recursive": true, "include_deleted": true })),
+            )
+            .await?;
+            Ok(parse::<LatestCursor>(response).await?.cursor)
+        }
+        _ => Err(AuthError::InternalError("Unsupported drive type".into())),
+    }
+}
+///
+/// 🔄 The changes that follow the cursor
+///
+/// Pages are retrieved until the provider reports the new cursor, or
+/// MAX_CHANGE_PAGES is reached (has_more).
+///
+/// Returns the changes, the cursor to use next time, and has_more.
+///
+pub(crate) async fn changes(
+    http: &HttpClient,
+    drive_provider: &DriveProvider,
+    files_request: &FilesRequest,
+    access_token: &DriveAccess,
+    cursor: &str,
+) -> Result<(Vec<Change>, String, bool), AuthError> {
+    let FilesRequest { drive_server, .. } = files_request;
+    let mut changes = Vec::new();
+    let mut cursor = cursor.to_string();
+
+    for _ in 0..MAX_CHANGE_PAGES {
+        match drive_provider {
+            DriveProvider::Google => {
+                let fields = format!(
+                    "nextPageToken,newStartPageToken,changes(removed,fileId,file({},trashed))",
+                    GOOGLE_FILE_FIELDS
+                );
+                let url = format!("{host}/drive/v3/changes", host = drive_server);
+                let page_size = PAGE_SIZE.to_string();
+                let query = [
+                    ("pageToken", cursor.as_str()),
+                    ("pageSize", page_size.as_str()),
+                    ("fields", fields.as_str()),
+                ];
+                let response = send(
+                    http,
+                    access_token,
+                    request(http, Method::GET, url, access_token).query(&query),
+                )
+                .await?;
+                let page = parse::<RawChangesGoogle>(response).await?;
+                changes.extend(page.changes.into_iter().map(Change::from));
+
+                match (page.new_start_page_token, page.next_page_token) {
+                    (Some(new_start), _) => return Ok((changes, new_start, false)),
+                    (None, Some(next)) => cursor = next,
+                    (None, None) => {
+                        return Err(AuthError::MissingProperty("Google page token".into()))
+                    }
+                }
+            }
+            DriveProvider::MSGraph => {
+                // the delta and next links are complete urls
+                if !hosted_by(drive_server, &cursor) {
+                    return Err(AuthError::InvalidUrl(
+                        "Delta link is not hosted by the drive server".into(),
+                    ));
+                }
+                let response = send(
+                    http,
+                    access_token,
+                    request(http, Method::GET, &cursor, access_token),
+                )
+                .await?;
+                let page = parse::<RawDeltaMSGraph>(response).await?;
+                changes.extend(page.value.into_iter().map(Change::from));
+
+                match (page.delta_link, page.next_link) {
+                    (Some(delta), _) => return Ok((changes, delta, false)),
+                    (None, Some(next)) => cursor = next,
+                    (None, None) => {
+                        return Err(AuthError::MissingProperty("MSGraph deltaLink".into()))
+                    }
+                }
+            }
+            DriveProvider::DropBox => {
+                let url = format!("{host}/2/files/list_folder/continue", host = drive_server);
+                let response = send_read_only(
+                    http,
+                    access_token,
+                    request(http, Method::POST, url, access_token)
+                        .header(CONTENT_TYPE, "application/json")
+                        .json(&json!({ "cursor": cursor })),
+                )
+                .await?;
+                let page = parse::<RawListFolderDropBox>(response).await?;
+                changes.extend(page.entries.into_iter().map(Change::from));
+                cursor = page.cursor;
+
+                if !page.has_more {
+                    return Ok((changes, cursor, false));
+                }
+            }
+            _ => return Err(AuthError::InternalError("Unsupported drive type".into())),
+        }
+    }
+    Ok((changes, cursor, true))
+}
+/* -------------------------------------------------------------------------------- */
+///
+/// 📣 Register to receive change notifications (webhooks)
+///
+/// * Google: POST changes/watch (a channel cannot be extended; replaced on renewal)
+/// * MSGraph: POST subscriptions; PATCH subscriptions/{id} to renew
+/// * DropBox: the app-wide webhook notifies by account; POST users/get_current_account
+///
+pub(crate) async fn subscribe(
+    http: &HttpClient,
+    drive_provider: &DriveProvider,
+    files_request: &FilesRequest,
+    access_token: &DriveAccess,
+    project_id: &ProjectId,
+    previous: Option<Subscription>,
+) -> Result<Subscription, AuthError> {
+    let FilesRequest { drive_server, .. } = files_request;
+    let address = webhook_endpoint(drive_provider)?.to_string();
+
+    match drive_provider {
+        DriveProvider::Google => {
+            #[derive(Deserialize)]
+            struct Channel {
+                id: String,
+                #[serde(rename = "resourceId")]
+                resource_id: String,
+                #[serde(deserialize_with = "deserialize_number_from_string")]
+                expiration: i64,
+            }
+            let page_token =
+                start_cursor(http, drive_provider, files_request, access_token).await?;
+            let client_state = Uuid::new_v4().to_string();
+            let expiration = Utc::now() + Duration::seconds(GOOGLE_CHANNEL_TTL);
+            let url = format!("{host}/drive/v3/changes/watch", host = drive_server);
+            let response = send(
+                http,
+                access_token,
+                request(http, Method::POST, url, access_token)
+                    .query(&[("pageToken", page_token)])
+                    .header(CONTENT_TYPE, "application/json")
+                    .json(&json!({
+                        "id": Uuid::new_v4().to_string(),
+                        "type": "web_hook",
+                        "address": address,
+                        "token": client_state,
+                        "expiration": expiration.timestamp_millis(),
+                    })),
+            )
+            .await?;
+            let channel = parse::<Channel>(response).await?;
+
+            // best effort; the previous channel expires regardless
+            if let Some(Subscription {
+                id,
+                resource_id: Some(resource_id),
+                ..
+            }) = previous
+            {
+                let url = format!("{host}/drive/v3/channels/stop", host = drive_server);
+                let stopped = send(
+                    http,
+                    access_token,
+                    request(http, Method::POST, url, access_token)
+                        .header(CONTENT_TYPE, "application/json")
+                        .json(&json!({ "id": id, "resourceId": resource_id })),
+                )
+                .await;
+                tracing::debug!("\n📣 stopped previous channel: {}\n", stopped.is_ok());
+            }
+
+            Ok(Subscription {
+                id: channel.id,
+                project_id: project_id.clone(),
+                drive_provider: drive_provider.clone(),
+                client_state,
+                resource_id: Some(channel.resource_id),
+                expires_at: Some(Utc.timestamp_millis(channel.expiration)),
+            })
+        }
+        DriveProvider::MSGraph => {
+            #[derive(Deserialize)]
+            struct GraphSubscription {
+                id: String,
+                #[serde(rename = "expirationDateTime")]
+                expiration_date_time: DateTime<Utc>,
+            }
+            let expiration = Utc::now() + Duration::minutes(MSGRAPH_SUBSCRIPTION_TTL);
+            let (request, client_state) = match previous {
+                Some(previous) => (
+                    request(
+                        http,
+                        Method::PATCH,
+                        format!(
+                            "{host}/v1.0/subscriptions/{id}",
+                            host = drive_server,
+                            id = previous.id
+                        ),
+                        access_token,
+                    )
+                    .json(&json!({ "expirationDateTime": expiration })),
+                    previous.client_state,
+                ),
+                None => {
+                    let client_state = Uuid::new_v4().to_string();
+                    (
+                        request(
+                            http,
+                            Method::POST,
+                            format!("{host}/v1.0/subscriptions", host = drive_server),
+                            access_token,
+                        )
+                        .json(&json!({
+                            "changeType": "updated",
+                            "notificationUrl": address,
+                            "resource": "/me/drive/root",
+                            "expirationDateTime": expiration,
+                            "clientState": client_state,
+                        })),
+                        client_state,
+                    )
+                }
+            };
+            let response = send(
+                http,
+                access_token,
+                request.header(CONTENT_TYPE, "application/json"),
+            )
+            .await?;
+            let subscription = parse::<GraphSubscription>(response).await?;
+
+            Ok(Subscription {
+                id: subscription.id,
+                project_id: project_id.clone(),
+                drive_provider: drive_provider.clone(),
+                client_state,
+                resource_id: None,
+                expires_at: Some(subscription.expiration_date_time),
+            })
+        }
+        DriveProvider::DropBox => {
+            #[derive(Deserialize)]
+            struct Account {
+                account_id: String,
+            }
+            let url = format!("{host}/2/users/get_current_account", host = drive_server);
+            let response = send_read_only(
+                http,
+                access_token,
+                request(http, Method::POST, url, access_token),
+            )
+            .await?;
+            let account = parse::<Account>(response).await?;
+
+            Ok(Subscription {
+                id: account.account_id,
+                project_id: project_id.clone(),
+                drive_provider: drive_provider.clone(),
+                // notifications are signed using the app secret
+                client_state: String::new(),
+                resource_id: None,
+                expires_at: None,
+            })
+        }
+        _ => Err(AuthError::InternalError("Unsupported drive type".into())),
+    }
+}
+///
+/// Escape non-ascii characters as required by the Dropbox-API-Arg header
+///
+pub(crate) fn ascii_json(value: &serde_json::Value) -> String {
+    value
+        .to_string()
+        .chars()
+        .map(|c| {
+            if c.is_ascii() {
+                c.to_string()
+            } else {
+                let mut buf = [0; 2];
+                c.encode_utf16(&mut buf)
+                    .iter()
+                    .map(|unit| format!("\\u{:04x}", unit))
+                    .collect()
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn folders_are_sent_without_a_trailing_slash() {
+        let folder = |path: Option<&str>| FolderParams {
+            path: path.map(ToString::to_string),
+        };
+        assert_eq!("", folder(None).path());
+        assert_eq!("", folder(Some("/")).path());
+        assert_eq!("/reports", folder(Some("/reports/")).path());
+        assert_eq!("/reports/2022", folder(Some("/reports/2022")).path());
+    }
+
+    #[test]
+    fn links_are_followed_to_the_drive_server_only() {
+        let server = "https://graph.microsoft.com";
+        assert!(hosted_by(
+            server,
+            "https://graph.microsoft.com/v1.0/me/drive/root/children?$skiptoken=x"
+        ));
+        assert!(hosted_by(server, "https://graph.microsoft.com:443/v1.0"));
+        // a prefix of the host is not the host
+        assert!(!hosted_by(
+            server,
+            "https://graph.microsoft.com.evil.io/v1.0"
+        ));
+        assert!(!hosted_by(
+            server,
+            "https://graph.microsoft.com@evil.io/v1.0"
+        ));
+        assert!(!hosted_by(server, "http://graph.microsoft.com/v1.0"));
+        assert!(!hosted_by(server, "https://graph.microsoft.com:8443/v1.0"));
+        assert!(!hosted_by(server, "/v1.0/me/drive"));
+        assert!(!hosted_by("not a url", "https://graph.microsoft.com"));
+    }
+}
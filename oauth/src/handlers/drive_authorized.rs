@@ -9,9 +9,8 @@
 /// ⬜ Confirm whether using the same cookie key somehow prevents the browser
 ///    from recognizing "already done"
 ///
-use async_redis_session::RedisSessionStore;
 use axum::extract::{Extension, Path, Query, TypedHeader};
-use axum::http::header::{ACCEPT, CONTENT_TYPE, COOKIE, USER_AGENT};
+use axum::http::header::{HeaderMap, ACCEPT, CONTENT_TYPE, COOKIE, SET_COOKIE, USER_AGENT};
 use axum::response::Redirect;
 use oauth2::TokenResponse;
 use serde_json;
@@ -24,7 +23,10 @@ use crate::models::auth_return::AuthReturnValues;
 use crate::models::drive_clients::{DriveClient, DriveClients};
 use crate::models::drive_provider::DriveProvider;
 use crate::models::drive_token::Builder;
+use crate::models::http_client::HttpClient;
 use crate::models::project_id::ProjectId;
+use crate::models::refresh_tokens::{RefreshTokens, StoredToken};
+use crate::models::session_store::AuthStore;
 
 /* -------------------------------------------------------------------------- */
 ///
@@ -36,13 +38,16 @@ use crate::models::project_id::ProjectId;
 /// that signals success.  This will signal that the api can retrieve the token
 /// and expect to connect to the drive
 ///
+#[allow(clippy::extra_unused_lifetimes)]
 pub async fn handle<'a>(
     Path(drive_provider): Path<DriveProvider>,
     Query(auth_return_values): Query<AuthReturnValues>,
-    Extension(store): Extension<RedisSessionStore>,
+    Extension(store): Extension<AuthStore>,
     Extension(clients): Extension<DriveClients>,
+    Extension(http): Extension<HttpClient>,
+    Extension(tokens): Extension<RefreshTokens>,
     TypedHeader(cookies): TypedHeader<headers::Cookie>,
-) -> Result<Redirect, AuthError> {
+) -> Result<(HeaderMap, Redirect), AuthError> {
     if let Some(DriveClient { client, .. }) = clients.get(&drive_provider) {
         //
         tracing::debug!(
@@ -62,7 +67,7 @@ pub async fn handle<'a>(
         /* ------------------------------------------------------------------ */
         // ✅ get the resource token
         /* ------------------------------------------------------------------ */
-        let token_response = shared::get_token(&auth_return_values, client, pkce).await?;
+        let token_response = shared::get_token(&auth_return_values, client, pkce, &http).await?;
         tracing::debug!(
             "\n ✅ Token response:\n{} \n",
             serde_json::to_string_pretty(&token_response)?
@@ -74,6 +79,20 @@ pub async fn handle<'a>(
 
         let drive_token = builder.build(&project_id, &drive_provider);
 
+        /* ------------------------------------------------------------------ */
+        // 🔄 Keep the refresh token to renew the access token on 401
+        //    (providers may only issue it with the first consent)
+        /* ------------------------------------------------------------------ */
+        let previous = tokens
+            .get(&project_id, &drive_provider)
+            .await?
+            .map(|stored| stored.refresh_token);
+        // 🔐 the signed-in user that authorized the drive owns the token
+        let owner = shared::session_user(&cookies, &store).await?;
+        if let Some(stored) = StoredToken::new(&token_response, previous, owner) {
+            tokens.set(&project_id, &drive_provider, &stored).await?;
+        }
+
         /* ------------------------------------------------------------------ */
         // 🎉  Access Token - store for re-use
         //
@@ -99,23 +118,22 @@ pub async fn handle<'a>(
         // 🔖 The task of submitting the token to the server & redirecting the
         //    user agent could be done in parallel.
         /* ------------------------------------------------------------------------- */
-        let client = reqwest::Client::builder().build().map_err(|err| {
-            AuthError::InternalError(format!("Failed to build client: {}", err).into())
-        })?;
         let (_pkce, _csrf_state) = shared::retrieve_validators(&cookies, &store).await?;
+        #[allow(clippy::needless_borrow)]
         let session_id = cookies.get(&TNC_SESSION_COOKIE).ok_or_else(|| {
             AuthError::MissingSession(
                 format!("missing session cookie: {}", &TNC_SESSION_COOKIE).into(),
             )
         })?;
-        let _token = client
-            .post(api_url.to_string())
-            .header(USER_AGENT, "Luci Drive Authorization")
-            .header(COOKIE, format!("{}={}", TNC_SESSION_COOKIE, session_id))
-            .header(ACCEPT, "application/json")
-            .header(CONTENT_TYPE, "application/json")
-            .json(&drive_token)
-            .send()
+        let _token = http
+            .send(
+                http.post(api_url.to_string())
+                    .header(USER_AGENT, "Luci Drive Authorization")
+                    .header(COOKIE, format!("{}={}", TNC_SESSION_COOKIE, session_id))
+                    .header(ACCEPT, "application/json")
+                    .header(CONTENT_TYPE, "application/json")
+                    .json(&drive_token),
+            )
             .await
             .map_err(|err| AuthError::TncSessionResponseError(err.to_string().into()))?;
 
@@ -129,11 +147,20 @@ pub async fn handle<'a>(
                 .map_err(|err| AuthError::JsonParsingError(err.to_string().into()))?
         );
 
+        /* ------------------------------------------------------------------------- */
+        // 🔐 The session was granted access to the drive; move it to a new id
+        /* ------------------------------------------------------------------------- */
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            SET_COOKIE,
+            shared::regenerate_session(&cookies, &store).await?,
+        );
+
         let redirect_uri = set_tnc_filesystem_endpoint(Some(project_id))?;
         tracing::debug!("\n🔗 👉 tnc redirect uri:\n{}\n", &redirect_uri);
 
         // redirect - the response status should encode success (vs failing to register)
-        Ok(Redirect::to(redirect_uri))
+        Ok((headers, Redirect::to(redirect_uri)))
     } else {
         Err(AuthError::UnsupportedProvider(
             (&("Auth client not found")).into(),
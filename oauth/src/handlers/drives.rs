@@ -0,0 +1,50 @@
+use axum::extract::{Extension, Path, Query};
+use axum::Json;
+
+use crate::errors::AuthError;
+use crate::handlers::drive_api::{self, AuthDriveToken, DriveAccess, DriveCaller};
+use crate::models::drive_clients::{DriveClient, DriveClients};
+use crate::models::drive_provider::DriveProvider;
+use crate::models::drives::Drives;
+use crate::models::http_client::HttpClient;
+use crate::models::project_id::ProjectId;
+use crate::models::refresh_tokens::RefreshTokens;
+
+///
+/// 🔗 drives endpoint
+/// The drives available to the user: the personal drive, and the shared
+/// drives, SharePoint libraries or team folders. Use the `id` as the
+/// `drive_id` parameter of the routes that read from a drive.
+///
+pub(crate) async fn handle(
+    Path((drive_provider, project_id)): Path<(DriveProvider, ProjectId)>,
+    Query(token): Query<AuthDriveToken>,
+    caller: DriveCaller,
+    Extension(clients): Extension<DriveClients>,
+    Extension(http): Extension<HttpClient>,
+    Extension(tokens): Extension<RefreshTokens>,
+) -> Result<Json<Drives>, AuthError> {
+    if let Some(drive_client @ DriveClient { files_request, .. }) = clients.get(&drive_provider) {
+        let access_token = DriveAccess::new(
+            token,
+            drive_client,
+            &tokens,
+            &project_id,
+            &drive_provider,
+            &caller,
+        );
+
+        let drives =
+            drive_api::list_drives(&http, &drive_provider, files_request, &access_token).await?;
+
+        tracing::debug!("\n🎉 Drives:\n{:#?}\n", &drives);
+        Ok(Json(Drives {
+            drive_provider,
+            drives,
+        }))
+    } else {
+        Err(AuthError::UnsupportedProvider(
+            (&("Auth client not found")).into(),
+        ))
+    }
+}
@@ -0,0 +1,57 @@
+use axum::extract::{Extension, Path, Query};
+use axum::Json;
+
+use crate::errors::AuthError;
+use crate::handlers::drive_api::{self, AuthDriveToken, DriveAccess, DriveCaller};
+use crate::models::drive_clients::{DriveClient, DriveClients};
+use crate::models::drive_provider::DriveProvider;
+use crate::models::drives::DriveParams;
+use crate::models::files::File;
+use crate::models::http_client::HttpClient;
+use crate::models::project_id::ProjectId;
+use crate::models::refresh_tokens::RefreshTokens;
+
+///
+/// 🔗 files/:file_id endpoint
+/// Normalized metadata for a single file, including the provider's content
+/// hashes and revision id. Use these to determine whether a file changed
+/// since it was last imported.
+///
+/// `drive_id` reads from a shared drive (see the drives endpoint).
+///
+pub(crate) async fn handle(
+    Path((drive_provider, project_id, file_id)): Path<(DriveProvider, ProjectId, String)>,
+    Query(token): Query<AuthDriveToken>,
+    caller: DriveCaller,
+    Query(drive): Query<DriveParams>,
+    Extension(clients): Extension<DriveClients>,
+    Extension(http): Extension<HttpClient>,
+    Extension(tokens): Extension<RefreshTokens>,
+) -> Result<Json<File>, AuthError> {
+    if let Some(drive_client @ DriveClient { files_request, .. }) = clients.get(&drive_provider) {
+        let access_token = DriveAccess::new(
+            token,
+            drive_client,
+            &tokens,
+            &project_id,
+            &drive_provider,
+            &caller,
+        );
+
+        let file = drive_api::get_file(
+            &http,
+            &drive_provider,
+            files_request,
+            &access_token,
+            &file_id,
+            drive.shared(),
+        )
+        .await?;
+
+        Ok(Json(file))
+    } else {
+        Err(AuthError::UnsupportedProvider(
+            (&("Auth client not found")).into(),
+        ))
+    }
+}
@@ -1,49 +1,75 @@
 use axum::extract::{Extension, Path, Query, TypedHeader};
-use axum::http::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE};
-use axum::Json;
+use axum::http::header::{CONTENT_TYPE, ETAG};
+use axum::http::StatusCode;
+use axum::response::{Headers, IntoResponse, Response};
 use core::str::FromStr;
-use http::Version;
-use oauth2::AccessToken;
-use serde::Deserialize;
+use headers::{CacheControl, ETag, IfNoneMatch};
 // use serde::{Deserialize, Serialize};
 
 use crate::errors::AuthError;
+use crate::handlers::drive_api::{
+    self, AuthDriveToken, DriveAccess, DriveCaller, FolderParams, PageParams,
+};
+use crate::models::body_template::{BodyTemplate, BodyVars};
 use crate::models::drive_clients;
 use crate::models::drive_clients::{DriveClient, DriveClients};
 use crate::models::drive_provider::DriveProvider;
+use crate::models::drives::DriveParams;
+use crate::models::file_filter::FilterParams;
 use crate::models::files::{
-    drop_box, google, ms_graph, Files, FilesBuilder, RawFileDropBox, RawFileGoogle, RawFileMSGraph,
+    drop_box, google, ms_graph, FilesBuilder, RawFileDropBox, RawFileGoogle, RawFileMSGraph,
     RawFiles,
 };
+use crate::models::http_client::HttpClient;
+use crate::models::listing_cache::{CachedListing, ListingCache, ListingKey};
 use crate::models::project_id::ProjectId;
+use crate::models::refresh_tokens::RefreshTokens;
 
-#[derive(Debug, Deserialize)]
-pub struct AuthDriveToken {
-    access_token: String,
-}
-impl From<AuthDriveToken> for AccessToken {
-    fn from(AuthDriveToken { access_token }: AuthDriveToken) -> AccessToken {
-        AccessToken::new(access_token)
-    }
-}
 ///
 /// 🔗 filesystem endpoint
 /// Use the auth code to retrieve the token.  This is a trusted, machine to machine exchange.
 /// Then go ahead and retrieve the resource (user email)
 ///
-pub(crate) async fn handle<'a>(
-    Path((drive_provider, _project_id)): Path<(DriveProvider, ProjectId)>,
+/// `drive_id` lists a shared drive, SharePoint library or team folder (see
+/// the drives endpoint); the user's personal drive otherwise.
+///
+/// `only=tabular` limits the listing to folders and files that can be joined.
+///
+/// `page` requests the page that follows (the `next_page` of the listing).
+///
+/// The configured `json_body_ls` is sent with the listing request (see
+/// BodyTemplate): `path` lists a folder (e.g., `path=/reports`), and the
+/// page cursor is sent in the body when it includes `{cursor}` (DropBox
+/// requests the pages that follow with list_folder/continue).
+///
+/// Listings are cached for the token that fetched them (see ListingCache)
+/// and tagged with an ETag; send `If-None-Match` to receive 304 when
+/// unchanged, `Cache-Control: no-cache` to bypass the cache.
+///
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn handle(
+    Path((drive_provider, project_id)): Path<(DriveProvider, ProjectId)>,
     Query(token): Query<AuthDriveToken>,
+    caller: DriveCaller,
+    Query(filter): Query<FilterParams>,
+    Query(drive): Query<DriveParams>,
+    Query(page): Query<PageParams>,
+    Query(folder): Query<FolderParams>,
     TypedHeader(_cookies): TypedHeader<headers::Cookie>,
+    cache_control: Option<TypedHeader<CacheControl>>,
+    if_none_match: Option<TypedHeader<IfNoneMatch>>,
     Extension(clients): Extension<DriveClients>,
-) -> Result<Json<Files>, AuthError> {
-    if let Some(DriveClient { files_request, .. }) = clients.get(&drive_provider) {
+    Extension(http): Extension<HttpClient>,
+    Extension(tokens): Extension<RefreshTokens>,
+    Extension(cache): Extension<ListingCache>,
+) -> Result<Response, AuthError> {
+    if let Some(drive_client @ DriveClient { files_request, .. }) = clients.get(&drive_provider) {
         let drive_clients::FilesRequest {
             method,
             drive_server,
             endpoint,
             query_ls,
-            // json_body_ls,
+            json_body_ls,
             ..
         } = files_request;
         /* ------------------------------------------------------------------------- */
@@ -69,105 +95,160 @@ pub(crate) async fn handle<'a>(
             query = &query_ls
         );
 
-        /*
-        // dropbox WIP
-        let json = r#"
-            {
-               "path": "",
-               "include_non_downloadable_files": false
+        // 🔐 cached for the token that fetched the listing
+        let token_digest = token.digest();
+        let key = ListingKey {
+            project_id: &project_id,
+            drive_provider: &drive_provider,
+            drive: drive.shared(),
+            token: &token_digest,
+            path: folder.path(),
+            cursor: page.page.as_deref(),
+            only: filter.only.as_deref(),
+        };
+        let no_cache = cache_control.map_or(false, |TypedHeader(value)| value.no_cache());
+        if !no_cache {
+            if let Some(listing) = cache.get(&key).await? {
+                tracing::debug!("\n🗃️ cached listing: {}\n", &listing.etag);
+                return respond(listing, if_none_match);
             }
-        "#;
-
-        #[derive(Deserialize, Serialize)]
-        struct ReqBody {
-            path: String,
-            include_non_downloadable_files: bool,
         }
-        let req_body = serde_json::from_str::<ReqBody>(json)
-            .map_err(|err| AuthError::JsonParsingError(err.into()))?;
-        */
 
-        let access_token: AccessToken = token.into();
-
-        let client = reqwest::Client::new();
-        let request = client
-            .request(
-                http::Method::from_str(method).unwrap(),
+        let access_token = DriveAccess::new(
+            token,
+            drive_client,
+            &tokens,
+            &project_id,
+            &drive_provider,
+            &caller,
+        );
+        let filter = filter.filter()?;
+        // the folder is sent in the configured body
+        if folder.path.is_some() && (drive.shared().is_some() || json_body_ls.is_none()) {
+            return Err(AuthError::MissingParameter(
                 format!(
+                    "{}: path requires a configured json_body_ls",
+                    &drive_provider
+                )
+                .into(),
+            ));
+        }
+        // DropBox: the cursor replaces the list_folder body (list_folder/continue)
+        let body_pages = drive.shared().is_none()
+            && drive_provider != DriveProvider::DropBox
+            && json_body_ls
+                .as_ref()
+                .map_or(false, BodyTemplate::has_cursor);
+
+        let request = match drive.shared() {
+            Some(drive_id) => drive_api::list_drive_request(
+                &http,
+                &drive_provider,
+                files_request,
+                &access_token,
+                drive_id,
+                filter.as_ref().map(|filter| filter.google_clause()),
+            )?,
+            None => {
+                let mut url = format!(
                     "{host}{endpoint}{query}",
                     host = &drive_server,
                     endpoint = &endpoint,
                     query = &query_ls
-                ),
-            )
-            .header(AUTHORIZATION, format!("Bearer {}", access_token.secret()))
-            .header(CONTENT_TYPE, "application/json")
-            .header(ACCEPT, "application/json")
-            .version(Version::HTTP_11);
-        // .json(&req_body);
-        tracing::debug!("\n📬 request:\n{:#?}\n", &request);
+                );
+                // Google applies the filter; the others filter the listing
+                if let (DriveProvider::Google, Some(filter)) = (&drive_provider, &filter) {
+                    url = drive_api::with_google_clause(&url, &filter.google_clause())?;
+                }
+                let request = drive_api::request(&http, method.clone(), url, &access_token);
+                match json_body_ls {
+                    Some(template) => request.json(&template.render(&BodyVars {
+                        path: folder.path(),
+                        cursor: page.page.as_deref().filter(|_| body_pages),
+                        limit: drive_api::PAGE_SIZE,
+                    })),
+                    None => request.header(CONTENT_TYPE, "application/json"),
+                }
+            }
+        };
+        let request = match &page.page {
+            Some(page) if !body_pages => drive_api::with_page(
+                &http,
+                &drive_provider,
+                files_request,
+                &access_token,
+                drive.shared(),
+                request,
+                page,
+            )?,
+            _ => request,
+        };
 
-        let response = request
-            .send() // convert Request to a Future
-            .await
-            .map_err(|err| AuthError::InvalidResponse(err.into()))?;
-        tracing::debug!("\n📥 response:\n{:#?}\n", &response);
+        // a listing changes nothing (DropBox: POST list_folder)
+        let response = drive_api::send_read_only(&http, &access_token, request).await?;
         /* ------------------------------------------------------------------------- */
         // Extract the data from the response body
         /* ------------------------------------------------------------------------- */
         // builder initialized with parsed RawFiles<T>
-        let files_builder: FilesBuilder = match response.status() {
-            reqwest::StatusCode::OK => {
-                let files_builder = match drive_provider {
-                    DriveProvider::Google => google(
-                        response
-                            .json::<RawFiles<RawFileGoogle>>()
-                            .await
-                            .map_err(|err| {
-                                let message = format!("Unexpected drive data: {}", err);
-                                AuthError::JsonParsingError(message.into())
-                            })?,
-                    ),
-                    DriveProvider::MSGraph => {
-                        ms_graph(response.json::<RawFiles<RawFileMSGraph>>().await.map_err(
-                            |err| {
-                                let message = format!("Unexpected drive data: {}", err);
-                                AuthError::JsonParsingError(message.into())
-                            },
-                        )?)
-                    }
-
-                    DriveProvider::DropBox => {
-                        drop_box(response.json::<RawFiles<RawFileDropBox>>().await.map_err(
-                            |err| {
-                                let message = format!("Unexpected drive data: {}", err);
-                                AuthError::JsonParsingError(message.into())
-                            },
-                        )?)
-                    }
-                    _ => return Err(AuthError::InternalError("Unsupported drive type".into())),
-                };
-                Ok(files_builder)
+        let files_builder: FilesBuilder = match drive_provider {
+            DriveProvider::Google => {
+                google(drive_api::parse::<RawFiles<RawFileGoogle>>(response).await?)
+            }
+            DriveProvider::MSGraph => {
+                ms_graph(drive_api::parse::<RawFiles<RawFileMSGraph>>(response).await?)
             }
-            reqwest::StatusCode::UNAUTHORIZED => {
-                // redirect to get a new token
-                let message = format!("Unauthorized drive access");
-                Err(AuthError::Unauthorized(message.into()))
+            DriveProvider::DropBox => {
+                drop_box(drive_api::parse::<RawFiles<RawFileDropBox>>(response).await?)
             }
-            // where dropbox fails
-            err => Err(AuthError::InternalError(err.to_string().into())),
-        }?
-        .set_path("root".to_string())
-        .set_drive_id("test_drive".to_string());
+            _ => return Err(AuthError::InternalError("Unsupported drive type".into())),
+        }
+        .set_path(folder.path.clone().unwrap_or_else(|| "/".to_string()));
+
+        let files_builder = match &drive.drive_id {
+            Some(drive_id) => files_builder.set_drive_id(drive_id.clone()),
+            None => files_builder,
+        };
 
-        let files = files_builder.build();
+        let mut files = files_builder.build();
+        if let Some(filter) = filter {
+            files.retain(|file| filter.is_match(file));
+        }
 
         // pretty print
         tracing::debug!("\n🎉 Files:\n{:#?}\n", &files);
-        Ok(Json(files))
+
+        let listing = CachedListing::new(serde_json::to_string(&files)?);
+        cache.set(&key, &listing).await?;
+        respond(listing, if_none_match)
     } else {
         Err(AuthError::UnsupportedProvider(
             (&("Auth client not found")).into(),
         ))
     }
 }
+///
+/// 304 when the client has the current listing
+///
+fn respond(
+    listing: CachedListing,
+    if_none_match: Option<TypedHeader<IfNoneMatch>>,
+) -> Result<Response, AuthError> {
+    let etag = ETag::from_str(&listing.etag)
+        .map_err(|_| AuthError::InternalError("Invalid listing etag".into()))?;
+
+    match if_none_match {
+        Some(TypedHeader(if_none_match)) if !if_none_match.precondition_passes(&etag) => Ok((
+            StatusCode::NOT_MODIFIED,
+            Headers(vec![(ETAG, listing.etag)]),
+        )
+            .into_response()),
+        _ => Ok((
+            Headers(vec![
+                (ETAG, listing.etag),
+                (CONTENT_TYPE, "application/json".to_string()),
+            ]),
+            listing.body,
+        )
+            .into_response()),
+    }
+}
@@ -1,4 +1,3 @@
-use async_redis_session::RedisSessionStore;
 use axum::extract::{Extension, Path, Query, TypedHeader};
 use axum::http::header::{HeaderMap, HeaderValue, CONTENT_TYPE, SET_COOKIE, USER_AGENT};
 use axum::response::Redirect;
@@ -10,11 +9,15 @@ use oauth2::TokenResponse;
 use crate::config::{tnc_app_endpoint, tnc_register_endpoint};
 use crate::errors::AuthError;
 use crate::handlers::shared;
+use crate::middleware::user_agent::ClientUserAgent;
 use crate::models::auth_return::AuthReturnValues;
+use crate::models::http_client::HttpClient;
 use crate::models::oauth_clients::{OauthClient, OauthClients};
 use crate::models::oauth_provider::OauthProvider;
+use crate::models::session_store::AuthStore;
 use crate::models::user;
 use crate::models::user_registration::UserRegistration;
+use crate::models::user_sessions;
 
 ///
 /// This is the second phase of the Authorization Code Grant w/pkce flow
@@ -34,9 +37,11 @@ use crate::models::user_registration::UserRegistration;
 pub async fn handle(
     Path(oauth_provider): Path<OauthProvider>,
     Query(auth_return_values): Query<AuthReturnValues>,
-    Extension(store): Extension<RedisSessionStore>,
+    Extension(store): Extension<AuthStore>,
     Extension(clients): Extension<OauthClients>,
+    Extension(http): Extension<HttpClient>,
     TypedHeader(cookies): TypedHeader<headers::Cookie>,
+    Extension(ClientUserAgent(user_agent)): Extension<ClientUserAgent>,
 ) -> Result<(HeaderMap, Redirect), AuthError> {
     if let Some(OauthClient {
         client,
@@ -59,7 +64,7 @@ pub async fn handle(
         /* ------------------------------------------------------------------------- */
         // ✅ get the resource token
         /* ------------------------------------------------------------------------- */
-        let token_response = shared::get_token(&auth_return_values, client, pkce).await?;
+        let token_response = shared::get_token(&auth_return_values, client, pkce, &http).await?;
 
         tracing::debug!(
             "\n✅ Token from Auth Provider (opaque):\n{:#?}\n",
@@ -69,13 +74,13 @@ pub async fn handle(
         /* ------------------------------------------------------------------------- */
         // 👉 Fetch user data from the identity provider (protected resource)
         /* ------------------------------------------------------------------------- */
-        let client = reqwest::Client::new();
-        let user_data = client
-            .get(identity_server)
-            .bearer_auth(token_response.access_token().secret())
-            .header(USER_AGENT, "Web App login")
-            .header(CONTENT_TYPE, "application/json")
-            .send() // convert Request to a Future
+        let user_data = http
+            .send(
+                http.get(identity_server)
+                    .bearer_auth(token_response.access_token().secret())
+                    .header(USER_AGENT, "Web App login")
+                    .header(CONTENT_TYPE, "application/json"),
+            )
             .await
             .map_err(|err| {
                 let message = format!("Identity response:\n{:?}", err);
@@ -152,13 +157,14 @@ pub async fn handle(
 
         tracing::debug!("\n🔗 tnc session_url:\n{}\n", &session_url);
 
-        let session = client
-            .post(session_url.to_string())
-            .header(USER_AGENT, "Luci Web Authorization")
-            .header(ACCEPT, "application/json")
-            .header(CONTENT_TYPE, "application/json")
-            .json(&user_registration)
-            .send()
+        let session = http
+            .send(
+                http.post(session_url.to_string())
+                    .header(USER_AGENT, "Luci Web Authorization")
+                    .header(ACCEPT, "application/json")
+                    .header(CONTENT_TYPE, "application/json")
+                    .json(&user_registration),
+            )
             .await
             .map_err(|err| AuthError::TncSessionResponseError((&err).into()))?;
 
@@ -177,12 +183,25 @@ pub async fn handle(
             &session_cookie
         );
 
+        /* ------------------------------------------------------------------------- */
+        // 🔗 Bind the auth session to the user (sign out everywhere); the
+        //    authenticated session moves to a new id
+        /* ------------------------------------------------------------------------- */
+        let auth_cookie = shared::bind_user(
+            &cookies,
+            &store,
+            &user_sessions::user_id(&user_registration),
+            user_agent.as_deref(),
+        )
+        .await?;
+
         let redirect_uri = tnc_app_endpoint()?;
         tracing::debug!("\n🔗 👉 redirect uri:\n{}\n", &redirect_uri);
 
         // update the headers with the Set-Cookie to complete the forwarding task
         let mut headers = HeaderMap::new();
         headers.insert(SET_COOKIE, session_cookie);
+        headers.append(SET_COOKIE, auth_cookie);
 
         Ok((headers, Redirect::to(redirect_uri)))
     } else {
@@ -1,12 +1,12 @@
-use async_redis_session::RedisSessionStore;
 use async_session::SessionStore;
 use axum::extract::{Extension, TypedHeader};
 use axum::response::{IntoResponse, Redirect};
 
 use crate::constants::AUTH_SESSION_COOKIE;
+use crate::models::session_store::AuthStore;
 
 pub async fn handle(
-    Extension(store): Extension<RedisSessionStore>,
+    Extension(store): Extension<AuthStore>,
     TypedHeader(cookies): TypedHeader<headers::Cookie>,
 ) -> impl IntoResponse {
     let cookie = cookies.get(AUTH_SESSION_COOKIE).unwrap();
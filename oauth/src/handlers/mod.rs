@@ -1,10 +1,20 @@
 pub mod authenticate;
 pub mod authorize;
+pub mod changes;
+pub(crate) mod drive_api;
 pub mod drive_authorized;
+pub mod drives;
 pub mod favicon;
+pub mod file_meta;
 pub mod filesystem;
 pub mod login_authorized;
 pub mod logout;
+pub mod preview;
+pub mod schema;
+pub mod search;
+pub mod sessions;
 mod shared;
+pub mod upload;
+pub mod webhooks;
 // pub mod user_form;
 // pub mod login;
@@ -0,0 +1,154 @@
+use axum::extract::{Extension, Path, Query};
+use axum::Json;
+
+use crate::errors::AuthError;
+use crate::handlers::drive_api::{self, AuthDriveToken, DriveAccess, DriveCaller};
+use crate::models::drive_clients::{DriveClient, DriveClients, FilesRequest};
+use crate::models::drive_provider::DriveProvider;
+use crate::models::drives::DriveParams;
+use crate::models::files::File;
+use crate::models::http_client::HttpClient;
+use crate::models::preview::{Format, Preview, PreviewParams};
+use crate::models::project_id::ProjectId;
+use crate::models::refresh_tokens::RefreshTokens;
+
+const DEFAULT_ROWS: usize = 10;
+const MAX_ROWS: usize = 100;
+/// CSV/TSV: the first range requested; doubled until it includes the rows
+const INITIAL_RANGE: usize = 64 * 1024;
+const MAX_RANGE: usize = 4 * 1024 * 1024;
+/// Workbooks are read in full (the zip directory is at the end of the file)
+const MAX_WORKBOOK: usize = 20 * 1024 * 1024;
+
+///
+/// 🔗 files/:file_id/preview endpoint
+/// The columns, their inferred types and the first `rows` (default 10, max
+/// 100) of a CSV/TSV, a workbook (XLSX, XLS, ODS) or a Google Sheet.
+///
+/// 👉 CSV/TSV: only the start of the file is downloaded (range request)
+/// 👉 workbooks: `sheet` selects the sheet; the first sheet by default
+///
+/// `drive_id` reads from a shared drive (see the drives endpoint).
+///
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn handle(
+    Path((drive_provider, project_id, file_id)): Path<(DriveProvider, ProjectId, String)>,
+    Query(token): Query<AuthDriveToken>,
+    caller: DriveCaller,
+    Query(drive): Query<DriveParams>,
+    Query(params): Query<PreviewParams>,
+    Extension(clients): Extension<DriveClients>,
+    Extension(http): Extension<HttpClient>,
+    Extension(tokens): Extension<RefreshTokens>,
+) -> Result<Json<Preview>, AuthError> {
+    if let Some(drive_client @ DriveClient { files_request, .. }) = clients.get(&drive_provider) {
+        let access_token = DriveAccess::new(
+            token,
+            drive_client,
+            &tokens,
+            &project_id,
+            &drive_provider,
+            &caller,
+        );
+
+        let file = drive_api::get_file(
+            &http,
+            &drive_provider,
+            files_request,
+            &access_token,
+            &file_id,
+            drive.shared(),
+        )
+        .await?;
+
+        let format = Format::from_file(&file).ok_or_else(|| {
+            AuthError::UnsupportedMedia(
+                format!("Preview is not available for {}", file.mime_type).into(),
+            )
+        })?;
+        let rows = params.rows.unwrap_or(DEFAULT_ROWS).clamp(1, MAX_ROWS);
+
+        let preview = read(
+            &http,
+            &drive_provider,
+            files_request,
+            &access_token,
+            &file,
+            drive.shared(),
+            format,
+            rows,
+            params.sheet.as_deref(),
+        )
+        .await?;
+
+        tracing::debug!("\n🎉 Preview:\n{:#?}\n", &preview.columns);
+        Ok(Json(preview))
+    } else {
+        Err(AuthError::UnsupportedProvider(
+            (&("Auth client not found")).into(),
+        ))
+    }
+}
+///
+/// Download the start of the file (CSV/TSV) or the whole file (workbooks) and
+/// read the first `rows`
+///
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn read(
+    http: &HttpClient,
+    drive_provider: &DriveProvider,
+    files_request: &FilesRequest,
+    access_token: &DriveAccess,
+    file: &File,
+    drive_id: Option<&str>,
+    format: Format,
+    rows: usize,
+    sheet: Option<&str>,
+) -> Result<Preview, AuthError> {
+    if file.size == Some(0) {
+        return Preview::delimited(file, format, &[], false, rows);
+    }
+    if format.is_delimited() {
+        let mut limit = INITIAL_RANGE;
+        loop {
+            let (content, truncated) = drive_api::download(
+                http,
+                drive_provider,
+                files_request,
+                access_token,
+                &file.id,
+                drive_id,
+                None,
+                limit,
+            )
+            .await?;
+            let preview = Preview::delimited(file, format, &content, truncated, rows)?;
+            if !truncated || preview.rows.len() >= rows || limit >= MAX_RANGE {
+                return Ok(preview);
+            }
+            limit = (limit * 2).min(MAX_RANGE);
+        }
+    }
+    if file.size.map_or(false, |size| size as usize > MAX_WORKBOOK) {
+        return Err(AuthError::UnsupportedMedia(
+            "The workbook is too large to preview".into(),
+        ));
+    }
+    let (content, truncated) = drive_api::download(
+        http,
+        drive_provider,
+        files_request,
+        access_token,
+        &file.id,
+        drive_id,
+        format.export(),
+        MAX_WORKBOOK,
+    )
+    .await?;
+    if truncated {
+        return Err(AuthError::UnsupportedMedia(
+            "The workbook is too large to preview".into(),
+        ));
+    }
+    Preview::workbook(file, format, content.to_vec(), sheet, rows)
+}
@@ -0,0 +1,182 @@
+use axum::extract::{Extension, Path, Query};
+use axum::Json;
+
+use crate::errors::AuthError;
+use crate::handlers::drive_api::{self, AuthDriveToken, DriveAccess, DriveCaller};
+use crate::handlers::preview;
+use crate::models::drive_clients::{DriveClient, DriveClients, FilesRequest};
+use crate::models::drive_provider::DriveProvider;
+use crate::models::drives::DriveParams;
+use crate::models::files::File;
+use crate::models::http_client::HttpClient;
+use crate::models::preview::Format;
+use crate::models::project_id::ProjectId;
+use crate::models::refresh_tokens::RefreshTokens;
+use crate::models::schema::{self, Schema, SchemaParams, MAX_FOOTER};
+use crate::models::schema_cache::SchemaCache;
+
+/// CSV/TSV, workbooks: the number of rows sampled
+const SAMPLE_ROWS: usize = 1000;
+/// Parquet: the bytes read from the end of the file in the first request;
+/// usually includes the whole footer
+const FOOTER_RANGE: u64 = 64 * 1024;
+
+///
+/// 🔗 files/:file_id/schema endpoint
+/// The schema of a CSV/TSV, workbook, Google Sheet or Parquet file: the type
+/// of each field (integer, float, boolean, date, datetime or string), the
+/// date format, the null rate and an estimate of the distinct values.
+///
+/// 👉 CSV/TSV, workbooks: inferred from the first 1000 rows
+/// 👉 Parquet: read from the footer metadata (range request)
+///
+/// The schema is cached using the content hash reported by the provider.
+/// `drive_id` reads from a shared drive; `sheet` selects a workbook sheet.
+///
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn handle(
+    Path((drive_provider, project_id, file_id)): Path<(DriveProvider, ProjectId, String)>,
+    Query(token): Query<AuthDriveToken>,
+    caller: DriveCaller,
+    Query(drive): Query<DriveParams>,
+    Query(params): Query<SchemaParams>,
+    Extension(clients): Extension<DriveClients>,
+    Extension(http): Extension<HttpClient>,
+    Extension(tokens): Extension<RefreshTokens>,
+    Extension(cache): Extension<SchemaCache>,
+) -> Result<Json<Schema>, AuthError> {
+    if let Some(drive_client @ DriveClient { files_request, .. }) = clients.get(&drive_provider) {
+        let access_token = DriveAccess::new(
+            token,
+            drive_client,
+            &tokens,
+            &project_id,
+            &drive_provider,
+            &caller,
+        );
+
+        let file = drive_api::get_file(
+            &http,
+            &drive_provider,
+            files_request,
+            &access_token,
+            &file_id,
+            drive.shared(),
+        )
+        .await?;
+
+        let content_hash = file.hashes.content_hash();
+        let sheet = params.sheet.as_deref();
+        if let Some(content_hash) = &content_hash {
+            if let Some(cached) = cache
+                .get(&project_id, &drive_provider, content_hash, sheet)
+                .await?
+            {
+                tracing::debug!("\n🗃️ cached schema: {}\n", content_hash);
+                // the same content may be stored under another name
+                return Ok(Json(Schema {
+                    file_id: file.id,
+                    name: file.name,
+                    ..cached
+                }));
+            }
+        }
+
+        let schema = if schema::is_parquet(&file) {
+            let footer = read_footer(
+                &http,
+                &drive_provider,
+                files_request,
+                &access_token,
+                &file,
+                drive.shared(),
+            )
+            .await?;
+            Schema::parquet(&file, &footer)?
+        } else {
+            let format = Format::from_file(&file).ok_or_else(|| {
+                AuthError::UnsupportedMedia(
+                    format!("Schema inference is not available for {}", file.mime_type).into(),
+                )
+            })?;
+            let sample = preview::read(
+                &http,
+                &drive_provider,
+                files_request,
+                &access_token,
+                &file,
+                drive.shared(),
+                format,
+                SAMPLE_ROWS,
+                sheet,
+            )
+            .await?;
+            Schema::sample(&file, &sample)
+        };
+
+        if let Some(content_hash) = &content_hash {
+            cache
+                .set(&project_id, &drive_provider, content_hash, sheet, &schema)
+                .await?;
+        }
+
+        tracing::debug!("\n🎉 Schema:\n{:#?}\n", &schema.fields);
+        Ok(Json(schema))
+    } else {
+        Err(AuthError::UnsupportedProvider(
+            (&("Auth client not found")).into(),
+        ))
+    }
+}
+///
+/// The Parquet footer: one range request from the end of the file, and a
+/// second one when the footer is longer than the first range
+///
+async fn read_footer(
+    http: &HttpClient,
+    drive_provider: &DriveProvider,
+    files_request: &FilesRequest,
+    access_token: &DriveAccess,
+    file: &File,
+    drive_id: Option<&str>,
+) -> Result<bytes::Bytes, AuthError> {
+    let size = file.size.ok_or_else(|| {
+        AuthError::UnsupportedMedia("The size of the Parquet file is unknown".into())
+    })?;
+    if size < schema::PARQUET_TRAILER as u64 {
+        return Err(AuthError::UnsupportedMedia("Not a Parquet file".into()));
+    }
+    let start = size.saturating_sub(FOOTER_RANGE);
+    let tail = drive_api::download_range(
+        http,
+        drive_provider,
+        files_request,
+        access_token,
+        &file.id,
+        drive_id,
+        start,
+        size - 1,
+    )
+    .await?;
+
+    let length = schema::footer_length(&tail)?;
+    if length > MAX_FOOTER || length as u64 > size {
+        return Err(AuthError::UnsupportedMedia(
+            "The Parquet footer is too large to read".into(),
+        ));
+    }
+    if length <= tail.len() {
+        return Ok(tail.slice(tail.len() - length..));
+    }
+    drive_api::download_range(
+        http,
+        drive_provider,
+        files_request,
+        access_token,
+        &file.id,
+        drive_id,
+        size - length as u64,
+        size - 1,
+    )
+    .await
+}
@@ -0,0 +1,70 @@
+use axum::extract::{Extension, Path, Query};
+use axum::Json;
+
+use crate::errors::AuthError;
+use crate::handlers::drive_api::{self, AuthDriveToken, DriveAccess, DriveCaller, SearchParams};
+use crate::models::drive_clients::{DriveClient, DriveClients};
+use crate::models::drive_provider::DriveProvider;
+use crate::models::file_filter::FilterParams;
+use crate::models::files::Files;
+use crate::models::http_client::HttpClient;
+use crate::models::project_id::ProjectId;
+use crate::models::refresh_tokens::RefreshTokens;
+
+///
+/// 🔗 search endpoint
+/// One search box for every connected drive: maps `q` (and optionally `mime`)
+/// to the provider's search api. Use the returned `next_page` value as the
+/// `page` parameter to retrieve the next page.
+///
+/// `only=tabular` limits the results to files that can be joined.
+///
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn handle(
+    Path((drive_provider, project_id)): Path<(DriveProvider, ProjectId)>,
+    Query(token): Query<AuthDriveToken>,
+    caller: DriveCaller,
+    Query(params): Query<SearchParams>,
+    Query(filter): Query<FilterParams>,
+    Extension(clients): Extension<DriveClients>,
+    Extension(http): Extension<HttpClient>,
+    Extension(tokens): Extension<RefreshTokens>,
+) -> Result<Json<Files>, AuthError> {
+    if let Some(drive_client @ DriveClient { files_request, .. }) = clients.get(&drive_provider) {
+        let access_token = DriveAccess::new(
+            token,
+            drive_client,
+            &tokens,
+            &project_id,
+            &drive_provider,
+            &caller,
+        );
+        let filter = filter.filter()?;
+
+        let mut files = drive_api::search(
+            &http,
+            &drive_provider,
+            files_request,
+            &access_token,
+            &params,
+            filter.as_ref(),
+        )
+        .await?
+        .build();
+
+        // MSGraph search does not filter by mime type
+        if let (DriveProvider::MSGraph, Some(mime)) = (&drive_provider, &params.mime) {
+            files.retain(|file| file.is_directory || &file.mime_type == mime);
+        }
+        if let Some(filter) = filter {
+            files.retain(|file| filter.is_match(file));
+        }
+
+        tracing::debug!("\n🎉 Files:\n{:#?}\n", &files);
+        Ok(Json(files))
+    } else {
+        Err(AuthError::UnsupportedProvider(
+            (&("Auth client not found")).into(),
+        ))
+    }
+}
@@ -0,0 +1,84 @@
+use async_redis_session::USER_ID_KEY;
+use async_session::{Session, SessionStore};
+use axum::extract::{Extension, Path, TypedHeader};
+use axum::http::StatusCode;
+use axum::Json;
+use serde_json::{json, Value};
+
+use crate::constants::AUTH_SESSION_COOKIE;
+use crate::errors::AuthError;
+use crate::models::session_store::AuthStore;
+use crate::models::user_sessions::SessionInfo;
+
+///
+/// 🔗 GET /api/sessions
+/// The active sessions of the user signed-in with the session cookie
+/// (created, last seen, user agent); `current` is the session used to make
+/// the request.
+///
+pub async fn list(
+    Extension(store): Extension<AuthStore>,
+    TypedHeader(cookies): TypedHeader<headers::Cookie>,
+) -> Result<Json<Vec<SessionInfo>>, AuthError> {
+    // the current session was touched as it was loaded
+    let (current, user_id) = current(&cookies, &store).await?;
+    let current_id = current.id().to_string();
+
+    let mut sessions: Vec<SessionInfo> = store
+        .user_sessions(&user_id)
+        .await?
+        .iter()
+        .map(|session| SessionInfo::new(session, &current_id))
+        .collect();
+    sessions.sort_by_key(|session| std::cmp::Reverse(session.last_seen));
+
+    tracing::debug!("\n📚 {} sessions: {}\n", &user_id, sessions.len());
+    Ok(Json(sessions))
+}
+///
+/// 🔗 DELETE /api/sessions/:session_id
+/// Revoke one of the user's sessions (e.g., a lost device)
+///
+pub async fn revoke(
+    Path(session_id): Path<String>,
+    Extension(store): Extension<AuthStore>,
+    TypedHeader(cookies): TypedHeader<headers::Cookie>,
+) -> Result<StatusCode, AuthError> {
+    let (_, user_id) = current(&cookies, &store).await?;
+    store.destroy_user_session(&user_id, &session_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+///
+/// 🔗 DELETE /api/sessions
+/// Sign out everywhere: revoke all of the user's sessions, including the
+/// current session
+///
+pub async fn revoke_all(
+    Extension(store): Extension<AuthStore>,
+    TypedHeader(cookies): TypedHeader<headers::Cookie>,
+) -> Result<Json<Value>, AuthError> {
+    let (_, user_id) = current(&cookies, &store).await?;
+    let revoked = store.destroy_user_sessions(&user_id).await?;
+    tracing::debug!("\n🚪 {} signed out of {} sessions\n", &user_id, revoked);
+    Ok(Json(json!({ "revoked": revoked })))
+}
+///
+/// The session of the request, and its user
+///
+async fn current(
+    cookies: &headers::Cookie,
+    store: &AuthStore,
+) -> Result<(Session, String), AuthError> {
+    let cookie = cookies.get(AUTH_SESSION_COOKIE).ok_or_else(|| {
+        AuthError::Unauthorized(format!("missing session cookie: {}", AUTH_SESSION_COOKIE).into())
+    })?;
+    let session = store
+        .load_session(cookie.to_string())
+        .await
+        .map_err(|err| AuthError::ReadSessionError(err.to_string().into()))?
+        .ok_or_else(|| AuthError::Unauthorized("The session is not active".into()))?;
+    let user_id: String = session
+        .get(USER_ID_KEY)
+        .ok_or_else(|| AuthError::Unauthorized("The session is not signed-in".into()))?;
+    Ok((session, user_id))
+}
@@ -20,29 +20,35 @@
 /// Finally, the pkce is used when exchanging the code for a token.  The token
 /// itself can be used as a stand-alone.
 ///
-use async_redis_session::RedisSessionStore;
+use async_redis_session::USER_ID_KEY;
 use async_session::{Session, SessionStore};
 use http::uri::InvalidUri;
 use http::Uri;
-use http::{header::InvalidHeaderValue, header::SET_COOKIE, HeaderMap};
+use http::{header::InvalidHeaderValue, header::SET_COOKIE, HeaderMap, HeaderValue};
 use oauth2::basic::{BasicClient, BasicTokenResponse};
-use oauth2::reqwest::async_http_client;
 use oauth2::{AuthorizationCode, CsrfToken, PkceCodeVerifier};
 use std::fmt;
 
 use crate::constants::{AUTH_SESSION_COOKIE, CSRF_COOKIE_NAME, PKCE_COOKIE_NAME};
 use crate::errors::AuthError;
 use crate::models::auth_return::AuthReturnValues;
+use crate::models::http_client::HttpClient;
+use crate::models::session_store::AuthStore;
+use crate::models::user_sessions::{self, CREATED_AT_KEY};
 
 /* -------------------------------------------------------------------------------- */
 ///
 /// Phase one: set the session value
 ///
+/// The user signed-in with the current session (if any) is bound to the new
+/// session (e.g., a drive is authorized by a signed-in user).
+///
 pub(crate) async fn set_session(
     headers: HeaderMap,
-    store: RedisSessionStore,
+    store: AuthStore,
     verifier: PkceCodeVerifier,
     csrf_state: CsrfToken,
+    user_id: Option<String>,
 ) -> Result<HeaderMap, AuthError> {
     //
     // 🔐 CsrfToken generates a random key that will be returned in the state key.
@@ -55,6 +61,11 @@ pub(crate) async fn set_session(
     //    3. storing the session id in the user-agent's cookie jar.
     //
     let mut session = Session::new();
+    session
+        .insert(CREATED_AT_KEY, chrono::Utc::now().timestamp())
+        .map_err(|err| {
+            AuthError::WriteSessionError(format!("Writing created at: {}", err).into())
+        })?;
     session.insert(PKCE_COOKIE_NAME, verifier).map_err(|err| {
         AuthError::WriteSessionError(format!("Writing pkce verifier: {}", err).into())
     })?;
@@ -63,6 +74,9 @@ pub(crate) async fn set_session(
         .map_err(|err| {
             AuthError::WriteSessionError(format!("Writing csrf verifier: {}", err).into())
         })?;
+    if let Some(user_id) = user_id {
+        user_sessions::bind(&mut session, &user_id, None)?;
+    }
     //
     tracing::debug!("\n📚 session:\n{:#?}", &session);
     //
@@ -79,14 +93,7 @@ pub(crate) async fn set_session(
             tracing::debug!("\n📚🔑: {}", &session_key);
 
             // stored session, copy to user-agent's cookie jar
-            format!(
-                "{}={}; SameSite=Lax; Path=/",
-                AUTH_SESSION_COOKIE, session_key
-            )
-            .parse() // parse string to HeaderValue
-            .map_err(|err: InvalidHeaderValue| {
-                AuthError::InternalError(format!("Failed to create HeaderValue: {}", err).into())
-            })
+            session_cookie(&session_key)
         })?;
 
     //
@@ -107,13 +114,12 @@ pub(crate) async fn set_session(
 ///
 pub(crate) async fn retrieve_validators(
     cookies: &headers::Cookie,
-    store: &RedisSessionStore,
+    store: &AuthStore,
 ) -> Result<(PkceCodeVerifier, CsrfToken), AuthError> {
     //
-    // debug status of the redis auth store
-    let count = store.count().await.unwrap();
+    // debug status of the auth store (the session count is not read on the
+    // callback path; see RedisSessionStore::count)
     tracing::debug!("\n📚 auth_store:\n{:#?}\n", &store);
-    tracing::debug!("\n🧮 auth_store session count: {}\n", count);
 
     /* ------------------------------------------------------------------------------------- */
     // ☠️  retrieve the session to validate the user_agent that now has a code
@@ -153,6 +159,78 @@ pub(crate) async fn retrieve_validators(
 }
 ///
 /// ### Phase two
+/// #### Bind the session to the authenticated user
+/// The user's sessions are listed and revoked using the sessions endpoints.
+///
+/// The session moves to a new id now that it is authenticated (session
+/// fixation); returns the Set-Cookie value of the new id.
+///
+pub(crate) async fn bind_user(
+    cookies: &headers::Cookie,
+    store: &AuthStore,
+    user_id: &str,
+    user_agent: Option<&str>,
+) -> Result<HeaderValue, AuthError> {
+    let mut session = load_session(cookies, store).await?;
+    user_sessions::bind(&mut session, user_id, user_agent)?;
+    session_cookie(&store.regenerate(session).await?)
+}
+///
+/// ### Phase two
+/// #### Move the session to a new id
+/// Once the session is granted access (e.g., to a drive); returns the
+/// Set-Cookie value of the new id.
+///
+pub(crate) async fn regenerate_session(
+    cookies: &headers::Cookie,
+    store: &AuthStore,
+) -> Result<HeaderValue, AuthError> {
+    let session = load_session(cookies, store).await?;
+    session_cookie(&store.regenerate(session).await?)
+}
+///
+/// The user bound to the auth session; None when the session is missing, or
+/// not (yet) bound to a user
+///
+pub(crate) async fn session_user(
+    cookies: &headers::Cookie,
+    store: &AuthStore,
+) -> Result<Option<String>, AuthError> {
+    let session_id = match cookies.get(AUTH_SESSION_COOKIE) {
+        Some(session_id) => session_id,
+        None => return Ok(None),
+    };
+    Ok(store
+        .load_session(session_id.to_string())
+        .await
+        .map_err(|err| AuthError::ReadSessionError(err.to_string().into()))?
+        .and_then(|session| session.get::<String>(USER_ID_KEY)))
+}
+async fn load_session(cookies: &headers::Cookie, store: &AuthStore) -> Result<Session, AuthError> {
+    let session_id = cookies.get(AUTH_SESSION_COOKIE).ok_or_else(|| {
+        AuthError::MissingSession(format!("missing session cookie: {}", AUTH_SESSION_COOKIE).into())
+    })?;
+    store
+        .load_session(session_id.to_string())
+        .await
+        .map_err(|err| AuthError::ReadSessionError(err.to_string().into()))?
+        .ok_or_else(|| AuthError::MissingSession(format!("none with id: {}", session_id).into()))
+}
+///
+/// 🍪 The Set-Cookie value that stores the session key in the user-agent
+///
+fn session_cookie(session_key: &str) -> Result<HeaderValue, AuthError> {
+    format!(
+        "{}={}; SameSite=Lax; Path=/",
+        AUTH_SESSION_COOKIE, session_key
+    )
+    .parse() // parse string to HeaderValue
+    .map_err(|err: InvalidHeaderValue| {
+        AuthError::InternalError(format!("Failed to create HeaderValue: {}", err).into())
+    })
+}
+///
+/// ### Phase two
 /// #### step 2 get the session value
 /// Get the token; requires providing the pkce verifier
 ///
@@ -160,6 +238,7 @@ pub(crate) async fn get_token(
     auth_return_values: &AuthReturnValues,
     client: &BasicClient,
     verifier: PkceCodeVerifier,
+    http: &HttpClient,
 ) -> Result<BasicTokenResponse, AuthError> {
     /* ------------------------------------------------------------------------------------- */
     // ✅ Got the code, now use it to get the token
@@ -176,7 +255,7 @@ pub(crate) async fn get_token(
     let token_response = client
         .exchange_code(AuthorizationCode::new(auth_return_values.code.clone()))
         .set_pkce_verifier(verifier)
-        .request_async(async_http_client)
+        .request_async(|request| http.oauth2(request))
         .await
         .map_err(|err| {
             let message = format!("Access token response:\n{:#?}", err);
@@ -0,0 +1,589 @@
+use axum::body::Bytes;
+use axum::extract::{BodyStream, Extension, Path, Query, TypedHeader};
+use axum::http::header::{CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, LOCATION};
+use axum::Json;
+use futures::{future, stream, Stream, StreamExt};
+use headers::{ContentLength, ContentType};
+use http::Method;
+use serde::Deserialize;
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::errors::AuthError;
+use crate::handlers::drive_api::{
+    self, ascii_json, AuthDriveToken, DriveAccess, DriveCaller, DROPBOX_CONTENT_SERVER,
+    GOOGLE_FILE_FIELDS,
+};
+use crate::models::drive_clients::{DriveClient, DriveClients, FilesRequest};
+use crate::models::drive_provider::DriveProvider;
+use crate::models::files::{File, RawFileDropBox, RawFileGoogle, RawFileMSGraph};
+use crate::models::http_client::HttpClient;
+use crate::models::listing_cache::ListingCache;
+use crate::models::project_id::ProjectId;
+use crate::models::refresh_tokens::RefreshTokens;
+
+/// Files up to this size are uploaded in a single request
+const SIMPLE_UPLOAD_LIMIT: u64 = 4 * 1024 * 1024;
+/// Multiple of 320 KiB (MSGraph) and 256 KiB (Google)
+const CHUNK_SIZE: usize = 5 * 1280 * 1024;
+#[derive(Debug, Deserialize)]
+pub struct UploadParams {
+    /// folder id (DropBox: folder path or id); the root when not provided
+    parent: Option<String>,
+    name: String,
+    /// when the folder has a file with the same name
+    #[serde(default)]
+    conflict: Conflict,
+}
+///
+/// The upload is named as an existing file of the folder
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Conflict {
+    /// the upload is renamed, e.g., `report (1).csv`
+    Rename,
+    /// the content of the existing file is replaced
+    Replace,
+    /// the upload fails (409)
+    Fail,
+}
+impl Default for Conflict {
+    fn default() -> Self {
+        Conflict::Rename
+    }
+}
+impl Conflict {
+    /// `@microsoft.graph.conflictBehavior`
+    fn ms_graph(self) -> &'static str {
+        match self {
+            Conflict::Rename => "rename",
+            Conflict::Replace => "replace",
+            Conflict::Fail => "fail",
+        }
+    }
+}
+///
+/// 🔗 upload endpoint
+/// Stream the request body to the user's drive, into the `parent` folder.
+///
+/// `conflict` (rename, replace or fail) applies when the folder has a file
+/// with the same name; the upload is renamed by default.
+///
+/// * small files: a single request
+///   (Google multipart, MSGraph PUT content, DropBox upload)
+/// * large files: an upload session sent in chunks
+///   (Google resumable, MSGraph createUploadSession, DropBox upload_session)
+///
+/// 🔖 Requires Content-Length; the providers need the size up-front.
+///
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn handle(
+    Path((drive_provider, project_id)): Path<(DriveProvider, ProjectId)>,
+    Query(token): Query<AuthDriveToken>,
+    caller: DriveCaller,
+    Query(params): Query<UploadParams>,
+    TypedHeader(content_length): TypedHeader<ContentLength>,
+    content_type: Option<TypedHeader<ContentType>>,
+    Extension(clients): Extension<DriveClients>,
+    Extension(http): Extension<HttpClient>,
+    Extension(tokens): Extension<RefreshTokens>,
+    Extension(cache): Extension<ListingCache>,
+    body: BodyStream,
+) -> Result<Json<File>, AuthError> {
+    if let Some(drive_client @ DriveClient { files_request, .. }) = clients.get(&drive_provider) {
+        let access_token = DriveAccess::new(
+            token,
+            drive_client,
+            &tokens,
+            &project_id,
+            &drive_provider,
+            &caller,
+        );
+        let upload = Upload {
+            http: &http,
+            files_request,
+            access_token: &access_token,
+            params: &params,
+            size: content_length.0,
+            content_type: content_type
+                .map(|TypedHeader(content_type)| content_type.to_string())
+                .unwrap_or_else(|| "application/octet-stream".to_string()),
+        };
+        tracing::debug!("\n📤 upload:\n{:#?}\n", &upload);
+
+        let file = match (&drive_provider, upload.size <= SIMPLE_UPLOAD_LIMIT) {
+            (DriveProvider::Google, true) => upload.google_simple(body).await,
+            (DriveProvider::Google, false) => upload.google_resumable(body).await,
+            (DriveProvider::MSGraph, true) => upload.ms_graph_simple(body).await,
+            (DriveProvider::MSGraph, false) => upload.ms_graph_session(body).await,
+            (DriveProvider::DropBox, true) => upload.drop_box_simple(body).await,
+            (DriveProvider::DropBox, false) => upload.drop_box_session(body).await,
+            _ => Err(AuthError::InternalError("Unsupported drive type".into())),
+        }?;
+
+        tracing::debug!("\n🎉 Uploaded:\n{:#?}\n", &file);
+        // the uploads go to the personal drive
+        cache.invalidate(&project_id, &drive_provider, None).await?;
+        Ok(Json(file))
+    } else {
+        Err(AuthError::UnsupportedProvider(
+            (&("Auth client not found")).into(),
+        ))
+    }
+}
+
+#[derive(Debug)]
+struct Upload<'a> {
+    http: &'a HttpClient,
+    files_request: &'a FilesRequest,
+    access_token: &'a DriveAccess,
+    params: &'a UploadParams,
+    size: u64,
+    content_type: String,
+}
+impl<'a> Upload<'a> {
+    /* ---------------------------------------------------------------------------- */
+    // Google
+    /* ---------------------------------------------------------------------------- */
+    fn google_metadata(&self, name: &str) -> serde_json::Value {
+        match &self.params.parent {
+            Some(parent) => json!({ "name": name, "parents": [parent] }),
+            None => json!({ "name": name }),
+        }
+    }
+    fn google_url(&self, file_id: Option<&str>, upload_type: &str) -> String {
+        format!(
+            "{host}/upload/drive/v3/files{file_id}?uploadType={upload_type}&fields={fields}",
+            host = self.files_request.drive_server,
+            file_id = file_id.map(|id| format!("/{}", id)).unwrap_or_default(),
+            upload_type = upload_type,
+            fields = GOOGLE_FILE_FIELDS
+        )
+    }
+    ///
+    /// Google does not require unique names: the conflict is resolved using
+    /// the files of the folder with a similar name
+    ///
+    async fn google_target(&self) -> Result<GoogleTarget, AuthError> {
+        #[derive(Deserialize)]
+        struct Similar {
+            files: Vec<Named>,
+        }
+        #[derive(Deserialize)]
+        struct Named {
+            id: String,
+            name: String,
+        }
+        let name = &self.params.name;
+        let (stem, _) = split_extension(name);
+        let query = format!(
+            "'{parent}' in parents and (name = '{name}' or name contains '{stem}') and trashed = false",
+            parent = google_quote(self.params.parent.as_deref().unwrap_or("root")),
+            name = google_quote(name),
+            stem = google_quote(stem),
+        );
+        let url = format!(
+            "{host}/drive/v3/files",
+            host = self.files_request.drive_server
+        );
+        let response = drive_api::send_read_only(
+            self.http,
+            self.access_token,
+            drive_api::request(self.http, Method::GET, url, self.access_token).query(&[
+                ("q", query.as_str()),
+                ("fields", "files(id,name)"),
+                ("pageSize", "1000"),
+            ]),
+        )
+        .await?;
+        let Similar { files } = drive_api::parse(response).await?;
+
+        let existing = match files.iter().find(|file| &file.name == name) {
+            Some(existing) => existing,
+            None => return Ok(GoogleTarget::Create(name.clone())),
+        };
+        match self.params.conflict {
+            Conflict::Replace => Ok(GoogleTarget::Update(existing.id.clone())),
+            Conflict::Fail => Err(conflict(name)),
+            Conflict::Rename => {
+                let taken = |candidate: &String| files.iter().any(|file| &file.name == candidate);
+                let renamed = (1..)
+                    .map(|n| renamed(name, n))
+                    .find(|candidate| !taken(candidate))
+                    .unwrap_or_else(|| name.clone());
+                Ok(GoogleTarget::Create(renamed))
+            }
+        }
+    }
+    async fn google_simple(&self, body: BodyStream) -> Result<File, AuthError> {
+        let name = match self.google_target().await? {
+            GoogleTarget::Create(name) => name,
+            GoogleTarget::Update(file_id) => {
+                let response = drive_api::send(
+                    self.http,
+                    self.access_token,
+                    drive_api::request(
+                        self.http,
+                        Method::PATCH,
+                        self.google_url(Some(&file_id), "media"),
+                        self.access_token,
+                    )
+                    .header(CONTENT_TYPE, &self.content_type)
+                    .header(CONTENT_LENGTH, self.size)
+                    .body(self.http.stream_body(body)),
+                )
+                .await?;
+                return Ok(drive_api::parse::<RawFileGoogle>(response).await?.into());
+            }
+        };
+        let boundary = Uuid::new_v4().simple().to_string();
+        let preamble = format!(
+            "--{boundary}\r\nContent-Type: application/json; charset=UTF-8\r\n\r\n{metadata}\r\n--{boundary}\r\nContent-Type: {content_type}\r\n\r\n",
+            boundary = boundary,
+            metadata = self.google_metadata(&name),
+            content_type = self.content_type
+        );
+        let closing = format!("\r\n--{}--\r\n", boundary);
+        let length = preamble.len() as u64 + self.size + closing.len() as u64;
+
+        let body = stream::once(future::ready(Ok(Bytes::from(preamble))))
+            .chain(body)
+            .chain(stream::once(future::ready(Ok(Bytes::from(closing)))));
+
+        let response = drive_api::send(
+            self.http,
+            self.access_token,
+            drive_api::request(
+                self.http,
+                Method::POST,
+                self.google_url(None, "multipart"),
+                self.access_token,
+            )
+            .header(
+                CONTENT_TYPE,
+                format!("multipart/related; boundary={}", boundary),
+            )
+            .header(CONTENT_LENGTH, length)
+            .body(self.http.stream_body(body)),
+        )
+        .await?;
+        Ok(drive_api::parse::<RawFileGoogle>(response).await?.into())
+    }
+    async fn google_resumable(&self, body: BodyStream) -> Result<File, AuthError> {
+        let (method, url, metadata) = match self.google_target().await? {
+            GoogleTarget::Create(name) => (
+                Method::POST,
+                self.google_url(None, "resumable"),
+                self.google_metadata(&name),
+            ),
+            GoogleTarget::Update(file_id) => (
+                Method::PATCH,
+                self.google_url(Some(&file_id), "resumable"),
+                json!({}),
+            ),
+        };
+        let response = drive_api::send(
+            self.http,
+            self.access_token,
+            drive_api::request(self.http, method, url, self.access_token)
+                .header("X-Upload-Content-Type", &self.content_type)
+                .header("X-Upload-Content-Length", self.size)
+                .json(&metadata),
+        )
+        .await?;
+        let session_uri = location(&response)?;
+
+        let response = self
+            .chunks(body, |chunk, offset| {
+                let request =
+                    drive_api::request(self.http, Method::PUT, &session_uri, self.access_token);
+                self.send_chunk(request, chunk, offset)
+            })
+            .await?;
+        Ok(drive_api::parse::<RawFileGoogle>(response).await?.into())
+    }
+    /* ---------------------------------------------------------------------------- */
+    // MSGraph
+    /* ---------------------------------------------------------------------------- */
+    fn ms_graph_item(&self) -> String {
+        format!(
+            "{host}/v1.0/me/drive/{parent}:/{name}:",
+            host = self.files_request.drive_server,
+            parent = self
+                .params
+                .parent
+                .as_ref()
+                .map_or_else(|| "root".to_string(), |parent| format!("items/{}", parent)),
+            name = percent_encoding::utf8_percent_encode(
+                &self.params.name,
+                percent_encoding::NON_ALPHANUMERIC
+            )
+        )
+    }
+    async fn ms_graph_simple(&self, body: BodyStream) -> Result<File, AuthError> {
+        let url = format!(
+            "{}/content?@microsoft.graph.conflictBehavior={}",
+            self.ms_graph_item(),
+            self.params.conflict.ms_graph()
+        );
+        let response = drive_api::send(
+            self.http,
+            self.access_token,
+            drive_api::request(self.http, Method::PUT, url, self.access_token)
+                .header(CONTENT_TYPE, &self.content_type)
+                .header(CONTENT_LENGTH, self.size)
+                .body(self.http.stream_body(body)),
+        )
+        .await?;
+        Ok(drive_api::parse::<RawFileMSGraph>(response).await?.into())
+    }
+    async fn ms_graph_session(&self, body: BodyStream) -> Result<File, AuthError> {
+        #[derive(Deserialize)]
+        struct UploadSession {
+            #[serde(rename = "uploadUrl")]
+            upload_url: String,
+        }
+        let url = format!("{}/createUploadSession", self.ms_graph_item());
+        let response = drive_api::send(
+            self.http,
+            self.access_token,
+            drive_api::request(self.http, Method::POST, url, self.access_token).json(&json!({
+                "item": { "@microsoft.graph.conflictBehavior": self.params.conflict.ms_graph() }
+            })),
+        )
+        .await?;
+        let UploadSession { upload_url } = drive_api::parse(response).await?;
+
+        let response = self
+            .chunks(body, |chunk, offset| {
+                // ⚠️  the upload url is pre-authenticated; do not send the token
+                let request = self.http.put(&upload_url);
+                self.send_chunk(request, chunk, offset)
+            })
+            .await?;
+        Ok(drive_api::parse::<RawFileMSGraph>(response).await?.into())
+    }
+    /* ---------------------------------------------------------------------------- */
+    // DropBox
+    /* ---------------------------------------------------------------------------- */
+    fn drop_box_url(&self, endpoint: &str) -> String {
+        format!(
+            "{host}/2/files/{endpoint}",
+            host = self
+                .files_request
+                .content_server
+                .as_deref()
+                .unwrap_or(DROPBOX_CONTENT_SERVER),
+            endpoint = endpoint
+        )
+    }
+    fn drop_box_commit(&self) -> serde_json::Value {
+        json!({
+            "path": format!("{}/{}", self.params.parent.as_deref().unwrap_or_default(), self.params.name),
+            "mode": match self.params.conflict {
+                Conflict::Replace => "overwrite",
+                Conflict::Rename | Conflict::Fail => "add",
+            },
+            "autorename": self.params.conflict == Conflict::Rename,
+        })
+    }
+    fn drop_box_request(&self, endpoint: &str, arg: serde_json::Value) -> reqwest::RequestBuilder {
+        drive_api::request(
+            self.http,
+            Method::POST,
+            self.drop_box_url(endpoint),
+            self.access_token,
+        )
+        .header(CONTENT_TYPE, "application/octet-stream")
+        // json in a header must be ascii
+        .header("Dropbox-API-Arg", ascii_json(&arg))
+    }
+    async fn drop_box_simple(&self, body: BodyStream) -> Result<File, AuthError> {
+        let response = drive_api::send(
+            self.http,
+            self.access_token,
+            self.drop_box_request("upload", self.drop_box_commit())
+                .header(CONTENT_LENGTH, self.size)
+                .body(self.http.stream_body(body)),
+        )
+        .await?;
+        Ok(drive_api::parse::<RawFileDropBox>(response).await?.into())
+    }
+    async fn drop_box_session(&self, body: BodyStream) -> Result<File, AuthError> {
+        #[derive(Deserialize)]
+        struct UploadSession {
+            session_id: String,
+        }
+        let response = drive_api::send(
+            self.http,
+            self.access_token,
+            self.drop_box_request("upload_session/start", json!({ "close": false })),
+        )
+        .await?;
+        let UploadSession { session_id } = drive_api::parse(response).await?;
+
+        let response = self
+            .chunks(body, |chunk, offset| {
+                let cursor = json!({ "session_id": session_id, "offset": offset });
+                let request = if offset + chunk.len() as u64 == self.size {
+                    self.drop_box_request(
+                        "upload_session/finish",
+                        json!({ "cursor": cursor, "commit": self.drop_box_commit() }),
+                    )
+                } else {
+                    self.drop_box_request(
+                        "upload_session/append_v2",
+                        json!({ "cursor": cursor, "close": false }),
+                    )
+                };
+                drive_api::send(self.http, self.access_token, request.body(chunk))
+            })
+            .await?;
+        Ok(drive_api::parse::<RawFileDropBox>(response).await?.into())
+    }
+    /* ---------------------------------------------------------------------------- */
+    // Chunked uploads
+    /* ---------------------------------------------------------------------------- */
+    ///
+    /// Send the body in CHUNK_SIZE chunks; returns the response to the last chunk
+    ///
+    async fn chunks<F, Fut>(
+        &self,
+        mut body: impl Stream<Item = Result<Bytes, axum::Error>> + Unpin,
+        mut send: F,
+    ) -> Result<reqwest::Response, AuthError>
+    where
+        F: FnMut(Bytes, u64) -> Fut,
+        Fut: std::future::Future<Output = Result<reqwest::Response, AuthError>>,
+    {
+        let mut buffer = bytes::BytesMut::with_capacity(CHUNK_SIZE);
+        let mut offset: u64 = 0;
+        let mut last = None;
+
+        loop {
+            let next = body.next().await.transpose().map_err(|err| {
+                AuthError::InvalidResponse(format!("Upload body: {}", err).into())
+            })?;
+            if let Some(bytes) = &next {
+                buffer.extend_from_slice(bytes);
+            }
+            while buffer.len() >= CHUNK_SIZE || (next.is_none() && !buffer.is_empty()) {
+                let length = buffer.len().min(CHUNK_SIZE);
+                let chunk = buffer.split_to(length).freeze();
+                last = Some(send(chunk, offset).await?);
+                offset += length as u64;
+            }
+            if next.is_none() {
+                break;
+            }
+        }
+        if offset != self.size {
+            return Err(AuthError::MissingParameter(
+                format!("Content-Length {} but received {} bytes", self.size, offset).into(),
+            ));
+        }
+        last.ok_or_else(|| AuthError::MissingParameter("Empty upload".into()))
+    }
+    ///
+    /// Google and MSGraph: one chunk of an upload session (see drive_api::send)
+    ///
+    async fn send_chunk(
+        &self,
+        request: reqwest::RequestBuilder,
+        chunk: Bytes,
+        offset: u64,
+    ) -> Result<reqwest::Response, AuthError> {
+        let end = offset + chunk.len() as u64 - 1;
+        drive_api::send(
+            self.http,
+            self.access_token,
+            request
+                .header(CONTENT_LENGTH, chunk.len())
+                .header(
+                    CONTENT_RANGE,
+                    format!("bytes {}-{}/{}", offset, end, self.size),
+                )
+                .body(chunk),
+        )
+        .await
+    }
+}
+///
+/// Where a Google upload is written
+///
+#[derive(Debug)]
+enum GoogleTarget {
+    /// a new file, with the name
+    Create(String),
+    /// the existing file, with the id
+    Update(String),
+}
+///
+/// `report.csv` -> (`report`, `.csv`); a leading dot is part of the stem
+///
+fn split_extension(name: &str) -> (&str, &str) {
+    match name.rfind('.') {
+        Some(dot) if dot > 0 => name.split_at(dot),
+        _ => (name, ""),
+    }
+}
+///
+/// The name of the n-th copy, as named by MSGraph and DropBox: `report (1).csv`
+///
+fn renamed(name: &str, n: usize) -> String {
+    let (stem, extension) = split_extension(name);
+    format!("{} ({}){}", stem, n, extension)
+}
+///
+/// A string literal of a Google query
+///
+fn google_quote(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\'', "\\'")
+}
+fn conflict(name: &str) -> AuthError {
+    AuthError::DriveConflict(format!("The folder already has a file named {}", name).into())
+}
+///
+/// The session uri in the Location header
+///
+fn location(response: &reqwest::Response) -> Result<String, AuthError> {
+    response
+        .headers()
+        .get(LOCATION)
+        .and_then(|location| location.to_str().ok())
+        .map(ToString::to_string)
+        .ok_or_else(|| AuthError::MissingProperty("Upload session location".into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copies_are_named_as_by_the_providers() {
+        assert_eq!("report (1).csv", renamed("report.csv", 1));
+        assert_eq!("archive.tar (2).gz", renamed("archive.tar.gz", 2));
+        assert_eq!("README (1)", renamed("README", 1));
+        assert_eq!(".env (1)", renamed(".env", 1));
+    }
+
+    #[test]
+    fn names_are_quoted_in_google_queries() {
+        assert_eq!(r"Bob\'s report", google_quote("Bob's report"));
+        assert_eq!(r"a\\b", google_quote(r"a\b"));
+    }
+
+    #[test]
+    fn uploads_are_renamed_unless_requested() {
+        let params: UploadParams = serde_json::from_value(json!({ "name": "a.csv" })).unwrap();
+        assert_eq!(Conflict::Rename, params.conflict);
+        let params: UploadParams =
+            serde_json::from_value(json!({ "name": "a.csv", "conflict": "replace" })).unwrap();
+        assert_eq!(Conflict::Replace, params.conflict);
+        assert!(serde_json::from_value::<UploadParams>(
+            json!({ "name": "a.csv", "conflict": "merge" })
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,462 @@
+///
+/// Inbound change notifications from the drive providers (webhooks).
+///
+/// Each notification is mapped to a (ProjectId, DriveProvider) using the
+/// subscriptions registered with `subscribe`, then published as a DriveEvent
+/// for the downstream tnc services (that in turn retrieve the changes).
+/// The cached listings of the drive are dropped.
+///
+/// The subscriptions expire (MSGraph: 29 days, Google: 7 days); they are
+/// renewed in the background before they do (see renew_subscriptions).
+///
+use axum::body::Bytes;
+use axum::extract::{Extension, Path, Query};
+use axum::http::header::{HeaderMap, CONTENT_TYPE, X_CONTENT_TYPE_OPTIONS};
+use axum::http::StatusCode;
+use axum::response::{Headers, IntoResponse, Response};
+use axum::Json;
+use chrono::{Duration, Utc};
+use hmac::{Hmac, Mac};
+use secrecy::ExposeSecret;
+use serde::Deserialize;
+use serde_json::json;
+use sha2::Sha256;
+use subtle::ConstantTimeEq;
+
+use crate::config::config_get;
+use crate::errors::AuthError;
+use crate::handlers::drive_api::{self, AuthDriveToken, DriveAccess, DriveCaller};
+use crate::models::drive_clients::{DriveClient, DriveClients};
+use crate::models::drive_provider::DriveProvider;
+use crate::models::http_client::HttpClient;
+use crate::models::listing_cache::ListingCache;
+use crate::models::project_id::ProjectId;
+use crate::models::refresh_tokens::RefreshTokens;
+use crate::models::webhooks::{DriveEvent, Subscription, Webhooks};
+
+/// Subscriptions that expire within this window are renewed
+const RENEW_WINDOW_HOURS: i64 = 24;
+/// How often the subscriptions due for renewal are looked up
+const RENEW_INTERVAL_MINUTES: u64 = 60;
+
+///
+/// 🔗 subscriptions endpoint
+/// Register (or renew when about to expire) the project's drive to receive
+/// change notifications. Call periodically; a current subscription is returned
+/// as-is.
+///
+pub(crate) async fn subscribe(
+    Path((drive_provider, project_id)): Path<(DriveProvider, ProjectId)>,
+    Query(token): Query<AuthDriveToken>,
+    caller: DriveCaller,
+    Extension(clients): Extension<DriveClients>,
+    Extension(http): Extension<HttpClient>,
+    Extension(tokens): Extension<RefreshTokens>,
+    Extension(webhooks): Extension<Webhooks>,
+) -> Result<impl IntoResponse, AuthError> {
+    if let Some(drive_client @ DriveClient { files_request, .. }) = clients.get(&drive_provider) {
+        let access_token = DriveAccess::new(
+            token,
+            drive_client,
+            &tokens,
+            &project_id,
+            &drive_provider,
+            &caller,
+        );
+        let renew_after = Utc::now() + Duration::hours(RENEW_WINDOW_HOURS);
+
+        let previous = webhooks
+            .get_for_project(&project_id, &drive_provider)
+            .await?;
+
+        let subscription = match previous {
+            Some(current) if current.expires_at.map_or(true, |at| at > renew_after) => current,
+            previous => {
+                let subscription = drive_api::subscribe(
+                    &http,
+                    &drive_provider,
+                    files_request,
+                    &access_token,
+                    &project_id,
+                    previous,
+                )
+                .await?;
+                webhooks.set(&subscription).await?;
+                if drive_provider == DriveProvider::DropBox {
+                    webhooks
+                        .add_account(&drive_provider, &subscription.id, &project_id)
+                        .await?;
+                }
+                tracing::debug!("\n📣 Subscribed:\n{:#?}\n", &subscription);
+                subscription
+            }
+        };
+
+        // 🔖 the client_state is a shared secret; not returned
+        Ok(Json(json!({
+            "id": subscription.id,
+            "drive_provider": subscription.drive_provider,
+            "expires_at": subscription.expires_at,
+        })))
+    } else {
+        Err(AuthError::UnsupportedProvider(
+            (&("Auth client not found")).into(),
+        ))
+    }
+}
+/* -------------------------------------------------------------------------------- */
+///
+/// ⏰ Renew the subscriptions that expire within the renewal window, using
+/// the token stored for the project (the access of the user that authorized
+/// the drive); runs until the server stops.
+///
+pub(crate) async fn renew_subscriptions(
+    clients: DriveClients,
+    http: HttpClient,
+    tokens: RefreshTokens,
+    webhooks: Webhooks,
+) {
+    let mut interval =
+        tokio::time::interval(std::time::Duration::from_secs(RENEW_INTERVAL_MINUTES * 60));
+    loop {
+        interval.tick().await;
+        let renew_after = Utc::now() + Duration::hours(RENEW_WINDOW_HOURS);
+        let due = match webhooks.due(renew_after).await {
+            Ok(due) => due,
+            Err(err) => {
+                tracing::warn!("\n⚠️  Subscriptions to renew: {}\n", err);
+                continue;
+            }
+        };
+        for subscription in due {
+            let id = subscription.id.clone();
+            match renew(&clients, &http, &tokens, &webhooks, subscription).await {
+                Ok(renewed) => tracing::debug!("\n📣 Renewed:\n{:#?}\n", &renewed),
+                Err(err) => tracing::warn!("\n⚠️  Renewing subscription {}: {}\n", id, err),
+            }
+        }
+    }
+}
+async fn renew(
+    clients: &DriveClients,
+    http: &HttpClient,
+    tokens: &RefreshTokens,
+    webhooks: &Webhooks,
+    previous: Subscription,
+) -> Result<Subscription, AuthError> {
+    let Subscription {
+        project_id,
+        drive_provider,
+        ..
+    } = previous.clone();
+    let drive_client = clients
+        .get(&drive_provider)
+        .ok_or_else(|| AuthError::UnsupportedProvider("Auth client not found".into()))?;
+    let stored = tokens
+        .get(&project_id, &drive_provider)
+        .await?
+        .ok_or_else(|| AuthError::Unauthorized("No token stored for the drive".into()))?;
+    let access_token =
+        DriveAccess::for_owner(stored, drive_client, tokens, &project_id, &drive_provider);
+
+    let subscription = drive_api::subscribe(
+        http,
+        &drive_provider,
+        &drive_client.files_request,
+        &access_token,
+        &project_id,
+        Some(previous),
+    )
+    .await?;
+    webhooks.set(&subscription).await?;
+    Ok(subscription)
+}
+/* -------------------------------------------------------------------------------- */
+///
+/// 🔗 Google push channel
+/// The channel id and token are sent as headers; the body is empty.
+///
+pub(crate) async fn google(
+    headers: HeaderMap,
+    Extension(webhooks): Extension<Webhooks>,
+    Extension(cache): Extension<ListingCache>,
+) -> Result<StatusCode, AuthError> {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .ok_or_else(|| AuthError::MissingParameter(format!("missing header: {}", name).into()))
+    };
+    let channel_id = header("X-Goog-Channel-ID")?;
+    let token = header("X-Goog-Channel-Token")?;
+    let state = header("X-Goog-Resource-State")?;
+
+    let subscription = webhooks
+        .get(channel_id)
+        .await?
+        .filter(|subscription| same_secret(&subscription.client_state, token))
+        .ok_or_else(|| AuthError::Unauthorized("Unknown channel".into()))?;
+
+    if reports_changes(state) {
+        notify(
+            &webhooks,
+            &cache,
+            subscription.project_id,
+            subscription.drive_provider,
+        )
+        .await?;
+    }
+    Ok(StatusCode::OK)
+}
+///
+/// 🔖 the first message confirms the channel (`sync`)
+///
+fn reports_changes(state: &str) -> bool {
+    state != "sync"
+}
+/* -------------------------------------------------------------------------------- */
+#[derive(Debug, Deserialize)]
+pub struct GraphValidation {
+    #[serde(rename = "validationToken")]
+    validation_token: Option<String>,
+}
+#[derive(Debug, Deserialize)]
+pub struct GraphNotifications {
+    value: Vec<GraphNotification>,
+}
+#[derive(Debug, Deserialize)]
+struct GraphNotification {
+    #[serde(rename = "subscriptionId")]
+    subscription_id: String,
+    #[serde(rename = "clientState")]
+    client_state: Option<String>,
+}
+impl GraphNotification {
+    /// the notification carries the secret of the subscription
+    fn is_signed_with(&self, client_state: &str) -> bool {
+        self.client_state
+            .as_deref()
+            .map_or(false, |state| same_secret(client_state, state))
+    }
+}
+///
+/// 🔗 MSGraph subscription
+/// Echo the validationToken when the subscription is created; otherwise
+/// a batch of notifications.
+///
+pub(crate) async fn ms_graph(
+    Query(validation): Query<GraphValidation>,
+    Extension(webhooks): Extension<Webhooks>,
+    Extension(cache): Extension<ListingCache>,
+    body: Bytes,
+) -> Result<Response, AuthError> {
+    if let Some(validation_token) = validation.validation_token {
+        return Ok(plain_text(validation_token));
+    }
+    let GraphNotifications { value } = serde_json::from_slice(&body)?;
+
+    for notification in value {
+        match webhooks.get(&notification.subscription_id).await? {
+            Some(subscription) if notification.is_signed_with(&subscription.client_state) => {
+                notify(
+                    &webhooks,
+                    &cache,
+                    subscription.project_id,
+                    subscription.drive_provider,
+                )
+                .await?;
+            }
+            _ => {
+                tracing::warn!(
+                    "\n⚠️  Ignored notification for subscription: {}\n",
+                    &notification.subscription_id
+                );
+            }
+        }
+    }
+    Ok(StatusCode::ACCEPTED.into_response())
+}
+/* -------------------------------------------------------------------------------- */
+#[derive(Debug, Deserialize)]
+pub struct DropBoxChallenge {
+    challenge: String,
+}
+#[derive(Debug, Deserialize)]
+struct DropBoxNotification {
+    list_folder: DropBoxAccounts,
+}
+#[derive(Debug, Deserialize)]
+struct DropBoxAccounts {
+    accounts: Vec<String>,
+}
+///
+/// 🔗 DropBox webhook verification: echo the challenge
+///
+pub(crate) async fn drop_box_challenge(Query(query): Query<DropBoxChallenge>) -> Response {
+    plain_text(query.challenge)
+}
+///
+/// 🔗 DropBox webhook
+/// The body is signed with the app secret (X-Dropbox-Signature); the
+/// notification lists the accounts with changes.
+///
+pub(crate) async fn drop_box(
+    headers: HeaderMap,
+    Extension(webhooks): Extension<Webhooks>,
+    Extension(cache): Extension<ListingCache>,
+    body: Bytes,
+) -> Result<StatusCode, AuthError> {
+    let secret = config_get()?
+        .drive_servers
+        .get(&DriveProvider::DropBox)
+        .map(|server| server.client_secret.expose_secret().clone())
+        .ok_or_else(|| AuthError::ConfigError("Missing DropBox configuration".into()))?;
+    verify_drop_box(
+        &secret,
+        &body,
+        headers
+            .get("X-Dropbox-Signature")
+            .and_then(|value| value.to_str().ok()),
+    )?;
+
+    let notification: DropBoxNotification = serde_json::from_slice(&body)?;
+
+    for account in notification.list_folder.accounts {
+        for project_id in webhooks
+            .projects_for_account(&DriveProvider::DropBox, &account)
+            .await?
+        {
+            notify(&webhooks, &cache, project_id, DriveProvider::DropBox).await?;
+        }
+    }
+    Ok(StatusCode::OK)
+}
+///
+/// 🔐 The signature is the HMAC-SHA256 of the body with the app secret (hex)
+///
+fn verify_drop_box(secret: &str, body: &[u8], signature: Option<&str>) -> Result<(), AuthError> {
+    let signature = signature
+        .and_then(|value| hex::decode(value).ok())
+        .ok_or_else(|| AuthError::Unauthorized("Missing signature".into()))?;
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .map_err(|err| AuthError::InternalError(err.to_string().into()))?;
+    mac.update(body);
+    mac.verify_slice(&signature)
+        .map_err(|_| AuthError::Unauthorized("Invalid signature".into()))
+}
+/* -------------------------------------------------------------------------------- */
+///
+/// The notifications do not specify the folder; drop all of the drive's
+/// cached listings, then publish the event.
+///
+async fn notify(
+    webhooks: &Webhooks,
+    cache: &ListingCache,
+    project_id: ProjectId,
+    drive_provider: DriveProvider,
+) -> Result<(), AuthError> {
+    cache.invalidate_all(&project_id, &drive_provider).await?;
+    webhooks
+        .publish(&DriveEvent::new(project_id, drive_provider))
+        .await
+}
+///
+/// 🔐 Compares the shared secrets in constant time
+///
+fn same_secret(expected: &str, received: &str) -> bool {
+    expected.as_bytes().ct_eq(received.as_bytes()).into()
+}
+fn plain_text(body: String) -> Response {
+    (
+        Headers([
+            (CONTENT_TYPE, "text/plain"),
+            (X_CONTENT_TYPE_OPTIONS, "nosniff"),
+        ]),
+        body,
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "app-secret";
+    const BODY: &str = r#"{"list_folder": {"accounts": ["dbid:AAH4f99T0taONIb-OurWxbNQ6ywGRopQngc"]}, "delta": {"users": [12345678]}}"#;
+    const SIGNATURE: &str = "4c5c4f66f44d1c4caf09bf8bb533cce0bfbf09ea119d0f1945e1a38bd6c54670";
+
+    #[test]
+    fn drop_box_notifications_are_signed_with_the_app_secret() {
+        assert!(verify_drop_box(SECRET, BODY.as_bytes(), Some(SIGNATURE)).is_ok());
+
+        let notification: DropBoxNotification = serde_json::from_str(BODY).unwrap();
+        assert_eq!(
+            vec!["dbid:AAH4f99T0taONIb-OurWxbNQ6ywGRopQngc".to_string()],
+            notification.list_folder.accounts
+        );
+    }
+
+    #[test]
+    fn drop_box_notifications_with_an_invalid_signature_are_rejected() {
+        let tampered = BODY.replace("12345678", "87654321");
+        for (secret, body, signature) in [
+            (SECRET, tampered.as_str(), Some(SIGNATURE)),
+            ("another-secret", BODY, Some(SIGNATURE)),
+            (SECRET, BODY, Some(&SIGNATURE[2..])),
+            (SECRET, BODY, Some("not hex")),
+            (SECRET, BODY, None),
+        ] {
+            assert!(matches!(
+                verify_drop_box(secret, body.as_bytes(), signature),
+                Err(AuthError::Unauthorized(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn secrets_are_compared_in_full() {
+        assert!(same_secret("a1b2c3", "a1b2c3"));
+        assert!(!same_secret("a1b2c3", "a1b2c4"));
+        assert!(!same_secret("a1b2c3", "a1b2c"));
+        assert!(!same_secret("a1b2c3", ""));
+    }
+
+    #[test]
+    fn graph_notifications_carry_the_subscription_secret() {
+        let body = r#"{
+            "value": [
+                {
+                    "subscriptionId": "7f105c7d-2dc5-4530-97cd-4e7ae6534c07",
+                    "clientState": "a1b2c3",
+                    "changeType": "updated",
+                    "resource": "me/drive/root",
+                    "tenantId": "bb8775a4-4d8c-42cf-a1d4-4d58c2bb668f"
+                },
+                {
+                    "subscriptionId": "1e2f5d3c-0a6b-4c7d-8e9f-a0b1c2d3e4f5",
+                    "changeType": "updated",
+                    "resource": "me/drive/root"
+                }
+            ]
+        }"#;
+        let GraphNotifications { value } = serde_json::from_str(body).unwrap();
+        assert_eq!(2, value.len());
+        assert_eq!(
+            "7f105c7d-2dc5-4530-97cd-4e7ae6534c07",
+            value[0].subscription_id
+        );
+        assert!(value[0].is_signed_with("a1b2c3"));
+        assert!(!value[0].is_signed_with("d4e5f6"));
+        // a notification without the secret is not trusted
+        assert!(!value[1].is_signed_with("a1b2c3"));
+    }
+
+    #[test]
+    fn the_google_channel_confirmation_is_skipped() {
+        assert!(!reports_changes("sync"));
+        for state in ["add", "remove", "update", "trash", "untrash", "change"] {
+            assert!(reports_changes(state), "{}", state);
+        }
+    }
+}
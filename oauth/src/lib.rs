@@ -1,4 +1,4 @@
-use async_redis_session::RedisSessionStore;
+use async_redis_session::RedisTopology;
 
 // use axum::error_handling::HandleErrorLayer;
 
@@ -6,13 +6,20 @@ use axum::extract::Extension;
 
 use axum::http::header::{HeaderValue, USER_AGENT};
 
+use axum_extra::middleware as axum_middleware;
+
 use axum::{
-    handler::Handler, http::StatusCode, response::IntoResponse, routing::get, AddExtensionLayer,
-    Router,
+    handler::Handler,
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{delete, get, post},
+    AddExtensionLayer, Router,
 };
 
 use secrecy::ExposeSecret;
 
+use std::future::Future;
+
 use tokio::sync::mpsc;
 
 use tower::ServiceBuilder;
@@ -39,6 +46,9 @@ mod errors;
 #[path = "state.rs"]
 mod state;
 
+#[path = "utils.rs"]
+mod utils;
+
 #[path = "config.rs"]
 pub mod config;
 
@@ -50,24 +60,65 @@ use crate::handlers::*;
 
 use crate::models::drive_clients;
 
+use crate::models::drive_cursor::DriveCursors;
+
+use crate::models::http_client::HttpClient;
+
+use crate::models::listing_cache::ListingCache;
+
+use crate::models::refresh_tokens::RefreshTokens;
+
+use crate::models::schema_cache::SchemaCache;
+
+use crate::models::session_store::AuthStore;
+
+use crate::models::webhooks::Webhooks;
+
 use crate::models::oauth_clients;
 
-pub fn app() -> Result<Router> {
+fn redis() -> Result<RedisTopology> {
     let redis_uri = config_get()?.options.redis_db.expose_secret().clone();
 
-    let redis_client = redis::Client::open(redis_uri).unwrap();
+    RedisTopology::open(&redis_uri)
+        .map_err(|err| AuthError::ConfigError(format!("redis_db: {}", err).into()))
+}
+
+pub fn app() -> Result<Router> {
+    let redis = redis()?;
+
+    let auth_store = AuthStore::new(&config_get()?.options, &redis)?;
+
+    let drive_cursors = DriveCursors::new(redis.clone());
 
-    let auth_store = RedisSessionStore::from_client(redis_client);
+    let listing_cache = ListingCache::new(redis.clone());
+
+    let schema_cache = SchemaCache::new(redis.clone());
+
+    let refresh_tokens = RefreshTokens::new(redis.clone(), &config_get()?.options)?;
+
+    let webhooks = Webhooks::new(redis);
 
     let oauth_clients = oauth_clients::init()?;
 
     let drive_clients = drive_clients::init()?;
 
+    let http_client = HttpClient::new(&config_get()?.options)?;
+
     let middleware_stack = ServiceBuilder::new()
         .layer(TraceLayer::new_for_http()) // tower_http=trace to activate
         .layer(AddExtensionLayer::new(auth_store))
         .layer(AddExtensionLayer::new(oauth_clients))
         .layer(AddExtensionLayer::new(drive_clients))
+        .layer(AddExtensionLayer::new(http_client))
+        .layer(AddExtensionLayer::new(drive_cursors))
+        .layer(AddExtensionLayer::new(listing_cache))
+        .layer(AddExtensionLayer::new(schema_cache))
+        .layer(AddExtensionLayer::new(refresh_tokens))
+        .layer(AddExtensionLayer::new(webhooks))
+        // the drive tokens refreshed by the handlers are returned in a header
+        .layer(axum_middleware::from_fn(middleware::drive_token::expose))
+        // the client's User-Agent is kept before it is overridden
+        .map_request(middleware::user_agent::keep)
         .layer(SetRequestHeaderLayer::overriding(
             USER_AGENT,
             HeaderValue::from_static("Luci Auth Service"),
@@ -94,18 +145,76 @@ pub fn app() -> Result<Router> {
             "/drive/authorized/:auth_provider",
             get(drive_authorized::handle),
         )
+        .route(
+            "/drive/:auth_provider/:project_id/drives",
+            get(drives::handle),
+        )
         // testing how to access files
         .route(
             "/drive/:auth_provider/:project_id/filesystem",
             get(filesystem::handle),
         )
+        .route(
+            "/drive/:auth_provider/:project_id/files",
+            post(upload::handle),
+        )
+        .route(
+            "/drive/:auth_provider/:project_id/files/:file_id",
+            get(file_meta::handle),
+        )
+        .route(
+            "/drive/:auth_provider/:project_id/files/:file_id/preview",
+            get(preview::handle),
+        )
+        .route(
+            "/drive/:auth_provider/:project_id/files/:file_id/schema",
+            get(schema::handle),
+        )
+        .route(
+            "/drive/:auth_provider/:project_id/changes",
+            get(changes::handle),
+        )
+        .route(
+            "/drive/:auth_provider/:project_id/search",
+            get(search::handle),
+        )
+        .route(
+            "/drive/:auth_provider/:project_id/subscriptions",
+            post(webhooks::subscribe),
+        )
+        .route("/webhooks/google", post(webhooks::google))
+        .route("/webhooks/msgraph", post(webhooks::ms_graph))
+        .route(
+            "/webhooks/dropbox",
+            get(webhooks::drop_box_challenge).post(webhooks::drop_box),
+        )
         .route("/api/logout", get(logout::handle))
+        .route(
+            "/api/sessions",
+            get(sessions::list).delete(sessions::revoke_all),
+        )
+        .route("/api/sessions/:session_id", delete(sessions::revoke))
         .fallback(handler_404.into_service())
         .layer(middleware_stack);
 
     Ok(router)
 }
 
+///
+/// ⏰ Renews the webhook subscriptions before they expire; spawn next to
+/// the server, and abort when the server stops
+///
+pub fn renewals() -> Result<impl Future<Output = ()>> {
+    let redis = redis()?;
+
+    Ok(webhooks::renew_subscriptions(
+        drive_clients::init()?,
+        HttpClient::new(&config_get()?.options)?,
+        RefreshTokens::new(redis.clone(), &config_get()?.options)?,
+        Webhooks::new(redis),
+    ))
+}
+
 // see https://kubernetes.io/docs/reference/using-api/health-checks/
 async fn health_check() -> impl IntoResponse {
     "ok"
@@ -70,6 +70,9 @@ async fn start_server(
         .unwrap();
     */
 
+    // ⏰ stopped with the server (the config is reloaded)
+    let renewals = tokio::spawn(oauth::renewals()?);
+
     // axum_server::bind_rustls(addr, certs)
     axum::Server::bind(&addr)
         .serve(
@@ -83,6 +86,7 @@ async fn start_server(
         })
         .await
         .ok();
+    renewals.abort();
     Ok(())
 }
 
@@ -0,0 +1,26 @@
+use axum::{
+    body::Body,
+    http::{HeaderValue, Request},
+    response::Response,
+};
+use axum_extra::middleware::Next;
+
+use crate::handlers::drive_api::{RefreshedToken, REFRESHED_TOKEN_HEADER};
+
+///
+/// 🔑 Return the drive access token refreshed while handling the request
+/// (see DriveAccess), so the client replaces the token it holds
+///
+pub async fn expose(mut req: Request<Body>, next: Next<Body>) -> Response {
+    let refreshed = RefreshedToken::default();
+    req.extensions_mut().insert(refreshed.clone());
+
+    let mut res = next.run(req).await;
+    if let Some(value) = refreshed
+        .take()
+        .and_then(|token| HeaderValue::from_str(&token).ok())
+    {
+        res.headers_mut().insert(REFRESHED_TOKEN_HEADER, value);
+    }
+    res
+}
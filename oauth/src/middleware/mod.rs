@@ -1 +1,3 @@
+pub mod drive_token;
 pub mod print_response;
+pub mod user_agent;
@@ -0,0 +1,22 @@
+use axum::http::header::USER_AGENT;
+use axum::http::Request;
+
+///
+/// The User-Agent of the client; the header is overridden (see lib.rs)
+/// before the handlers run.
+///
+#[derive(Debug, Clone, Default)]
+pub struct ClientUserAgent(pub Option<String>);
+
+///
+/// Keep the User-Agent of the client as a request extension
+///
+pub fn keep<B>(mut req: Request<B>) -> Request<B> {
+    let user_agent = req
+        .headers()
+        .get(USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    req.extensions_mut().insert(ClientUserAgent(user_agent));
+    req
+}
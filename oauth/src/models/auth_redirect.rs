@@ -1,5 +1,6 @@
 use axum::response::{IntoResponse, Redirect, Response};
 
+#[allow(dead_code)]
 pub struct AuthRedirect;
 
 impl IntoResponse for AuthRedirect {
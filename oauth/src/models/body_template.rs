@@ -0,0 +1,205 @@
+///
+/// Hosts the json body sent with a listing request (config `json_body_ls`),
+/// for providers that list files with a POST (e.g., DropBox list_folder).
+///
+/// The template is parsed when the config is loaded; string values may
+/// include placeholders:
+///
+/// 👉 `{path}` the folder listed ("" for the root)
+/// 👉 `{cursor}` the page cursor (null for the first page)
+/// 👉 `{limit}` the page size
+///
+/// 🔖 DropBox requests the pages that follow with list_folder/continue (see
+///    drive_api::with_page); the body is only sent for the first page.
+///
+/// A value that is only a placeholder is replaced with a typed value
+/// (e.g., `"{limit}"` -> 100); otherwise the placeholder is replaced in the
+/// text (e.g., `"/data{path}"`).
+///
+/// ```json
+/// { "path": "{path}", "limit": "{limit}", "include_non_downloadable_files": false }
+/// ```
+///
+use serde_json::Value;
+
+use crate::errors::AuthError;
+
+const PLACEHOLDERS: [&str; 3] = ["path", "cursor", "limit"];
+
+#[derive(Debug, Clone)]
+pub struct BodyTemplate(Value);
+
+///
+/// The values that replace the placeholders
+///
+#[derive(Debug, Clone)]
+pub struct BodyVars<'a> {
+    pub path: &'a str,
+    pub cursor: Option<&'a str>,
+    pub limit: u16,
+}
+
+impl BodyTemplate {
+    pub fn parse(template: &str) -> Result<Self, AuthError> {
+        let value: Value = serde_json::from_str(template).map_err(|err| {
+            AuthError::ConfigError(format!("json_body_ls is not valid json: {}", err).into())
+        })?;
+        let mut unknown = Vec::new();
+        visit_strings(&value, &mut |text| {
+            unknown.extend(
+                placeholders(text)
+                    .filter(|name| !PLACEHOLDERS.contains(name))
+                    .map(str::to_string),
+            )
+        });
+        if !unknown.is_empty() {
+            return Err(AuthError::ConfigError(
+                format!(
+                    "json_body_ls has unknown placeholders: {} (expected one of {:?})",
+                    unknown.join(", "),
+                    PLACEHOLDERS
+                )
+                .into(),
+            ));
+        }
+        Ok(BodyTemplate(value))
+    }
+    ///
+    /// The request pages using the body (rather than a separate request)
+    ///
+    pub fn has_cursor(&self) -> bool {
+        let mut found = false;
+        visit_strings(&self.0, &mut |text| {
+            found = found || placeholders(text).any(|name| name == "cursor")
+        });
+        found
+    }
+    pub fn render(&self, vars: &BodyVars) -> Value {
+        render(&self.0, vars)
+    }
+}
+fn render(value: &Value, vars: &BodyVars) -> Value {
+    match value {
+        Value::String(text) => match text.as_str() {
+            "{path}" => vars.path.into(),
+            "{cursor}" => vars.cursor.map_or(Value::Null, Value::from),
+            "{limit}" => vars.limit.into(),
+            _ => text
+                .replace("{path}", vars.path)
+                .replace("{cursor}", vars.cursor.unwrap_or_default())
+                .replace("{limit}", &vars.limit.to_string())
+                .into(),
+        },
+        Value::Array(values) => values.iter().map(|value| render(value, vars)).collect(),
+        Value::Object(entries) => entries
+            .iter()
+            .map(|(key, value)| (key.clone(), render(value, vars)))
+            .collect::<serde_json::Map<_, _>>()
+            .into(),
+        other => other.clone(),
+    }
+}
+fn visit_strings(value: &Value, f: &mut impl FnMut(&str)) {
+    match value {
+        Value::String(text) => f(text),
+        Value::Array(values) => values.iter().for_each(|value| visit_strings(value, f)),
+        Value::Object(entries) => entries.values().for_each(|value| visit_strings(value, f)),
+        _ => {}
+    }
+}
+///
+/// The names in `{name}`
+///
+fn placeholders(text: &str) -> impl Iterator<Item = &str> {
+    text.split('{').skip(1).filter_map(|part| {
+        part.split_once('}').map(|(name, _)| name).filter(|name| {
+            !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn placeholders_are_replaced_with_typed_values() {
+        let template = BodyTemplate::parse(
+            r#"{ "path": "{path}", "limit": "{limit}", "recursive": false, "tags": ["{path}"] }"#,
+        )
+        .unwrap();
+        let body = template.render(&BodyVars {
+            path: "/reports",
+            cursor: None,
+            limit: 100,
+        });
+        assert_eq!(
+            json!({ "path": "/reports", "limit": 100, "recursive": false, "tags": ["/reports"] }),
+            body
+        );
+    }
+
+    #[test]
+    fn placeholders_are_replaced_in_text() {
+        let template =
+            BodyTemplate::parse(r#"{ "path": "/data{path}", "note": "max {limit}" }"#).unwrap();
+        let body = template.render(&BodyVars {
+            path: "",
+            cursor: None,
+            limit: 25,
+        });
+        assert_eq!(json!({ "path": "/data", "note": "max 25" }), body);
+    }
+
+    #[test]
+    fn the_cursor_is_null_for_the_first_page() {
+        let template = BodyTemplate::parse(
+            r#"{ "path": "{path}", "cursor": "{cursor}", "limit": "{limit}" }"#,
+        )
+        .unwrap();
+        assert!(template.has_cursor());
+        let first = template.render(&BodyVars {
+            path: "/reports",
+            cursor: None,
+            limit: 100,
+        });
+        assert_eq!(
+            json!({ "path": "/reports", "cursor": null, "limit": 100 }),
+            first
+        );
+        let next = template.render(&BodyVars {
+            path: "/reports",
+            cursor: Some("AAEz9"),
+            limit: 100,
+        });
+        assert_eq!(json!("AAEz9"), next["cursor"]);
+    }
+
+    #[test]
+    fn unknown_placeholders_are_a_config_error() {
+        let template = BodyTemplate::parse(r#"{ "path": "{path}", "limit": "{limit}" }"#).unwrap();
+        assert!(!template.has_cursor());
+
+        let err = BodyTemplate::parse(r#"{ "path": "{folder}", "limit": "{limit}" }"#).unwrap_err();
+        assert!(matches!(err, AuthError::ConfigError(_)));
+        assert!(err.to_string().contains("folder"));
+    }
+
+    #[test]
+    fn invalid_json_is_a_config_error() {
+        let err = BodyTemplate::parse(r#"{ "path": "{path}" "#).unwrap_err();
+        assert!(matches!(err, AuthError::ConfigError(_)));
+    }
+
+    #[test]
+    fn braces_that_are_not_placeholders_are_kept() {
+        let template = BodyTemplate::parse(r#"{ "query": "{ } {a-b}" }"#).unwrap();
+        let body = template.render(&BodyVars {
+            path: "",
+            cursor: None,
+            limit: 1,
+        });
+        assert_eq!(json!({ "query": "{ } {a-b}" }), body);
+    }
+}
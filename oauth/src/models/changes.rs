@@ -0,0 +1,367 @@
+///
+/// Hosts the changes to a drive since the last sync (delta).
+///
+/// Provider changes -> Vec<Change> -> Changes
+///
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::models::drive_cursor::DriveCursor;
+use crate::models::files::{File, Kind, RawFileDropBox, RawFileGoogle, RawFileMSGraph};
+
+/* --------------------------------------------------------------------------------------------- */
+// Google
+/* --------------------------------------------------------------------------------------------- */
+///
+/// GET changes (one page)
+///
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct RawChangesGoogle {
+    pub changes: Vec<RawChangeGoogle>,
+    #[serde(rename = "nextPageToken")]
+    pub next_page_token: Option<String>,
+    // only on the last page
+    #[serde(rename = "newStartPageToken")]
+    pub new_start_page_token: Option<String>,
+}
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct RawChangeGoogle {
+    #[serde(default)]
+    removed: bool,
+    #[serde(rename = "fileId")]
+    file_id: String,
+    file: Option<RawFileGoogle>,
+}
+impl From<RawChangeGoogle> for Change {
+    fn from(change: RawChangeGoogle) -> Change {
+        match change.file {
+            Some(file) if !change.removed && !file.is_trashed() => Change::Upserted(file.into()),
+            Some(file) => Change::Deleted(file.into()),
+            None => Change::Deleted(File {
+                id: change.file_id,
+                ..File::default()
+            }),
+        }
+    }
+}
+/* --------------------------------------------------------------------------------------------- */
+// MSGraph
+/* --------------------------------------------------------------------------------------------- */
+///
+/// GET delta (one page)
+///
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct RawDeltaMSGraph {
+    pub value: Vec<RawFileMSGraph>,
+    #[serde(rename = "@odata.nextLink")]
+    pub next_link: Option<String>,
+    // only on the last page
+    #[serde(rename = "@odata.deltaLink")]
+    pub delta_link: Option<String>,
+}
+impl From<RawFileMSGraph> for Change {
+    fn from(item: RawFileMSGraph) -> Change {
+        if item.is_deleted() {
+            Change::Deleted(item.into())
+        } else {
+            Change::Upserted(item.into())
+        }
+    }
+}
+/* --------------------------------------------------------------------------------------------- */
+// DropBox
+/* --------------------------------------------------------------------------------------------- */
+///
+/// POST list_folder/continue (one page)
+///
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct RawListFolderDropBox {
+    pub entries: Vec<RawFileDropBox>,
+    pub cursor: String,
+    pub has_more: bool,
+}
+impl From<RawFileDropBox> for Change {
+    fn from(entry: RawFileDropBox) -> Change {
+        if entry.is_deleted() {
+            Change::Deleted(entry.into())
+        } else {
+            Change::Upserted(entry.into())
+        }
+    }
+}
+/* --------------------------------------------------------------------------------------------- */
+///
+/// Where a sync starts: the cursor, when the previous sync took place (to
+/// tell the added files from the modified), and the stored cursor that is
+/// advanced once the changes are retrieved
+///
+#[derive(Debug, Clone)]
+pub(crate) struct SyncStart {
+    pub cursor: String,
+    pub synced_at: Option<DateTime<Utc>>,
+    /// None when the request sends its own cursor (`since`)
+    pub stored: Option<DriveCursor>,
+}
+impl SyncStart {
+    ///
+    /// None when there is nothing to sync from (changes are not tracked yet)
+    ///
+    pub fn new(since: Option<String>, stored: Option<DriveCursor>) -> Option<SyncStart> {
+        match (since, stored) {
+            (Some(since), stored) => {
+                // only know when the sync took place for the stored cursor
+                let synced_at = stored
+                    .filter(|stored| stored.cursor == since)
+                    .map(|stored| stored.synced_at);
+                Some(SyncStart {
+                    cursor: since,
+                    synced_at,
+                    stored: None,
+                })
+            }
+            (None, Some(stored)) => Some(SyncStart {
+                cursor: stored.cursor.clone(),
+                synced_at: Some(stored.synced_at),
+                stored: Some(stored),
+            }),
+            (None, None) => None,
+        }
+    }
+}
+/* --------------------------------------------------------------------------------------------- */
+///
+/// A single change reported by a provider
+///
+#[derive(Debug, Clone)]
+pub(crate) enum Change {
+    Upserted(File),
+    Deleted(File),
+}
+///
+/// Changes implements Serialize (the returned/exported type)
+///
+/// Files created after the previous sync are `added`; files for which the
+/// provider does not report a create time are `modified`.
+///
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct Changes {
+    kind: Kind,
+    added: Vec<File>,
+    modified: Vec<File>,
+    deleted: Vec<File>,
+    /// use as `since` to retrieve the changes that follow
+    cursor: String,
+    /// more changes are available using the cursor
+    has_more: bool,
+}
+impl Changes {
+    pub fn new(
+        kind: Kind,
+        changes: Vec<Change>,
+        synced_at: Option<DateTime<Utc>>,
+        cursor: String,
+        has_more: bool,
+    ) -> Changes {
+        let mut result = Changes {
+            kind,
+            added: Vec::new(),
+            modified: Vec::new(),
+            deleted: Vec::new(),
+            cursor,
+            has_more,
+        };
+        for change in changes {
+            match change {
+                Change::Deleted(file) => result.deleted.push(file),
+                Change::Upserted(file) => match (file.created_time, synced_at) {
+                    (Some(created), Some(synced)) if created > synced => result.added.push(file),
+                    _ => result.modified.push(file),
+                },
+            }
+        }
+        result
+    }
+    ///
+    /// The cursor stored after the sync; the sync time only moves once all
+    /// of the changes are retrieved, so that the pages that follow are
+    /// compared with the previous sync
+    ///
+    pub fn next_cursor(&self, stored: &DriveCursor) -> DriveCursor {
+        if self.has_more {
+            DriveCursor {
+                cursor: self.cursor.clone(),
+                synced_at: stored.synced_at,
+            }
+        } else {
+            DriveCursor::new(self.cursor.clone())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use serde_json::json;
+
+    fn synced_at() -> DateTime<Utc> {
+        Utc.ymd(2022, 2, 1).and_hms(12, 0, 0)
+    }
+    fn names(files: &[File]) -> Vec<&str> {
+        files.iter().map(|file| file.name.as_str()).collect()
+    }
+
+    #[test]
+    fn google_changes_are_classified() {
+        let page: RawChangesGoogle = serde_json::from_value(json!({
+            "changes": [
+                { "fileId": "1", "file": { "id": "1", "name": "new.csv", "mimeType": "text/csv",
+                    "createdTime": "2022-02-01T13:00:00Z" } },
+                { "fileId": "2", "file": { "id": "2", "name": "old.csv", "mimeType": "text/csv",
+                    "createdTime": "2022-01-01T13:00:00Z" } },
+                { "fileId": "3", "file": { "id": "3", "name": "trashed.csv", "mimeType": "text/csv",
+                    "trashed": true } },
+                { "fileId": "4", "removed": true }
+            ],
+            "newStartPageToken": "42"
+        }))
+        .unwrap();
+        let changes = Changes::new(
+            Kind::Google,
+            page.changes.into_iter().map(Change::from).collect(),
+            Some(synced_at()),
+            "42".to_string(),
+            false,
+        );
+        assert_eq!(vec!["new.csv"], names(&changes.added));
+        assert_eq!(vec!["old.csv"], names(&changes.modified));
+        assert_eq!(vec!["trashed.csv", ""], names(&changes.deleted));
+        // removed files are only known by their id
+        assert_eq!("4", changes.deleted[1].id);
+    }
+
+    #[test]
+    fn ms_graph_changes_are_classified() {
+        let page: RawDeltaMSGraph = serde_json::from_value(json!({
+            "value": [
+                { "id": "01A", "name": "new.xlsx", "file": {},
+                    "createdDateTime": "2022-02-02T08:00:00Z" },
+                { "id": "01B", "name": "data", "folder": { "childCount": 1 },
+                    "createdDateTime": "2021-06-01T08:00:00Z" },
+                { "id": "01C", "deleted": { "state": "deleted" } }
+            ],
+            "@odata.deltaLink": "https://graph.microsoft.com/v1.0/me/drive/root/delta?token=x"
+        }))
+        .unwrap();
+        let changes = Changes::new(
+            Kind::MSGraph,
+            page.value.into_iter().map(Change::from).collect(),
+            Some(synced_at()),
+            page.delta_link.unwrap(),
+            false,
+        );
+        assert_eq!(vec!["new.xlsx"], names(&changes.added));
+        assert_eq!(vec!["data"], names(&changes.modified));
+        assert_eq!("01C", changes.deleted[0].id);
+    }
+
+    #[test]
+    fn drop_box_changes_are_modified_or_deleted() {
+        let page: RawListFolderDropBox = serde_json::from_value(json!({
+            "entries": [
+                { ".tag": "file", "id": "id:1", "name": "new.csv", "path_lower": "/new.csv",
+                    "server_modified": "2022-02-02T08:00:00Z" },
+                { ".tag": "deleted", "name": "old.csv", "path_lower": "/old.csv" }
+            ],
+            "cursor": "AAF",
+            "has_more": true
+        }))
+        .unwrap();
+        let changes = Changes::new(
+            Kind::DropBox,
+            page.entries.into_iter().map(Change::from).collect(),
+            Some(synced_at()),
+            page.cursor,
+            page.has_more,
+        );
+        // DropBox does not report a create time
+        assert!(changes.added.is_empty());
+        assert_eq!(vec!["new.csv"], names(&changes.modified));
+        assert_eq!(vec!["old.csv"], names(&changes.deleted));
+        assert!(changes.has_more);
+    }
+
+    #[test]
+    fn files_are_modified_without_the_previous_sync() {
+        let file = File {
+            name: "new.csv".to_string(),
+            created_time: Some(Utc::now()),
+            ..File::default()
+        };
+        let changes = Changes::new(
+            Kind::Google,
+            vec![Change::Upserted(file)],
+            None,
+            "42".to_string(),
+            false,
+        );
+        assert!(changes.added.is_empty());
+        assert_eq!(vec!["new.csv"], names(&changes.modified));
+    }
+
+    #[test]
+    fn syncs_start_from_the_stored_cursor() {
+        let stored = DriveCursor {
+            cursor: "10".to_string(),
+            synced_at: synced_at(),
+        };
+        assert!(SyncStart::new(None, None).is_none());
+
+        let start = SyncStart::new(None, Some(stored.clone())).unwrap();
+        assert_eq!("10", start.cursor);
+        assert_eq!(Some(synced_at()), start.synced_at);
+        assert!(start.stored.is_some());
+
+        // the request's own cursor is not stored
+        let start = SyncStart::new(Some("10".to_string()), Some(stored.clone())).unwrap();
+        assert_eq!(Some(synced_at()), start.synced_at);
+        assert!(start.stored.is_none());
+
+        // the sync time of another cursor is unknown
+        let start = SyncStart::new(Some("7".to_string()), Some(stored)).unwrap();
+        assert_eq!(("7", None), (start.cursor.as_str(), start.synced_at));
+        assert!(start.stored.is_none());
+        assert!(SyncStart::new(Some("7".to_string()), None)
+            .unwrap()
+            .stored
+            .is_none());
+    }
+
+    #[test]
+    fn the_sync_time_moves_with_the_last_page() {
+        let stored = DriveCursor {
+            cursor: "10".to_string(),
+            synced_at: synced_at(),
+        };
+        let page = Changes::new(
+            Kind::Google,
+            Vec::new(),
+            Some(synced_at()),
+            "11".to_string(),
+            true,
+        );
+        let next = page.next_cursor(&stored);
+        assert_eq!(("11", synced_at()), (next.cursor.as_str(), next.synced_at));
+
+        let last = Changes::new(
+            Kind::Google,
+            Vec::new(),
+            Some(synced_at()),
+            "12".to_string(),
+            false,
+        );
+        let next = last.next_cursor(&stored);
+        assert_eq!("12", next.cursor);
+        assert!(next.synced_at > synced_at());
+    }
+}
@@ -1,9 +1,11 @@
+use http::Method;
 use oauth2::{basic::BasicClient, AuthUrl, ClientId, ClientSecret, RedirectUrl, TokenUrl};
 use secrecy::ExposeSecret;
 use std::collections::HashMap;
 
-use crate::config::{config_get, tnc_authorized_drive_endpoint};
+use crate::config::{self, config_get, tnc_authorized_drive_endpoint};
 use crate::errors::AuthError;
+use crate::models::body_template::BodyTemplate;
 use crate::models::drive_provider::DriveProvider;
 
 ///
@@ -29,12 +31,15 @@ impl DriveClient {
 /// public
 #[derive(Debug, Clone)]
 pub struct FilesRequest {
-    pub method: String,
+    /// the listing method (GET or POST)
+    pub method: Method,
     pub drive_server: String,
     pub endpoint: String,
     pub query_ls: String,
     pub query_read: Option<String>,
-    pub json_body_ls: Option<String>,
+    /// the listing body (POST)
+    pub json_body_ls: Option<BodyTemplate>,
+    pub content_server: Option<String>,
 }
 #[derive(Debug, Clone)]
 pub struct DriveClients(pub HashMap<DriveProvider, DriveClient>);
@@ -77,16 +82,18 @@ pub fn init() -> Result<DriveClients, AuthError> {
                 ),
                 cfg.scopes.clone(),
                 FilesRequest {
-                    method: cfg
-                        .files_request
-                        .method
-                        .clone()
-                        .unwrap_or_else(|| "post".to_string()),
+                    method: list_method(drive_service, &cfg.files_request)?,
                     drive_server: cfg.files_request.drive_server.clone(),
                     endpoint: cfg.files_request.endpoint.clone(),
                     query_ls: cfg.files_request.query_ls.clone(),
                     query_read: cfg.files_request.query_read.clone(),
-                    json_body_ls: cfg.files_request.json_body_ls.clone(),
+                    json_body_ls: cfg
+                        .files_request
+                        .json_body_ls
+                        .as_deref()
+                        .map(BodyTemplate::parse)
+                        .transpose()?,
+                    content_server: cfg.files_request.content_server.clone(),
                 },
             ),
         );
@@ -95,3 +102,26 @@ pub fn init() -> Result<DriveClients, AuthError> {
 
     Ok(DriveClients(clients))
 }
+///
+/// The listing method; a body requires POST
+///
+fn list_method(
+    drive_service: &DriveProvider,
+    cfg: &config::FilesRequest,
+) -> Result<Method, AuthError> {
+    let method = cfg.method.as_deref().unwrap_or("post").to_uppercase();
+    match (method.as_str(), &cfg.json_body_ls) {
+        ("POST", _) => Ok(Method::POST),
+        ("GET", None) => Ok(Method::GET),
+        ("GET", Some(_)) => Err(AuthError::ConfigError(
+            format!("{}: json_body_ls requires the post method", drive_service).into(),
+        )),
+        _ => Err(AuthError::ConfigError(
+            format!(
+                "{}: unsupported files_request method {} (expected get or post)",
+                drive_service, method
+            )
+            .into(),
+        )),
+    }
+}
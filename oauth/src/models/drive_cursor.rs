@@ -0,0 +1,72 @@
+use async_redis_session::RedisTopology;
+///
+/// Hosts the per-project change cursors (delta sync) in redis.
+///
+use chrono::{DateTime, Utc};
+use redis::{Cmd, FromRedisValue};
+use serde::{Deserialize, Serialize};
+
+use crate::errors::AuthError;
+use crate::models::drive_provider::DriveProvider;
+use crate::models::project_id::ProjectId;
+
+const KEY_PREFIX: &str = "drive-cursors/";
+
+///
+/// The provider's cursor and when it was last used to sync
+///
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DriveCursor {
+    pub cursor: String,
+    pub synced_at: DateTime<Utc>,
+}
+impl DriveCursor {
+    pub fn new(cursor: String) -> Self {
+        DriveCursor {
+            cursor,
+            synced_at: Utc::now(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DriveCursors {
+    topology: RedisTopology,
+}
+impl DriveCursors {
+    pub fn new(topology: RedisTopology) -> Self {
+        DriveCursors { topology }
+    }
+    fn key(project_id: &ProjectId, drive_provider: &DriveProvider) -> String {
+        format!("{}{}/{}", KEY_PREFIX, project_id, drive_provider.to_path())
+    }
+    /// sends the command to the node that serves the key (Cluster), over the
+    /// connection shared by the stores
+    async fn query<T: FromRedisValue>(&self, key: &str, cmd: &Cmd) -> Result<T, AuthError> {
+        self.topology.query_for(key, cmd).await.map_err(store_error)
+    }
+    pub async fn get(
+        &self,
+        project_id: &ProjectId,
+        drive_provider: &DriveProvider,
+    ) -> Result<Option<DriveCursor>, AuthError> {
+        let key = Self::key(project_id, drive_provider);
+        let record: Option<String> = self.query(&key, &Cmd::get(&key)).await?;
+        Ok(record
+            .map(|value| serde_json::from_str(&value))
+            .transpose()?)
+    }
+    pub async fn set(
+        &self,
+        project_id: &ProjectId,
+        drive_provider: &DriveProvider,
+        cursor: &DriveCursor,
+    ) -> Result<(), AuthError> {
+        let key = Self::key(project_id, drive_provider);
+        self.query(&key, &Cmd::set(&key, serde_json::to_string(cursor)?))
+            .await
+    }
+}
+fn store_error(err: redis::RedisError) -> AuthError {
+    AuthError::StoreError(err.to_string().into())
+}
@@ -0,0 +1,307 @@
+///
+/// Hosts the error reported by a drive provider, normalized so that the
+/// user-agent can react to it (e.g., ask to reconnect, wait and retry).
+///
+/// 👉 Google: `error.errors[].reason`, `error.message`
+/// 👉 MSGraph: `error.code`, `error.message`
+/// 👉 DropBox: `error_summary` (e.g., `path/not_found/..`)
+///
+/// The body is parsed by its shape; the provider is not required.
+///
+use reqwest::StatusCode;
+use serde_json::Value;
+use strum_macros::Display;
+
+use crate::errors::AuthError;
+
+/// Text bodies (e.g., DropBox 400) are truncated to this length
+const MAX_MESSAGE: usize = 300;
+
+#[derive(Debug, Clone, Copy, Display, PartialEq, Eq)]
+pub enum DriveErrorKind {
+    NotFound,
+    Forbidden,
+    /// an item with the same name exists
+    Conflict,
+    /// storage (not request) quota
+    QuotaExceeded,
+    RateLimited,
+    TokenRevoked,
+    Other,
+}
+
+#[derive(Debug, Clone)]
+pub struct DriveError {
+    pub status: StatusCode,
+    /// the provider's code for the error
+    pub reason: Option<String>,
+    pub message: Option<String>,
+}
+impl DriveError {
+    pub fn parse(status: StatusCode, body: &str) -> Self {
+        let (reason, message) = match serde_json::from_str::<Value>(body) {
+            Ok(value) => {
+                let error = &value["error"];
+                let reason = error["errors"][0]["reason"]
+                    .as_str()
+                    .or_else(|| error["code"].as_str())
+                    .or_else(|| value["error_summary"].as_str())
+                    .or_else(|| error.as_str())
+                    .map(|reason| reason.trim_end_matches(&['.', '/'][..]).to_string());
+                let message = error["message"]
+                    .as_str()
+                    .or_else(|| value["error_description"].as_str())
+                    .map(ToString::to_string);
+                (reason, message)
+            }
+            Err(_) => {
+                let text = body.trim();
+                let message = (!text.is_empty()).then(|| text.chars().take(MAX_MESSAGE).collect());
+                (None, message)
+            }
+        };
+        DriveError {
+            status,
+            reason,
+            message,
+        }
+    }
+    ///
+    /// The provider's reason comes first; the status is used without one.
+    /// DropBox reports each of its endpoint errors with 409, so a 409 is only
+    /// a conflict when the provider reports no other reason.
+    ///
+    pub fn kind(&self) -> DriveErrorKind {
+        let reason = self
+            .reason
+            .as_deref()
+            .unwrap_or_default()
+            .replace('_', "")
+            .to_lowercase();
+        let has = |codes: &[&str]| codes.iter().any(|code| reason.contains(code));
+
+        if has(&[
+            "storagequotaexceeded",
+            "quotalimitreached",
+            "insufficientspace",
+            "insufficientquota",
+            "teamdrivefilelimitexceeded",
+        ]) || self.status == StatusCode::INSUFFICIENT_STORAGE
+        {
+            DriveErrorKind::QuotaExceeded
+        } else if has(&[
+            "ratelimitexceeded",
+            "dailylimitexceeded",
+            "quotaexceeded",
+            "toomanyrequests",
+            "toomanywriteoperations",
+            "activitylimitreached",
+            "throttledrequest",
+        ]) || self.status == StatusCode::TOO_MANY_REQUESTS
+        {
+            DriveErrorKind::RateLimited
+        } else if has(&[
+            "invalidaccesstoken",
+            "expiredaccesstoken",
+            "invalidauthenticationtoken",
+            "invalidgrant",
+            "autherror",
+        ]) || self.status == StatusCode::UNAUTHORIZED
+        {
+            DriveErrorKind::TokenRevoked
+        } else if has(&["notfound"]) || self.status == StatusCode::NOT_FOUND {
+            DriveErrorKind::NotFound
+        } else if has(&["namealreadyexists", "conflict"])
+            || (self.status == StatusCode::CONFLICT && self.reason.is_none())
+        {
+            DriveErrorKind::Conflict
+        } else if has(&[
+            "forbidden",
+            "accessdenied",
+            "insufficientpermissions",
+            "insufficientfilepermissions",
+            "appnotauthorizedtofile",
+            "nowritepermission",
+            "domainpolicy",
+        ]) || self.status == StatusCode::FORBIDDEN
+        {
+            DriveErrorKind::Forbidden
+        } else {
+            DriveErrorKind::Other
+        }
+    }
+}
+impl std::fmt::Display for DriveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.message, &self.reason) {
+            (Some(message), Some(reason)) => write!(f, "{} ({})", message, reason),
+            (Some(message), None) => write!(f, "{}", message),
+            (None, Some(reason)) => write!(f, "{}", reason),
+            (None, None) => write!(f, "{}", self.status),
+        }
+    }
+}
+impl From<DriveError> for AuthError {
+    fn from(err: DriveError) -> AuthError {
+        let message = err.to_string();
+        match err.kind() {
+            DriveErrorKind::NotFound => AuthError::DriveNotFound(message.into()),
+            DriveErrorKind::Forbidden => AuthError::DriveForbidden(message.into()),
+            DriveErrorKind::Conflict => AuthError::DriveConflict(message.into()),
+            DriveErrorKind::QuotaExceeded => AuthError::DriveQuotaExceeded(message.into()),
+            DriveErrorKind::RateLimited => AuthError::DriveRateLimited(message.into()),
+            DriveErrorKind::TokenRevoked => AuthError::Unauthorized(message.into()),
+            DriveErrorKind::Other => {
+                AuthError::DriveError(format!("{}: {}", err.status, message).into())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kind(status: u16, body: &str) -> DriveErrorKind {
+        DriveError::parse(StatusCode::from_u16(status).unwrap(), body).kind()
+    }
+
+    #[test]
+    fn google_errors_are_classified_by_reason() {
+        let body = r#"{
+            "error": {
+                "errors": [{
+                    "domain": "usageLimits",
+                    "reason": "userRateLimitExceeded",
+                    "message": "User Rate Limit Exceeded"
+                }],
+                "code": 403,
+                "message": "User Rate Limit Exceeded"
+            }
+        }"#;
+        let err = DriveError::parse(StatusCode::FORBIDDEN, body);
+        assert_eq!(Some("userRateLimitExceeded".to_string()), err.reason);
+        assert_eq!(DriveErrorKind::RateLimited, err.kind());
+        assert_eq!(
+            "User Rate Limit Exceeded (userRateLimitExceeded)",
+            err.to_string()
+        );
+
+        let quota = r#"{"error": {"errors": [{"reason": "storageQuotaExceeded"}], "code": 403, "message": "The user's Drive storage quota has been exceeded."}}"#;
+        assert_eq!(DriveErrorKind::QuotaExceeded, kind(403, quota));
+        let denied = r#"{"error": {"errors": [{"reason": "insufficientFilePermissions"}], "code": 403, "message": "The user does not have sufficient permissions for this file."}}"#;
+        assert_eq!(DriveErrorKind::Forbidden, kind(403, denied));
+        let missing = r#"{"error": {"errors": [{"reason": "notFound"}], "code": 404, "message": "File not found: 1a."}}"#;
+        assert_eq!(DriveErrorKind::NotFound, kind(404, missing));
+    }
+
+    #[test]
+    fn ms_graph_errors_are_classified_by_code() {
+        let body = r#"{
+            "error": {
+                "code": "nameAlreadyExists",
+                "message": "The specified item name already exists.",
+                "innerError": { "request-id": "9a1b" }
+            }
+        }"#;
+        assert_eq!(DriveErrorKind::Conflict, kind(409, body));
+
+        let throttled = r#"{"error": {"code": "activityLimitReached", "message": "The app or user has been throttled."}}"#;
+        assert_eq!(DriveErrorKind::RateLimited, kind(429, throttled));
+        let token = r#"{"error": {"code": "InvalidAuthenticationToken", "message": "Access token has expired or is not yet valid."}}"#;
+        assert_eq!(DriveErrorKind::TokenRevoked, kind(401, token));
+        let denied = r#"{"error": {"code": "accessDenied", "message": "Access denied"}}"#;
+        assert_eq!(DriveErrorKind::Forbidden, kind(403, denied));
+    }
+
+    #[test]
+    fn drop_box_errors_are_classified_by_summary() {
+        // DropBox reports each endpoint error with 409
+        let missing = r#"{"error_summary": "path/not_found/..", "error": {".tag": "path", "path": {".tag": "not_found"}}}"#;
+        let err = DriveError::parse(StatusCode::CONFLICT, missing);
+        assert_eq!(Some("path/not_found".to_string()), err.reason);
+        assert_eq!(DriveErrorKind::NotFound, err.kind());
+
+        let conflict = r#"{"error_summary": "path/conflict/file/...", "error": {".tag": "path", "reason": {".tag": "conflict"}}}"#;
+        assert_eq!(DriveErrorKind::Conflict, kind(409, conflict));
+        let full = r#"{"error_summary": "path/insufficient_space/..", "error": {".tag": "path"}}"#;
+        assert_eq!(DriveErrorKind::QuotaExceeded, kind(409, full));
+        let malformed = r#"{"error_summary": "path/malformed_path/.", "error": {".tag": "path"}}"#;
+        assert_eq!(DriveErrorKind::Other, kind(409, malformed));
+        let revoked = r#"{"error_summary": "expired_access_token/", "error": {".tag": "expired_access_token"}}"#;
+        assert_eq!(DriveErrorKind::TokenRevoked, kind(401, revoked));
+    }
+
+    #[test]
+    fn a_conflict_status_with_another_reason_is_not_a_conflict() {
+        let restricted =
+            r#"{"error_summary": "path/restricted_content/.", "error": {".tag": "path"}}"#;
+        assert_eq!(DriveErrorKind::Other, kind(409, restricted));
+        let moved =
+            r#"{"error_summary": "from_lookup/not_found/..", "error": {".tag": "from_lookup"}}"#;
+        assert_eq!(DriveErrorKind::NotFound, kind(409, moved));
+        let modified = r#"{"error": {"code": "resourceModified", "message": "The resource has changed since the caller last read it."}}"#;
+        assert_eq!(DriveErrorKind::Other, kind(409, modified));
+        // MSGraph names its conflicts
+        let exists =
+            r#"{"error": {"code": "nameAlreadyExists", "message": "Name already exists"}}"#;
+        assert_eq!(DriveErrorKind::Conflict, kind(409, exists));
+    }
+
+    #[test]
+    fn oauth_errors_are_classified() {
+        let body = r#"{"error": "invalid_grant", "error_description": "Token has been expired or revoked."}"#;
+        let err = DriveError::parse(StatusCode::BAD_REQUEST, body);
+        assert_eq!(DriveErrorKind::TokenRevoked, err.kind());
+        assert_eq!(
+            Some("Token has been expired or revoked.".to_string()),
+            err.message
+        );
+    }
+
+    #[test]
+    fn text_bodies_are_kept_as_the_message() {
+        // DropBox 400: a plain text body
+        let body = "Error in call to API function \"files/list_folder\": request body: unknown field 'limt'";
+        let err = DriveError::parse(StatusCode::BAD_REQUEST, body);
+        assert_eq!(None, err.reason);
+        assert_eq!(Some(body.to_string()), err.message);
+        assert_eq!(DriveErrorKind::Other, err.kind());
+
+        let long = "x".repeat(2 * MAX_MESSAGE);
+        let err = DriveError::parse(StatusCode::BAD_GATEWAY, &long);
+        assert_eq!(MAX_MESSAGE, err.message.unwrap().len());
+    }
+
+    #[test]
+    fn the_status_is_used_without_a_reason() {
+        assert_eq!(DriveErrorKind::NotFound, kind(404, ""));
+        assert_eq!(DriveErrorKind::Conflict, kind(409, "  "));
+        assert_eq!(
+            DriveErrorKind::RateLimited,
+            kind(429, "<html>Too Many Requests</html>")
+        );
+        assert_eq!(DriveErrorKind::QuotaExceeded, kind(507, ""));
+        assert_eq!(DriveErrorKind::Forbidden, kind(403, "{}"));
+        assert_eq!(DriveErrorKind::Other, kind(500, "{\"error\": {}}"));
+        assert_eq!(
+            "500 Internal Server Error",
+            DriveError::parse(StatusCode::INTERNAL_SERVER_ERROR, "").to_string()
+        );
+    }
+
+    #[test]
+    fn errors_are_mapped_to_auth_errors() {
+        let conflict = r#"{"error": {"code": "nameAlreadyExists", "message": "The specified item name already exists."}}"#;
+        let err: AuthError = DriveError::parse(StatusCode::CONFLICT, conflict).into();
+        assert!(matches!(err, AuthError::DriveConflict(_)));
+
+        let err: AuthError = DriveError::parse(StatusCode::BAD_GATEWAY, "upstream").into();
+        match err {
+            AuthError::DriveError(message) => {
+                assert_eq!(Some("502 Bad Gateway: upstream".to_string()), message.0)
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,381 @@
+///
+/// Hosts the drives available to the user (personal and shared)
+///
+/// 👉 Google: My Drive and the Shared Drives
+/// 👉 MSGraph: OneDrive and the SharePoint document libraries
+/// 👉 DropBox: the home and team spaces, and the shared (team) folders
+///
+/// The `drive_id` is used to list (and read from) a drive other than the
+/// user's personal drive.
+///
+use serde::{Deserialize, Serialize};
+use strum_macros::Display;
+
+use crate::models::drive_provider::DriveProvider;
+
+/// Google's alias for the user's My Drive
+pub const GOOGLE_MY_DRIVE: &str = "root";
+
+#[derive(Debug, Clone, Copy, Display, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum DriveKind {
+    /// My Drive, OneDrive, the DropBox home folder
+    Personal,
+    /// Google Shared Drive, OneDrive shared with the user
+    Shared,
+    /// SharePoint document library
+    Library,
+    /// DropBox team space and team (shared) folders
+    Team,
+}
+
+///
+/// Drive implements Serialize (the returned/exported type)
+///
+#[derive(Debug, Clone, Serialize)]
+pub struct Drive {
+    pub id: String,
+    pub name: String,
+    pub kind: DriveKind,
+    /// SharePoint: the site hosting the library
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub site: Option<String>,
+}
+///
+/// Drives implements Serialize (the returned/exported type)
+///
+#[derive(Debug, Clone, Serialize)]
+pub struct Drives {
+    pub drive_provider: DriveProvider,
+    pub drives: Vec<Drive>,
+}
+
+///
+/// Query parameter shared by the routes that read from a drive
+///
+#[derive(Debug, Default, Deserialize)]
+pub struct DriveParams {
+    /// the id of a drive listed by the drives endpoint; the user's
+    /// personal drive when not specified
+    pub drive_id: Option<String>,
+}
+impl DriveParams {
+    /// The drive other than the personal drive, if any
+    pub fn shared(&self) -> Option<&str> {
+        self.drive_id
+            .as_deref()
+            .filter(|drive_id| !drive_id.is_empty() && *drive_id != GOOGLE_MY_DRIVE)
+    }
+}
+/* --------------------------------------------------------------------------------------------- */
+// Google
+/* --------------------------------------------------------------------------------------------- */
+///
+/// GET drives (one page)
+///
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct RawDrivesGoogle {
+    #[serde(default)]
+    pub drives: Vec<RawDriveGoogle>,
+    #[serde(rename = "nextPageToken")]
+    pub next_page_token: Option<String>,
+}
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct RawDriveGoogle {
+    id: String,
+    name: String,
+}
+impl From<RawDriveGoogle> for Drive {
+    fn from(drive: RawDriveGoogle) -> Drive {
+        Drive {
+            id: drive.id,
+            name: drive.name,
+            kind: DriveKind::Shared,
+            site: None,
+        }
+    }
+}
+/* --------------------------------------------------------------------------------------------- */
+// MSGraph
+/* --------------------------------------------------------------------------------------------- */
+///
+/// GET me/drives, sites/{id}/drives
+///
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct RawDrivesMSGraph {
+    #[serde(default)]
+    pub value: Vec<RawDriveMSGraph>,
+}
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct RawDriveMSGraph {
+    id: String,
+    #[serde(default)]
+    name: String,
+    /// personal, business or documentLibrary
+    #[serde(rename = "driveType", default)]
+    drive_type: String,
+}
+impl RawDriveMSGraph {
+    pub fn into_drive(self, site: Option<String>) -> Drive {
+        let kind = match (self.drive_type.as_str(), &site) {
+            (_, Some(_)) | ("documentLibrary", _) => DriveKind::Library,
+            _ => DriveKind::Personal,
+        };
+        Drive {
+            id: self.id,
+            name: self.name,
+            kind,
+            site,
+        }
+    }
+}
+///
+/// GET sites?search=*
+///
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct RawSitesMSGraph {
+    #[serde(default)]
+    pub value: Vec<RawSiteMSGraph>,
+}
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct RawSiteMSGraph {
+    pub id: String,
+    #[serde(rename = "displayName", default)]
+    pub display_name: String,
+}
+/* --------------------------------------------------------------------------------------------- */
+// DropBox
+/* --------------------------------------------------------------------------------------------- */
+///
+/// POST users/get_current_account
+///
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct RawAccountDropBox {
+    pub root_info: RawRootInfoDropBox,
+}
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct RawRootInfoDropBox {
+    /// team or user
+    #[serde(rename = ".tag")]
+    pub tag: String,
+    pub root_namespace_id: String,
+    pub home_namespace_id: String,
+}
+impl From<RawAccountDropBox> for Vec<Drive> {
+    fn from(account: RawAccountDropBox) -> Vec<Drive> {
+        let root_info = account.root_info;
+        let mut drives = vec![Drive {
+            id: root_info.home_namespace_id.clone(),
+            name: "Home".to_string(),
+            kind: DriveKind::Personal,
+            site: None,
+        }];
+        if root_info.tag == "team" && root_info.root_namespace_id != root_info.home_namespace_id {
+            drives.push(Drive {
+                id: root_info.root_namespace_id,
+                name: "Team space".to_string(),
+                kind: DriveKind::Team,
+                site: None,
+            });
+        }
+        drives
+    }
+}
+///
+/// POST sharing/list_folders
+///
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct RawSharedFoldersDropBox {
+    #[serde(default)]
+    pub entries: Vec<RawSharedFolderDropBox>,
+}
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct RawSharedFolderDropBox {
+    shared_folder_id: String,
+    name: String,
+    #[serde(default)]
+    is_team_folder: bool,
+}
+impl From<RawSharedFolderDropBox> for Drive {
+    fn from(folder: RawSharedFolderDropBox) -> Drive {
+        Drive {
+            id: folder.shared_folder_id,
+            name: folder.name,
+            kind: if folder.is_team_folder {
+                DriveKind::Team
+            } else {
+                DriveKind::Shared
+            },
+            site: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn summary(drives: &[Drive]) -> Vec<(&str, &str, DriveKind, Option<&str>)> {
+        drives
+            .iter()
+            .map(|drive| {
+                (
+                    drive.id.as_str(),
+                    drive.name.as_str(),
+                    drive.kind,
+                    drive.site.as_deref(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn google_shared_drives_are_normalized() {
+        let page: RawDrivesGoogle = serde_json::from_value(json!({
+            "kind": "drive#driveList",
+            "nextPageToken": "page-2",
+            "drives": [
+                { "kind": "drive#drive", "id": "0AF3kQ", "name": "Finance" },
+                { "kind": "drive#drive", "id": "0AB7pZ", "name": "Research" }
+            ]
+        }))
+        .unwrap();
+        assert_eq!(Some("page-2".to_string()), page.next_page_token);
+        let drives: Vec<Drive> = page.drives.into_iter().map(Drive::from).collect();
+        assert_eq!(
+            vec![
+                ("0AF3kQ", "Finance", DriveKind::Shared, None),
+                ("0AB7pZ", "Research", DriveKind::Shared, None),
+            ],
+            summary(&drives)
+        );
+
+        // the last page, without Shared Drives
+        let page: RawDrivesGoogle =
+            serde_json::from_value(json!({ "kind": "drive#driveList" })).unwrap();
+        assert!(page.drives.is_empty());
+        assert!(page.next_page_token.is_none());
+    }
+
+    #[test]
+    fn ms_graph_drives_and_libraries_are_normalized() {
+        let drives: RawDrivesMSGraph = serde_json::from_value(json!({
+            "value": [
+                { "id": "b!personal", "name": "OneDrive", "driveType": "business" },
+                { "id": "b!library", "name": "Documents", "driveType": "documentLibrary" }
+            ]
+        }))
+        .unwrap();
+        let drives: Vec<Drive> = drives
+            .value
+            .into_iter()
+            .map(|drive| drive.into_drive(None))
+            .collect();
+        assert_eq!(
+            vec![
+                ("b!personal", "OneDrive", DriveKind::Personal, None),
+                ("b!library", "Documents", DriveKind::Library, None),
+            ],
+            summary(&drives)
+        );
+
+        let sites: RawSitesMSGraph = serde_json::from_value(json!({
+            "value": [{
+                "id": "contoso.sharepoint.com,2C712604-1370-44E7-A1F5-426573FDA80A,2D2244C3-251A-49EA-93A8-39E1C3A060FE",
+                "displayName": "Marketing",
+                "webUrl": "https://contoso.sharepoint.com/sites/marketing"
+            }]
+        }))
+        .unwrap();
+        let site = &sites.value[0];
+        assert_eq!("Marketing", site.display_name);
+        let libraries: RawDrivesMSGraph = serde_json::from_value(json!({
+            "value": [{ "id": "b!campaigns", "name": "Campaigns", "driveType": "documentLibrary" }]
+        }))
+        .unwrap();
+        let libraries: Vec<Drive> = libraries
+            .value
+            .into_iter()
+            .map(|drive| drive.into_drive(Some(site.display_name.clone())))
+            .collect();
+        assert_eq!(
+            vec![(
+                "b!campaigns",
+                "Campaigns",
+                DriveKind::Library,
+                Some("Marketing")
+            )],
+            summary(&libraries)
+        );
+    }
+
+    #[test]
+    fn drop_box_team_spaces_and_folders_are_normalized() {
+        let account: RawAccountDropBox = serde_json::from_value(json!({
+            "account_id": "dbid:AAH4f99T0taONIb-OurWxbNQ6ywGRopQngc",
+            "root_info": {
+                ".tag": "team",
+                "root_namespace_id": "3235641",
+                "home_namespace_id": "3235642",
+                "home_path": "/Franz Ferdinand"
+            }
+        }))
+        .unwrap();
+        let mut drives: Vec<Drive> = account.into();
+
+        let folders: RawSharedFoldersDropBox = serde_json::from_value(json!({
+            "entries": [
+                { "shared_folder_id": "84528192421", "name": "Marketing", "is_team_folder": true,
+                    "access_type": { ".tag": "editor" } },
+                { "shared_folder_id": "84528192422", "name": "Holidays",
+                    "access_type": { ".tag": "viewer" } }
+            ],
+            "cursor": "ZtkX9_EHj3x7PMkVuFIhwKYXEpwpLwyxp9vMKomUhllil9q7eWiAu"
+        }))
+        .unwrap();
+        drives.extend(folders.entries.into_iter().map(Drive::from));
+        assert_eq!(
+            vec![
+                ("3235642", "Home", DriveKind::Personal, None),
+                ("3235641", "Team space", DriveKind::Team, None),
+                ("84528192421", "Marketing", DriveKind::Team, None),
+                ("84528192422", "Holidays", DriveKind::Shared, None),
+            ],
+            summary(&drives)
+        );
+    }
+
+    #[test]
+    fn drop_box_personal_accounts_only_have_a_home() {
+        let account: RawAccountDropBox = serde_json::from_value(json!({
+            "root_info": {
+                ".tag": "user",
+                "root_namespace_id": "3235641",
+                "home_namespace_id": "3235641"
+            }
+        }))
+        .unwrap();
+        let drives: Vec<Drive> = account.into();
+        assert_eq!(
+            vec![("3235641", "Home", DriveKind::Personal, None)],
+            summary(&drives)
+        );
+    }
+
+    #[test]
+    fn the_personal_drive_is_not_shared() {
+        for (drive_id, expected) in [
+            (None, None),
+            (Some(""), None),
+            (Some(GOOGLE_MY_DRIVE), None),
+            (Some("0AF3kQ"), Some("0AF3kQ")),
+        ] {
+            let params = DriveParams {
+                drive_id: drive_id.map(str::to_string),
+            };
+            assert_eq!(expected, params.shared());
+        }
+    }
+}
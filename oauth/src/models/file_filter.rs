@@ -0,0 +1,167 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use crate::config::config_get;
+use crate::errors::AuthError;
+use crate::models::files::File;
+
+/// The filter that is always available, even when not configured
+pub const TABULAR: &str = "tabular";
+
+const GOOGLE_FOLDER: &str = "application/vnd.google-apps.folder";
+
+///
+/// Sets of mime types and extensions used to limit which files are listed.
+/// Folders are always kept so that the user can navigate.
+///
+/// Configured by name in the `file_filters` section of the .toml files;
+/// requested using `?only=<name>`.
+///
+#[derive(Debug, Clone, Deserialize)]
+pub struct FileFilter {
+    #[serde(default)]
+    pub mime_types: Vec<String>,
+    #[serde(default)]
+    pub extensions: Vec<String>,
+}
+pub type FileFilters = HashMap<String, FileFilter>;
+
+impl FileFilter {
+    ///
+    /// The files that can be used as inputs to a data-join
+    ///
+    pub fn tabular() -> Self {
+        FileFilter {
+            mime_types: [
+                "text/csv",
+                "text/tab-separated-values",
+                "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+                "application/vnd.ms-excel",
+                "application/vnd.apache.parquet",
+                "application/x-parquet",
+                "application/json",
+                "application/vnd.google-apps.spreadsheet",
+            ]
+            .iter()
+            .map(ToString::to_string)
+            .collect(),
+            extensions: ["csv", "tsv", "xlsx", "xls", "parquet", "json"]
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+        }
+    }
+    ///
+    /// Lookup the filter by name; configured values take precedence
+    ///
+    pub fn from_name(name: &str) -> Result<Self, AuthError> {
+        match config_get()?.file_filters.get(name) {
+            Some(filter) => Ok(filter.clone()),
+            None if name == TABULAR => Ok(FileFilter::tabular()),
+            None => Err(AuthError::MissingParameter(
+                format!("Unsupported file filter: {}", name).into(),
+            )),
+        }
+    }
+    pub fn is_match(&self, file: &File) -> bool {
+        file.is_directory
+            || self.mime_types.iter().any(|mime| mime == &file.mime_type)
+            || file.name.rsplit_once('.').map_or(false, |(_, extension)| {
+                self.extensions
+                    .iter()
+                    .any(|configured| configured.eq_ignore_ascii_case(extension))
+            })
+    }
+    ///
+    /// The mimeType clause for a Google `q` query
+    ///
+    pub fn google_clause(&self) -> String {
+        let clause = std::iter::once(GOOGLE_FOLDER)
+            .chain(self.mime_types.iter().map(String::as_str))
+            .map(|mime| format!("mimeType = '{}'", mime))
+            .collect::<Vec<_>>()
+            .join(" or ");
+        format!("({})", clause)
+    }
+}
+///
+/// Query parameter shared by the listing endpoints
+///
+#[derive(Debug, Default, Deserialize)]
+pub struct FilterParams {
+    pub only: Option<String>,
+}
+impl FilterParams {
+    pub fn filter(&self) -> Result<Option<FileFilter>, AuthError> {
+        self.only.as_deref().map(FileFilter::from_name).transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::files::FileKind;
+
+    fn file(name: &str, mime_type: &str) -> File {
+        File {
+            name: name.to_string(),
+            mime_type: mime_type.to_string(),
+            ..File::default()
+        }
+    }
+
+    #[test]
+    fn tabular_files_are_matched_by_mime_type_or_extension() {
+        let filter = FileFilter::tabular();
+        assert!(filter.is_match(&file("sales", "text/csv")));
+        assert!(filter.is_match(&file("Budget", "application/vnd.google-apps.spreadsheet")));
+        // DropBox: derived from the extension; uppercase extensions
+        assert!(filter.is_match(&file("SALES.CSV", "application/octet-stream")));
+        assert!(filter.is_match(&file("archive.2022.parquet", "")));
+    }
+
+    #[test]
+    fn other_files_are_not_matched() {
+        let filter = FileFilter::tabular();
+        assert!(!filter.is_match(&file("report.pdf", "application/pdf")));
+        assert!(!filter.is_match(&file("csv", "application/octet-stream")));
+        assert!(!filter.is_match(&file("notes.csv.bak", "")));
+        assert!(!filter.is_match(&file("", "")));
+    }
+
+    #[test]
+    fn folders_are_kept() {
+        let folder = File {
+            kind: FileKind::Folder,
+            is_directory: true,
+            ..file("reports", GOOGLE_FOLDER)
+        };
+        let filter = FileFilter {
+            mime_types: Vec::new(),
+            extensions: vec!["txt".to_string()],
+        };
+        assert!(filter.is_match(&folder));
+    }
+
+    #[test]
+    fn configured_extensions_ignore_case() {
+        let filter = FileFilter {
+            mime_types: Vec::new(),
+            extensions: vec!["CSV".to_string()],
+        };
+        assert!(filter.is_match(&file("sales.csv", "")));
+        assert!(filter.is_match(&file("sales.Csv", "")));
+    }
+
+    #[test]
+    fn the_google_clause_keeps_folders() {
+        let filter = FileFilter {
+            mime_types: vec!["text/csv".to_string()],
+            extensions: Vec::new(),
+        };
+        assert_eq!(
+            "(mimeType = 'application/vnd.google-apps.folder' or mimeType = 'text/csv')",
+            filter.google_clause()
+        );
+    }
+}
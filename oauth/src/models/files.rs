@@ -24,8 +24,11 @@
 //   }
 // }
 use crate::models::drive_provider::DriveProvider;
-use chrono::NaiveDateTime;
+use crate::utils::deserialize_option_number_from_string;
+use chrono::{DateTime, Utc};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
 use serde::{Deserialize, Serialize};
+use strum_macros::Display;
 ///
 /// Root value for raw data?
 ///
@@ -33,6 +36,31 @@ use serde::{Deserialize, Serialize};
 pub(crate) struct RawFiles<RF> {
     #[serde(alias = "files", alias = "value", alias = "entries")]
     inner: Vec<RF>,
+    // paging: Google, MSGraph (a url), DropBox
+    #[serde(alias = "nextPageToken", alias = "@odata.nextLink", alias = "cursor")]
+    next_page: Option<String>,
+    // DropBox always returns a cursor
+    has_more: Option<bool>,
+}
+impl<RF> RawFiles<RF> {
+    pub fn new(inner: Vec<RF>, next_page: Option<String>) -> Self {
+        RawFiles {
+            inner,
+            next_page,
+            has_more: None,
+        }
+    }
+    /// The token (or url) required to request the next page
+    pub fn next_page(&self) -> Option<String> {
+        match self.has_more {
+            Some(false) => None,
+            _ => self.next_page.clone(),
+        }
+    }
+    fn map<T>(self, f: impl FnMut(RF) -> T) -> RawFiles<T> {
+        let next_page = self.next_page();
+        RawFiles::new(self.inner.into_iter().map(f).collect(), next_page)
+    }
 }
 impl<RF> IntoIterator for RawFiles<RF>
 where
@@ -55,6 +83,14 @@ pub(crate) struct Files {
     path: Option<String>,
     drive_id: Option<String>,
     files: Vec<File>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_page: Option<String>,
+}
+impl Files {
+    /// Keep only the files that satisfy the predicate
+    pub fn retain(&mut self, predicate: impl FnMut(&File) -> bool) {
+        self.files.retain(predicate);
+    }
 }
 #[derive(Debug, Clone)]
 pub(crate) struct FilesBuilder {
@@ -89,12 +125,29 @@ impl FilesBuilder {
     }
     // convert RawFiles -> Files
     pub fn build(self) -> Files {
+        let path = self.path;
+        let next_page = self.raw_files.next_page();
+        // Does not need to be generic because uses Vec
+        let files = self
+            .raw_files
+            .into_iter()
+            .map(Into::into)
+            .map(|mut file: File| {
+                // fill-in the path when the provider does not report it
+                if file.path.is_none() {
+                    file.path = path
+                        .as_ref()
+                        .map(|parent| format!("{}/{}", parent.trim_end_matches('/'), file.name));
+                }
+                file
+            })
+            .collect();
         Files {
             kind: self.kind,
-            path: self.path,
+            path,
             drive_id: self.drive_id,
-            // Does not need to be generic because uses Vec
-            files: self.raw_files.into_iter().map(Into::into).collect(),
+            files,
+            next_page,
         }
     }
 }
@@ -106,36 +159,78 @@ impl FilesBuilder {
 /// RawFileGoogle -> File
 /// implements Deserialize (the intake type)
 ///
+/// 🔖 The `fields` parameter in the configured query must request the optional
+///    values (e.g., parents, webViewLink, md5Checksum) for them to be populated.
+///
 #[derive(Debug, Clone, Deserialize)]
 pub(crate) struct RawFileGoogle {
     id: String,
     #[serde(rename = "mimeType")]
     mime_type: String,
 
-    // todo chono NaiveDateTime (has Z)
     #[serde(rename = "createdTime")]
-    created_time: String,
+    created_time: Option<DateTime<Utc>>,
     #[serde(rename = "modifiedTime")]
-    modified_time: String,
+    modified_time: Option<DateTime<Utc>>,
 
     name: String,
-    size: Option<String>,
+    // Google encodes int64 values as strings
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    size: Option<u64>,
+
+    #[serde(default)]
+    parents: Vec<String>,
+    #[serde(rename = "webViewLink")]
+    web_view_link: Option<String>,
+
+    #[serde(rename = "md5Checksum")]
+    md5_checksum: Option<String>,
+    #[serde(rename = "sha1Checksum")]
+    sha1_checksum: Option<String>,
+    #[serde(rename = "sha256Checksum")]
+    sha256_checksum: Option<String>,
+    // only binary files have revisions
+    #[serde(rename = "headRevisionId")]
+    head_revision_id: Option<String>,
+    #[serde(default)]
+    trashed: bool,
 }
 impl RawFileGoogle {
-    fn is_dir(&self) -> bool {
-        self.mime_type.eq("application/vnd.google-apps.folder")
+    pub fn is_trashed(&self) -> bool {
+        self.trashed
+    }
+    fn kind(&self) -> FileKind {
+        match self.mime_type.as_str() {
+            "application/vnd.google-apps.folder" => FileKind::Folder,
+            "application/vnd.google-apps.shortcut" => FileKind::Shortcut,
+            _ => FileKind::File,
+        }
     }
 }
 impl From<RawFileGoogle> for File {
     fn from(fd: RawFileGoogle) -> File {
+        let kind = fd.kind();
         File {
-            id: fd.id.clone(),
-            name: fd.name.clone(),
-            is_directory: fd.is_dir(),
-            mime_type: fd.mime_type.clone(),
-            created_time: Some(fd.created_time.clone()),
-            modified_time: Some(fd.modified_time.clone()),
+            id: fd.id,
+            name: fd.name,
+            kind,
+            is_directory: kind == FileKind::Folder,
+            mime_type: fd.mime_type,
             size: fd.size,
+            created_time: fd.created_time,
+            modified_time: fd.modified_time,
+            // a file can only have one parent (since Sept 2020)
+            parent_id: fd.parents.into_iter().next(),
+            // Google does not report paths; set when building Files
+            path: None,
+            web_view_link: fd.web_view_link,
+            revision: fd.head_revision_id,
+            hashes: ContentHashes {
+                md5: fd.md5_checksum,
+                sha1: fd.sha1_checksum,
+                sha256: fd.sha256_checksum,
+                ..ContentHashes::default()
+            },
         }
     }
 }
@@ -148,46 +243,121 @@ impl From<RawFileGoogle> for File {
 #[derive(Debug, Clone, Deserialize)]
 pub(crate) struct RawFileMSGraph {
     #[serde(rename = "createdDateTime")]
-    created_time: String,
+    created_time: Option<DateTime<Utc>>,
     #[serde(rename = "lastModifiedDateTime")]
-    modified_time: String,
+    modified_time: Option<DateTime<Utc>>,
 
     id: String,
+    // deleted items may not report a name
+    #[serde(default)]
     name: String,
 
-    #[serde(flatten)]
-    file_or_folder: MSGraphFileOrFolder,
+    file: Option<MSGraphFile>,
+    folder: Option<MSGraphFolder>,
+    // item shared from another drive
+    #[serde(rename = "remoteItem")]
+    remote_item: Option<serde_json::Value>,
+
+    size: Option<u64>,
+
+    #[serde(rename = "parentReference")]
+    parent_reference: Option<MSGraphParentReference>,
+    #[serde(rename = "webUrl")]
+    web_url: Option<String>,
+    // changes only when the content changes (vs eTag)
+    #[serde(rename = "cTag")]
+    c_tag: Option<String>,
+    // delta: the item was removed
+    deleted: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct MSGraphFile {
+    #[serde(rename = "mimeType")]
+    mime_type: Option<String>,
+    #[serde(default)]
+    hashes: MSGraphHashes,
+}
 
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct MSGraphFolder {
+    #[serde(rename = "childCount")]
     #[allow(dead_code)]
-    size: Option<u32>,
+    child_count: Option<u64>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+pub(crate) struct MSGraphHashes {
+    #[serde(rename = "quickXorHash")]
+    quick_xor_hash: Option<String>,
+    #[serde(rename = "sha1Hash")]
+    sha1_hash: Option<String>,
+    #[serde(rename = "sha256Hash")]
+    sha256_hash: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
-#[serde(rename_all = "lowercase")]
-pub(crate) enum MSGraphFileOrFolder {
-    File {
-        #[serde(rename = "mimeType")]
-        mime_type: String,
-    },
-    Folder {},
+pub(crate) struct MSGraphParentReference {
+    id: Option<String>,
+    // e.g., /drive/root:/folder
+    path: Option<String>,
+}
+
+impl RawFileMSGraph {
+    pub fn is_deleted(&self) -> bool {
+        self.deleted.is_some()
+    }
+    fn kind(&self) -> FileKind {
+        if self.folder.is_some() {
+            FileKind::Folder
+        } else if self.remote_item.is_some() && self.file.is_none() {
+            FileKind::Shortcut
+        } else {
+            FileKind::File
+        }
+    }
+    /// Graph reports the parent as "/drive/root:/path"; strip the drive prefix.
+    fn path(&self) -> Option<String> {
+        self.parent_reference
+            .as_ref()
+            .and_then(|parent| parent.path.as_ref())
+            .map(|parent| {
+                let parent = parent.split_once(':').map_or("", |(_, rest)| rest);
+                format!("{}/{}", parent.trim_end_matches('/'), self.name)
+            })
+    }
 }
 
 impl From<RawFileMSGraph> for File {
     fn from(fd: RawFileMSGraph) -> File {
+        let kind = fd.kind();
+        let path = fd.path();
+        let (mime_type, hashes) = match fd.file {
+            Some(MSGraphFile { mime_type, hashes }) => (
+                mime_type.unwrap_or_else(|| "application/octet-stream".to_string()),
+                ContentHashes {
+                    sha1: hashes.sha1_hash,
+                    sha256: hashes.sha256_hash,
+                    quick_xor: hashes.quick_xor_hash,
+                    ..ContentHashes::default()
+                },
+            ),
+            None => (kind.to_string(), ContentHashes::default()),
+        };
         File {
-            id: fd.id.clone(),
-            mime_type: match fd.file_or_folder {
-                MSGraphFileOrFolder::File { ref mime_type, .. } => mime_type.to_string(),
-                _ => "folder".to_string(),
-            },
-            name: fd.name.clone(),
-            is_directory: match fd.file_or_folder {
-                MSGraphFileOrFolder::Folder { .. } => true,
-                _ => false,
-            },
-            created_time: Some(fd.created_time.clone()),
-            modified_time: Some(fd.modified_time.clone()),
-            size: fd.size.map(|v| v.to_string()),
+            id: fd.id,
+            name: fd.name,
+            kind,
+            is_directory: kind == FileKind::Folder,
+            mime_type,
+            size: fd.size,
+            created_time: fd.created_time,
+            modified_time: fd.modified_time,
+            parent_id: fd.parent_reference.and_then(|parent| parent.id),
+            path,
+            web_view_link: fd.web_url,
+            revision: fd.c_tag,
+            hashes,
         }
     }
 }
@@ -197,45 +367,228 @@ impl From<RawFileMSGraph> for File {
 ///
 /// RawFileDropBox -> File
 ///
+/// 🔖 DropBox accepts a path wherever an id is expected; the parent path
+///    stands in for the parent id.
+///
 #[derive(Debug, Clone, Deserialize)]
 pub(crate) struct RawFileDropBox {
+    // deleted entries do not have an id
+    #[serde(default)]
     id: String,
-    #[serde(rename = ".tag")]
-    mime_type: String,
+    // upload responses do not include the tag
+    #[serde(rename = ".tag", default = "RawFileDropBox::file_tag")]
+    tag: String,
     name: String,
-    // todo: create time and take the latest of the two
-    client_modified: Option<NaiveDateTime>,
-    #[allow(dead_code)]
-    server_modified: Option<NaiveDateTime>,
-    size: Option<String>,
+    path_lower: Option<String>,
+    path_display: Option<String>,
+    // DropBox does not report a create time
+    client_modified: Option<DateTime<Utc>>,
+    server_modified: Option<DateTime<Utc>>,
+    size: Option<u64>,
+    content_hash: Option<String>,
+    rev: Option<String>,
+}
+impl RawFileDropBox {
+    fn file_tag() -> String {
+        "file".to_string()
+    }
+    pub fn is_deleted(&self) -> bool {
+        self.tag == "deleted"
+    }
+    fn kind(&self) -> FileKind {
+        match self.tag.as_str() {
+            "folder" => FileKind::Folder,
+            _ => FileKind::File,
+        }
+    }
+    fn parent_id(&self) -> Option<String> {
+        self.path_lower
+            .as_ref()
+            .and_then(|path| path.rsplit_once('/'))
+            .map(|(parent, _)| parent)
+            .filter(|parent| !parent.is_empty())
+            .map(ToString::to_string)
+    }
 }
 impl From<RawFileDropBox> for File {
     fn from(fd: RawFileDropBox) -> File {
+        let kind = fd.kind();
+        let parent_id = fd.parent_id();
+        // DropBox does not report a mime type
+        let mime_type = match kind {
+            FileKind::Folder => kind.to_string(),
+            _ => mime_for_name(&fd.name).to_string(),
+        };
         File {
-            id: fd.id.clone(),
-            name: fd.name.clone(),
-            is_directory: fd.mime_type.eq("folder"),
-            mime_type: fd.mime_type.clone(),
-            created_time: None,
-            modified_time: fd.client_modified.map(|v| v.to_string()),
+            // DropBox accepts the path in place of the id
+            id: if fd.id.is_empty() {
+                fd.path_lower.clone().unwrap_or_default()
+            } else {
+                fd.id
+            },
+            name: fd.name,
+            kind,
+            is_directory: kind == FileKind::Folder,
+            mime_type,
             size: fd.size,
+            created_time: None,
+            // the latest of the two
+            modified_time: fd.client_modified.max(fd.server_modified),
+            parent_id,
+            web_view_link: fd.path_display.as_ref().map(|path| {
+                format!(
+                    "https://www.dropbox.com/home{}",
+                    utf8_percent_encode(path, WEB_PATH)
+                )
+            }),
+            path: fd.path_display,
+            revision: fd.rev,
+            hashes: ContentHashes {
+                dropbox: fd.content_hash,
+                ..ContentHashes::default()
+            },
         }
     }
 }
+///
+/// The segments of a path are encoded; the separators are kept
+///
+const WEB_PATH: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'/')
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+///
+/// The mime type for the file extension
+///
+fn mime_for_name(name: &str) -> &'static str {
+    let extension = name
+        .rsplit_once('.')
+        .map(|(_, extension)| extension.to_lowercase());
+    match extension.as_deref() {
+        Some("csv") => "text/csv",
+        Some("tsv" | "tab") => "text/tab-separated-values",
+        Some("txt") => "text/plain",
+        Some("xlsx") => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        Some("xlsm") => "application/vnd.ms-excel.sheet.macroEnabled.12",
+        Some("xls") => "application/vnd.ms-excel",
+        Some("ods") => "application/vnd.oasis.opendocument.spreadsheet",
+        Some("parquet") => "application/vnd.apache.parquet",
+        Some("json") => "application/json",
+        Some("pdf") => "application/pdf",
+        Some("zip") => "application/zip",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        _ => "application/octet-stream",
+    }
+}
+///
+/// DropBox search_v2 -> RawFiles<RawFileDropBox>
+/// Each match wraps the file metadata.
+///
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct RawSearchDropBox {
+    matches: Vec<RawSearchMatchDropBox>,
+    has_more: bool,
+    cursor: Option<String>,
+}
+#[derive(Debug, Clone, Deserialize)]
+struct RawSearchMatchDropBox {
+    metadata: RawSearchMetadataDropBox,
+}
+#[derive(Debug, Clone, Deserialize)]
+struct RawSearchMetadataDropBox {
+    metadata: RawFileDropBox,
+}
+impl From<RawSearchDropBox> for RawFiles<RawFileDropBox> {
+    fn from(search: RawSearchDropBox) -> Self {
+        RawFiles::new(
+            search
+                .matches
+                .into_iter()
+                .map(|m| m.metadata.metadata)
+                .collect(),
+            search.cursor.filter(|_| search.has_more),
+        )
+    }
+}
 /* --------------------------------------------------------------------------------------------- */
 ///
 /// File implements Serialize (the returned/exported type)
 ///
+/// The normalized view of a file hosted by any of the drive providers.
+///
 #[derive(Default, Debug, Clone, Serialize)]
 pub struct File {
     pub id: String,
     pub name: String,
+    pub kind: FileKind,
     pub is_directory: bool,
     pub mime_type: String,
-    // todo convert to int
-    pub size: Option<String>,
-    pub created_time: Option<String>,
-    pub modified_time: Option<String>,
+    pub size: Option<u64>,
+    pub created_time: Option<DateTime<Utc>>,
+    pub modified_time: Option<DateTime<Utc>>,
+    pub parent_id: Option<String>,
+    pub path: Option<String>,
+    pub web_view_link: Option<String>,
+    /// Provider revision id; changes when the content changes
+    pub revision: Option<String>,
+    pub hashes: ContentHashes,
+}
+///
+/// Normalized kind of drive entry
+///
+#[derive(Debug, Clone, Copy, Display, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum FileKind {
+    File,
+    Folder,
+    Shortcut,
+}
+impl Default for FileKind {
+    fn default() -> Self {
+        FileKind::File
+    }
+}
+///
+/// Content hashes reported by the providers; each provider only
+/// reports a subset.
+///
+#[derive(Default, Debug, Clone, Serialize)]
+pub struct ContentHashes {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub md5: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha1: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quick_xor: Option<String>,
+    /// DropBox content_hash
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dropbox: Option<String>,
+}
+impl ContentHashes {
+    ///
+    /// The strongest hash reported, prefixed with its algorithm
+    /// (e.g., `sha256:…`); identifies the content across renames and moves
+    ///
+    pub fn content_hash(&self) -> Option<String> {
+        [
+            ("sha256", &self.sha256),
+            ("dropbox", &self.dropbox),
+            ("sha1", &self.sha1),
+            ("md5", &self.md5),
+            ("quickxor", &self.quick_xor),
+        ]
+        .iter()
+        .find_map(|(algorithm, hash)| {
+            hash.as_ref()
+                .map(|hash| format!("{}:{}", algorithm, hash.to_lowercase()))
+        })
+    }
 }
 /* --------------------------------------------------------------------------------------------- */
 
@@ -279,28 +632,13 @@ pub(crate) enum RawFile {
 }
 // constructors
 pub(crate) fn google(data: RawFiles<RawFileGoogle>) -> FilesBuilder {
-    FilesBuilder::new(
-        Kind::Google,
-        RawFiles {
-            inner: data.into_iter().map(RawFile::Google).collect(),
-        },
-    )
+    FilesBuilder::new(Kind::Google, data.map(RawFile::Google))
 }
 pub(crate) fn ms_graph(data: RawFiles<RawFileMSGraph>) -> FilesBuilder {
-    FilesBuilder::new(
-        Kind::MSGraph,
-        RawFiles {
-            inner: data.into_iter().map(RawFile::MSGraph).collect(),
-        },
-    )
+    FilesBuilder::new(Kind::MSGraph, data.map(RawFile::MSGraph))
 }
 pub(crate) fn drop_box(data: RawFiles<RawFileDropBox>) -> FilesBuilder {
-    FilesBuilder::new(
-        Kind::DropBox,
-        RawFiles {
-            inner: data.into_iter().map(RawFile::DropBox).collect(),
-        },
-    )
+    FilesBuilder::new(Kind::DropBox, data.map(RawFile::DropBox))
 }
 
 impl From<RawFile> for File {
@@ -331,3 +669,201 @@ impl From<RawFileDropBox> for RawFile {
         RawFile::DropBox(rf)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn parse<RF: serde::de::DeserializeOwned>(value: serde_json::Value) -> RawFiles<RF> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn google_files_are_normalized() {
+        let raw = parse::<RawFileGoogle>(json!({
+            "nextPageToken": "page-2",
+            "files": [{
+                "id": "1a",
+                "name": "sales.csv",
+                "mimeType": "text/csv",
+                "createdTime": "2022-01-10T08:00:00.000Z",
+                "modifiedTime": "2022-01-11T09:30:00.000Z",
+                "size": "2211054",
+                "parents": ["root-id"],
+                "md5Checksum": "5d41402abc4b2a76b9719d911017c592",
+                "headRevisionId": "rev-1"
+            }, {
+                "id": "2b",
+                "name": "reports",
+                "mimeType": "application/vnd.google-apps.folder"
+            }]
+        }));
+        assert_eq!(Some("page-2".to_string()), raw.next_page());
+
+        let files: Vec<File> = raw.into_iter().map(File::from).collect();
+        let file = &files[0];
+        assert_eq!(FileKind::File, file.kind);
+        assert_eq!(Some(2211054), file.size);
+        assert_eq!(Some("root-id".to_string()), file.parent_id);
+        assert_eq!(
+            "2022-01-11T09:30:00+00:00",
+            file.modified_time.unwrap().to_rfc3339()
+        );
+        assert_eq!(
+            Some("5d41402abc4b2a76b9719d911017c592".to_string()),
+            file.hashes.md5
+        );
+        assert!(files[1].is_directory);
+        assert_eq!(None, files[1].size);
+    }
+
+    #[test]
+    fn google_sizes_must_be_numbers() {
+        let raw = serde_json::from_value::<RawFileGoogle>(json!({
+            "id": "1a",
+            "name": "sales.csv",
+            "mimeType": "text/csv",
+            "size": "large"
+        }));
+        assert!(raw.is_err());
+    }
+
+    #[test]
+    fn ms_graph_files_are_normalized() {
+        let raw = parse::<RawFileMSGraph>(json!({
+            "@odata.nextLink": "https://graph.microsoft.com/v1.0/me/drive/root/children?$skiptoken=x",
+            "value": [{
+                "id": "01ABC",
+                "name": "sales.xlsx",
+                "size": 1024,
+                "createdDateTime": "2022-01-10T08:00:00Z",
+                "lastModifiedDateTime": "2022-01-11T09:30:00Z",
+                "parentReference": { "id": "01ROOT", "path": "/drive/root:/data" },
+                "file": {
+                    "mimeType": "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+                    "hashes": { "quickXorHash": "qx==" }
+                },
+                "cTag": "ctag-1"
+            }, {
+                "id": "01DIR",
+                "name": "archive",
+                "folder": { "childCount": 3 },
+                "parentReference": { "id": "01ROOT", "path": "/drive/root:" }
+            }]
+        }));
+        assert!(raw.next_page().unwrap().contains("skiptoken"));
+
+        let files: Vec<File> = raw.into_iter().map(File::from).collect();
+        assert_eq!(Some("/data/sales.xlsx".to_string()), files[0].path);
+        assert_eq!(Some("01ROOT".to_string()), files[0].parent_id);
+        assert_eq!(Some("qx==".to_string()), files[0].hashes.quick_xor);
+        assert_eq!(Some("ctag-1".to_string()), files[0].revision);
+        assert_eq!(Some("/archive".to_string()), files[1].path);
+        assert_eq!("folder", files[1].mime_type);
+    }
+
+    #[test]
+    fn drop_box_files_are_normalized() {
+        let raw = parse::<RawFileDropBox>(json!({
+            "entries": [{
+                ".tag": "file",
+                "id": "id:a4ayc_80_OEAAAAAAAAAXw",
+                "name": "Q1 sales#2.CSV",
+                "path_lower": "/data/q1 sales#2.csv",
+                "path_display": "/Data/Q1 sales#2.CSV",
+                "client_modified": "2022-01-10T08:00:00Z",
+                "server_modified": "2022-01-11T09:30:00Z",
+                "size": 7212,
+                "rev": "a1c10ce0dd78",
+                "content_hash": "e3b0c442"
+            }, {
+                ".tag": "folder",
+                "id": "id:a4ayc_80_OEAAAAAAAAAXz",
+                "name": "Data",
+                "path_lower": "/data",
+                "path_display": "/Data"
+            }],
+            "cursor": "ZtkX9_EHj3x7PMkVuFIhwKYXEpwpLwyxp9vMKomUhllil9q7eWiAu",
+            "has_more": false
+        }));
+        // the cursor is only a page when there is more
+        assert_eq!(None, raw.next_page());
+
+        let files: Vec<File> = raw.into_iter().map(File::from).collect();
+        let file = &files[0];
+        // the mime type is derived from the extension (not the .tag)
+        assert_eq!("text/csv", file.mime_type);
+        assert_eq!(Some("/data".to_string()), file.parent_id);
+        assert_eq!(
+            Some("https://www.dropbox.com/home/Data/Q1%20sales%232.CSV".to_string()),
+            file.web_view_link
+        );
+        assert_eq!(
+            "2022-01-11T09:30:00+00:00",
+            file.modified_time.unwrap().to_rfc3339()
+        );
+        assert_eq!(Some("e3b0c442".to_string()), file.hashes.dropbox);
+
+        let folder = &files[1];
+        assert!(folder.is_directory);
+        assert_eq!("folder", folder.mime_type);
+        assert_eq!(None, folder.parent_id);
+    }
+
+    #[test]
+    fn drop_box_uploads_and_deletions() {
+        // upload responses do not include the tag
+        let upload = serde_json::from_value::<RawFileDropBox>(json!({
+            "id": "id:new",
+            "name": "notes",
+            "path_lower": "/notes",
+            "path_display": "/notes",
+            "size": 3
+        }))
+        .unwrap();
+        assert!(!upload.is_deleted());
+        let file = File::from(upload);
+        assert_eq!(FileKind::File, file.kind);
+        assert_eq!("application/octet-stream", file.mime_type);
+
+        // deleted entries do not have an id
+        let deleted = serde_json::from_value::<RawFileDropBox>(json!({
+            ".tag": "deleted",
+            "name": "old.csv",
+            "path_lower": "/old.csv"
+        }))
+        .unwrap();
+        assert!(deleted.is_deleted());
+        assert_eq!("/old.csv", File::from(deleted).id);
+    }
+
+    #[test]
+    fn drop_box_search_matches_are_unwrapped() {
+        let search = serde_json::from_value::<RawSearchDropBox>(json!({
+            "matches": [{
+                "metadata": {
+                    ".tag": "metadata",
+                    "metadata": { ".tag": "file", "id": "id:1", "name": "a.tsv", "path_lower": "/a.tsv" }
+                }
+            }],
+            "has_more": true,
+            "cursor": "next"
+        }))
+        .unwrap();
+        let raw: RawFiles<RawFileDropBox> = search.into();
+        assert_eq!(Some("next".to_string()), raw.next_page());
+        let files: Vec<File> = raw.into_iter().map(File::from).collect();
+        assert_eq!("text/tab-separated-values", files[0].mime_type);
+    }
+
+    #[test]
+    fn malformed_timestamps_are_rejected() {
+        let raw = serde_json::from_value::<RawFileMSGraph>(json!({
+            "id": "01ABC",
+            "name": "sales.csv",
+            "lastModifiedDateTime": "yesterday"
+        }));
+        assert!(raw.is_err());
+    }
+}
@@ -0,0 +1,363 @@
+///
+/// Hosts the outbound http client shared by the handlers (app state).
+///
+/// 👉 connections are pooled by the reqwest client
+/// 👉 requests are retried with exponential backoff when throttled (429), and
+///    when idempotent requests time out or the server is unavailable (5xx).
+///    A Retry-After header sent by the server takes precedence. Read-only
+///    POST requests (e.g., DropBox list_folder) are sent with send_read_only
+///    to be retried as well.
+/// 👉 requests complete within the http timeout, except those with a
+///    streaming body (uploads): each read of the body is limited instead
+///    (see stream_body).
+///
+/// 🔖 Requests with a streaming body cannot be retried (not cloneable).
+///
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::Stream;
+use http::header::RETRY_AFTER;
+use http::{Method, StatusCode};
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::{sleep, Instant, Sleep};
+
+use crate::config::Options;
+use crate::errors::AuthError;
+
+/// Delay before the first retry; doubled with each attempt
+const BASE_DELAY_MS: u64 = 500;
+/// Upper bound for any one delay, including Retry-After
+const MAX_DELAY_SECS: u64 = 60;
+
+#[derive(Debug, Clone)]
+pub struct HttpClient {
+    client: reqwest::Client,
+    max_retries: u32,
+    timeout: Duration,
+}
+impl HttpClient {
+    pub fn new(options: &Options) -> Result<Self, AuthError> {
+        let client = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(options.http_connect_timeout))
+            .build()
+            .map_err(|err| {
+                AuthError::InternalError(format!("Failed to build client: {}", err).into())
+            })?;
+        Ok(HttpClient {
+            client,
+            max_retries: options.http_max_retries,
+            timeout: Duration::from_secs(options.http_timeout),
+        })
+    }
+    pub fn request(&self, method: Method, url: impl AsRef<str>) -> reqwest::RequestBuilder {
+        self.client.request(method, url.as_ref())
+    }
+    pub fn get(&self, url: impl AsRef<str>) -> reqwest::RequestBuilder {
+        self.request(Method::GET, url)
+    }
+    pub fn post(&self, url: impl AsRef<str>) -> reqwest::RequestBuilder {
+        self.request(Method::POST, url)
+    }
+    pub fn put(&self, url: impl AsRef<str>) -> reqwest::RequestBuilder {
+        self.request(Method::PUT, url)
+    }
+    ///
+    /// A streaming request body; each read waits at most the http timeout
+    ///
+    pub fn stream_body<S>(&self, stream: S) -> reqwest::Body
+    where
+        S: Stream<Item = Result<Bytes, axum::Error>> + Send + Sync + 'static,
+    {
+        reqwest::Body::wrap_stream(ReadTimeout::new(stream, self.timeout))
+    }
+    ///
+    /// 📬 Send the request; retry as described above
+    ///
+    pub async fn send(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response, reqwest::Error> {
+        self.execute(request.build()?).await
+    }
+    ///
+    /// 📬 Send a request that does not change anything, whatever its method;
+    /// retried as an idempotent request
+    ///
+    pub async fn send_read_only(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response, reqwest::Error> {
+        self.execute_read_only(request.build()?).await
+    }
+    pub async fn execute(
+        &self,
+        request: reqwest::Request,
+    ) -> Result<reqwest::Response, reqwest::Error> {
+        let idempotent = is_idempotent(request.method());
+        self.execute_with(request, idempotent).await
+    }
+    pub async fn execute_read_only(
+        &self,
+        request: reqwest::Request,
+    ) -> Result<reqwest::Response, reqwest::Error> {
+        self.execute_with(request, true).await
+    }
+    async fn execute_with(
+        &self,
+        mut request: reqwest::Request,
+        idempotent: bool,
+    ) -> Result<reqwest::Response, reqwest::Error> {
+        // a streaming body is limited by read (see stream_body)
+        let streaming = request
+            .body()
+            .map_or(false, |body| body.as_bytes().is_none());
+        if !streaming && request.timeout().is_none() {
+            *request.timeout_mut() = Some(self.timeout);
+        }
+        let mut attempt: u32 = 0;
+        loop {
+            let retry = if attempt < self.max_retries {
+                request.try_clone()
+            } else {
+                None
+            };
+            let result = self.client.execute(request).await;
+
+            let delay = match (&result, &retry) {
+                (_, None) => None,
+                (Ok(response), _) if response.status() == StatusCode::TOO_MANY_REQUESTS => {
+                    Some(retry_after(response).unwrap_or_else(|| backoff(attempt)))
+                }
+                (Ok(response), _) if idempotent && is_unavailable(response.status()) => {
+                    Some(retry_after(response).unwrap_or_else(|| backoff(attempt)))
+                }
+                (Err(err), _) if idempotent && (err.is_timeout() || err.is_connect()) => {
+                    Some(backoff(attempt))
+                }
+                _ => None,
+            };
+            match (delay, retry) {
+                (Some(delay), Some(next)) => {
+                    tracing::debug!(
+                        "\n🔁 retry {} of {} in {:?}: {}\n",
+                        attempt + 1,
+                        self.max_retries,
+                        delay,
+                        next.url()
+                    );
+                    tokio::time::sleep(delay).await;
+                    request = next;
+                    attempt += 1;
+                }
+                _ => return result,
+            }
+        }
+    }
+    ///
+    /// The http client used by oauth2 to exchange (and refresh) tokens
+    ///
+    pub async fn oauth2(
+        &self,
+        request: oauth2::HttpRequest,
+    ) -> Result<oauth2::HttpResponse, reqwest::Error> {
+        let response = self
+            .send(
+                self.request(request.method, request.url.as_str())
+                    .headers(request.headers)
+                    .body(request.body),
+            )
+            .await?;
+        Ok(oauth2::HttpResponse {
+            status_code: response.status(),
+            headers: response.headers().to_owned(),
+            body: response.bytes().await?.to_vec(),
+        })
+    }
+}
+///
+/// Fails the stream when the next chunk takes longer than the timeout
+///
+struct ReadTimeout<S> {
+    stream: Pin<Box<S>>,
+    timeout: Duration,
+    deadline: Pin<Box<Sleep>>,
+}
+impl<S> ReadTimeout<S> {
+    fn new(stream: S, timeout: Duration) -> Self {
+        ReadTimeout {
+            stream: Box::pin(stream),
+            timeout,
+            deadline: Box::pin(sleep(timeout)),
+        }
+    }
+}
+impl<S> Stream for ReadTimeout<S>
+where
+    S: Stream<Item = Result<Bytes, axum::Error>>,
+{
+    type Item = Result<Bytes, io::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.stream.as_mut().poll_next(cx) {
+            Poll::Ready(item) => {
+                let deadline = Instant::now() + self.timeout;
+                self.deadline.as_mut().reset(deadline);
+                Poll::Ready(
+                    item.map(|chunk| {
+                        chunk.map_err(|err| io::Error::new(io::ErrorKind::Other, err))
+                    }),
+                )
+            }
+            Poll::Pending => match self.deadline.as_mut().poll(cx) {
+                Poll::Ready(()) => Poll::Ready(Some(Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "the body stalled",
+                )))),
+                Poll::Pending => Poll::Pending,
+            },
+        }
+    }
+}
+fn is_idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::PUT | Method::DELETE
+    )
+}
+fn is_unavailable(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+fn backoff(attempt: u32) -> Duration {
+    Duration::from_millis(BASE_DELAY_MS.saturating_mul(2u64.saturating_pow(attempt)))
+        .min(Duration::from_secs(MAX_DELAY_SECS))
+}
+///
+/// Retry-After is either a number of seconds or an http date
+///
+fn retry_after(response: &reqwest::Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    let delay = match value.trim().parse::<u64>() {
+        Ok(seconds) => Duration::from_secs(seconds),
+        Err(_) => (DateTime::parse_from_rfc2822(value)
+            .ok()?
+            .with_timezone(&Utc)
+            - Utc::now())
+        .to_std()
+        .unwrap_or_default(),
+    };
+    Some(delay.min(Duration::from_secs(MAX_DELAY_SECS)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{stream, StreamExt};
+
+    fn with_retry_after(value: &str) -> reqwest::Response {
+        http::Response::builder()
+            .header(RETRY_AFTER, value)
+            .body("")
+            .unwrap()
+            .into()
+    }
+
+    #[test]
+    fn retry_after_is_read_in_seconds_or_as_a_date() {
+        let in_30s = (Utc::now() + chrono::Duration::seconds(30)).to_rfc2822();
+        let an_hour_ago = (Utc::now() - chrono::Duration::hours(1)).to_rfc2822();
+        let tomorrow = (Utc::now() + chrono::Duration::days(1)).to_rfc2822();
+        for (value, expected) in [
+            ("5", Some(Duration::from_secs(5))),
+            (" 7 ", Some(Duration::from_secs(7))),
+            ("0", Some(Duration::ZERO)),
+            // capped
+            ("120", Some(Duration::from_secs(MAX_DELAY_SECS))),
+            (&tomorrow, Some(Duration::from_secs(MAX_DELAY_SECS))),
+            // a date that has passed
+            (&an_hour_ago, Some(Duration::ZERO)),
+            ("soon", None),
+            ("-1", None),
+        ] {
+            assert_eq!(expected, retry_after(&with_retry_after(value)), "{}", value);
+        }
+        // the date is rounded down to the second
+        let delay = retry_after(&with_retry_after(&in_30s)).unwrap();
+        assert!(delay > Duration::from_secs(28) && delay <= Duration::from_secs(30));
+
+        let response: reqwest::Response = http::Response::new("").into();
+        assert_eq!(None, retry_after(&response));
+    }
+
+    #[test]
+    fn the_backoff_doubles_up_to_the_cap() {
+        for (attempt, expected) in [
+            (0, Duration::from_millis(500)),
+            (1, Duration::from_secs(1)),
+            (2, Duration::from_secs(2)),
+            (6, Duration::from_secs(32)),
+            (7, Duration::from_secs(MAX_DELAY_SECS)),
+            (u32::MAX, Duration::from_secs(MAX_DELAY_SECS)),
+        ] {
+            assert_eq!(expected, backoff(attempt), "attempt {}", attempt);
+        }
+    }
+
+    #[test]
+    fn only_idempotent_methods_are_retried() {
+        for (method, expected) in [
+            (Method::GET, true),
+            (Method::HEAD, true),
+            (Method::OPTIONS, true),
+            (Method::PUT, true),
+            (Method::DELETE, true),
+            (Method::POST, false),
+            (Method::PATCH, false),
+        ] {
+            assert_eq!(expected, is_idempotent(&method), "{}", method);
+        }
+    }
+
+    #[test]
+    fn gateway_errors_are_unavailable() {
+        for (status, expected) in [
+            (StatusCode::BAD_GATEWAY, true),
+            (StatusCode::SERVICE_UNAVAILABLE, true),
+            (StatusCode::GATEWAY_TIMEOUT, true),
+            (StatusCode::INTERNAL_SERVER_ERROR, false),
+            (StatusCode::NOT_IMPLEMENTED, false),
+            (StatusCode::TOO_MANY_REQUESTS, false),
+            (StatusCode::OK, false),
+        ] {
+            assert_eq!(expected, is_unavailable(status), "{}", status);
+        }
+    }
+
+    #[tokio::test]
+    async fn a_stalled_body_times_out() {
+        let chunks = stream::iter(vec![Ok(Bytes::from("a"))]).chain(stream::pending());
+        let mut body = ReadTimeout::new(chunks, Duration::from_millis(50));
+        assert_eq!(Bytes::from("a"), body.next().await.unwrap().unwrap());
+        let err = body.next().await.unwrap().unwrap_err();
+        assert_eq!(io::ErrorKind::TimedOut, err.kind());
+    }
+
+    #[tokio::test]
+    async fn a_slow_body_is_not_limited_in_total() {
+        let chunks = stream::iter(0..4).then(|_| async {
+            sleep(Duration::from_millis(30)).await;
+            Ok(Bytes::from("a"))
+        });
+        let body: Vec<_> = ReadTimeout::new(chunks, Duration::from_millis(50))
+            .collect()
+            .await;
+        assert_eq!(4, body.len());
+        assert!(body.iter().all(Result::is_ok));
+    }
+}
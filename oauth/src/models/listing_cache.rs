@@ -0,0 +1,379 @@
+///
+/// Hosts the drive listings (serialized Files) in redis.
+///
+/// 👉 (project, provider, drive) -> hash of listings by variant (token, folder, page, filter)
+/// 👉 (project, provider) -> the cached drives (used to invalidate them all)
+///
+/// Each listing records when it expires: a listing is served ttl seconds at
+/// most, whatever the variants cached after it (the hash expires ttl seconds
+/// after the last of them).
+///
+/// The filesystem endpoint lists the root of a drive: the listings are
+/// cached by drive. A drive is invalidated when a file is uploaded to it;
+/// all of the project's drives are invalidated when the provider notifies
+/// of a change (the notifications do not specify the folder).
+///
+/// 🔐 A listing is only served to the access token that fetched it (the
+///    provider accepted the token); a digest of the token is part of the
+///    variant, the token itself is not stored.
+///
+/// The (project, provider) part of the keys is a hash tag: the drives and
+/// their index share a hash slot (Cluster).
+///
+use async_redis_session::RedisTopology;
+use chrono::Utc;
+use redis::{Cmd, FromRedisValue, Pipeline};
+use sha2::{Digest, Sha256};
+
+use crate::config::config_get;
+use crate::errors::AuthError;
+use crate::models::drive_provider::DriveProvider;
+use crate::models::project_id::ProjectId;
+
+const KEY_PREFIX: &str = "listings/";
+/// The drive key of the user's personal drive
+pub const PERSONAL_DRIVE: &str = "personal";
+
+///
+/// A listing ready to send: the json body and its entity tag
+///
+#[derive(Debug, Clone)]
+pub struct CachedListing {
+    pub body: String,
+    pub etag: String,
+}
+impl CachedListing {
+    pub fn new(body: String) -> Self {
+        let etag = format!("\"{}\"", hex::encode(Sha256::digest(body.as_bytes())));
+        CachedListing { body, etag }
+    }
+}
+
+///
+/// Identifies a cached listing
+///
+#[derive(Debug, Clone)]
+pub struct ListingKey<'a> {
+    pub project_id: &'a ProjectId,
+    pub drive_provider: &'a DriveProvider,
+    /// the drive listed (see DriveParams::shared); None for the personal drive
+    pub drive: Option<&'a str>,
+    /// the digest of the access token that fetched the listing
+    /// (see AuthDriveToken::digest)
+    pub token: &'a str,
+    /// the folder listed ("" for the root)
+    pub path: &'a str,
+    /// the page cursor; None for the first page
+    pub cursor: Option<&'a str>,
+    /// the filter applied to the listing (`only`)
+    pub only: Option<&'a str>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ListingCache {
+    topology: RedisTopology,
+}
+impl ListingCache {
+    pub fn new(topology: RedisTopology) -> Self {
+        ListingCache { topology }
+    }
+    fn index_key(project_id: &ProjectId, drive_provider: &DriveProvider) -> String {
+        format!(
+            "{}{{{}/{}}}",
+            KEY_PREFIX,
+            project_id,
+            drive_provider.to_path()
+        )
+    }
+    fn drive_key(
+        project_id: &ProjectId,
+        drive_provider: &DriveProvider,
+        drive: Option<&str>,
+    ) -> String {
+        format!(
+            "{}/{}",
+            Self::index_key(project_id, drive_provider),
+            drive.unwrap_or(PERSONAL_DRIVE)
+        )
+    }
+    fn field(key: &ListingKey) -> String {
+        format!(
+            "{}|{}|{}|{}",
+            key.token,
+            key.path,
+            key.cursor.unwrap_or_default(),
+            key.only.unwrap_or_default()
+        )
+    }
+    fn ttl() -> Result<usize, AuthError> {
+        Ok(config_get()?.options.listing_cache_ttl)
+    }
+    /// sends the command to the node that serves the key (Cluster), over the
+    /// connection shared by the stores
+    async fn query<T: FromRedisValue>(&self, key: &str, cmd: &Cmd) -> Result<T, AuthError> {
+        self.topology.query_for(key, cmd).await.map_err(store_error)
+    }
+    /// the keys of the pipeline share the hash slot of the key
+    async fn query_pipe<T: FromRedisValue>(
+        &self,
+        key: &str,
+        pipe: &Pipeline,
+    ) -> Result<T, AuthError> {
+        self.topology
+            .query_pipe_for(key, pipe)
+            .await
+            .map_err(store_error)
+    }
+    pub async fn get(&self, key: &ListingKey<'_>) -> Result<Option<CachedListing>, AuthError> {
+        if Self::ttl()? == 0 {
+            return Ok(None);
+        }
+        let drive_key = Self::drive_key(key.project_id, key.drive_provider, key.drive);
+        let record: Option<String> = self
+            .query(&drive_key, &Cmd::hget(&drive_key, Self::field(key)))
+            .await?;
+        Ok(record
+            .and_then(|record| unexpired(&record, now()).map(str::to_string))
+            .map(CachedListing::new))
+    }
+    ///
+    /// Cache the listing for ttl seconds; the drive expires ttl seconds after
+    /// the last set
+    ///
+    pub async fn set(
+        &self,
+        key: &ListingKey<'_>,
+        listing: &CachedListing,
+    ) -> Result<(), AuthError> {
+        let ttl = Self::ttl()?;
+        if ttl == 0 {
+            return Ok(());
+        }
+        let index_key = Self::index_key(key.project_id, key.drive_provider);
+        let drive_key = Self::drive_key(key.project_id, key.drive_provider, key.drive);
+
+        self.query_pipe(
+            &index_key,
+            redis::pipe()
+                .atomic()
+                .hset(
+                    &drive_key,
+                    Self::field(key),
+                    record(&listing.body, now() + ttl as i64),
+                )
+                .expire(&drive_key, ttl)
+                .sadd(&index_key, &drive_key)
+                .expire(&index_key, ttl),
+        )
+        .await
+    }
+    ///
+    /// Drop the listings of a drive (all tokens, folders, pages and filters)
+    ///
+    pub async fn invalidate(
+        &self,
+        project_id: &ProjectId,
+        drive_provider: &DriveProvider,
+        drive: Option<&str>,
+    ) -> Result<(), AuthError> {
+        let drive_key = Self::drive_key(project_id, drive_provider, drive);
+
+        self.query_pipe(
+            &drive_key,
+            &Self::invalidate_pipe(project_id, drive_provider, drive),
+        )
+        .await
+    }
+    fn invalidate_pipe(
+        project_id: &ProjectId,
+        drive_provider: &DriveProvider,
+        drive: Option<&str>,
+    ) -> Pipeline {
+        let drive_key = Self::drive_key(project_id, drive_provider, drive);
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .del(&drive_key)
+            .srem(Self::index_key(project_id, drive_provider), &drive_key);
+        pipe
+    }
+    ///
+    /// Drop all of the listings for the project's drive
+    ///
+    pub async fn invalidate_all(
+        &self,
+        project_id: &ProjectId,
+        drive_provider: &DriveProvider,
+    ) -> Result<(), AuthError> {
+        let index_key = Self::index_key(project_id, drive_provider);
+
+        let drive_keys: Vec<String> = self.query(&index_key, &Cmd::smembers(&index_key)).await?;
+        self.query(
+            &index_key,
+            &Self::invalidate_all_cmd(&index_key, drive_keys),
+        )
+        .await
+    }
+    /// deletes the cached drives and their index
+    fn invalidate_all_cmd(index_key: &str, mut drive_keys: Vec<String>) -> Cmd {
+        drive_keys.push(index_key.to_string());
+        Cmd::del(drive_keys)
+    }
+}
+///
+/// The listing, prefixed with when it expires: `<expires at>|<body>`
+///
+fn record(body: &str, expires_at: i64) -> String {
+    format!("{}|{}", expires_at, body)
+}
+///
+/// The body of a listing that has not expired
+///
+fn unexpired(record: &str, now: i64) -> Option<&str> {
+    let (expires_at, body) = record.split_once('|')?;
+    match expires_at.parse::<i64>() {
+        Ok(expires_at) if expires_at > now => Some(body),
+        _ => None,
+    }
+}
+fn now() -> i64 {
+    Utc::now().timestamp()
+}
+fn store_error(err: redis::RedisError) -> AuthError {
+    AuthError::StoreError(err.to_string().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project_id() -> ProjectId {
+        ProjectId::try_from("6f4b3a2c-1d5e-4f7a-8b9c-0d1e2f3a4b5c").unwrap()
+    }
+    fn key<'a>(project_id: &'a ProjectId, drive_provider: &'a DriveProvider) -> ListingKey<'a> {
+        ListingKey {
+            project_id,
+            drive_provider,
+            drive: None,
+            token: "digest",
+            path: "",
+            cursor: None,
+            only: None,
+        }
+    }
+    fn args(cmd: &Cmd) -> Vec<String> {
+        cmd.args_iter()
+            .map(|arg| match arg {
+                redis::Arg::Simple(arg) => String::from_utf8_lossy(arg).to_string(),
+                redis::Arg::Cursor => "<cursor>".to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn each_variant_of_a_listing_has_its_field() {
+        let project_id = project_id();
+        let first = key(&project_id, &DriveProvider::Google);
+        assert_eq!("digest|||", ListingCache::field(&first));
+
+        let variants = [
+            ListingKey {
+                token: "other",
+                ..first.clone()
+            },
+            ListingKey {
+                path: "/reports",
+                ..first.clone()
+            },
+            ListingKey {
+                cursor: Some("page-2"),
+                ..first.clone()
+            },
+            ListingKey {
+                only: Some("folders"),
+                ..first.clone()
+            },
+        ];
+        let fields: Vec<String> = variants.iter().map(ListingCache::field).collect();
+        assert_eq!(
+            vec![
+                "other|||",
+                "digest|/reports||",
+                "digest||page-2|",
+                "digest|||folders"
+            ],
+            fields
+        );
+    }
+
+    #[test]
+    fn the_drives_share_the_hash_slot_of_their_index() {
+        let project_id = project_id();
+        let index_key = ListingCache::index_key(&project_id, &DriveProvider::Google);
+        assert_eq!(
+            "listings/{6f4b3a2c-1d5e-4f7a-8b9c-0d1e2f3a4b5c/google}",
+            index_key
+        );
+        assert_eq!(
+            format!("{}/personal", index_key),
+            ListingCache::drive_key(&project_id, &DriveProvider::Google, None)
+        );
+        assert_eq!(
+            format!("{}/0AF", index_key),
+            ListingCache::drive_key(&project_id, &DriveProvider::Google, Some("0AF"))
+        );
+        assert_ne!(
+            index_key,
+            ListingCache::index_key(&project_id, &DriveProvider::DropBox)
+        );
+    }
+
+    #[test]
+    fn listings_expire_on_their_own() {
+        let record = record("{\"files\":[]}", 1_000);
+        assert_eq!(Some("{\"files\":[]}"), unexpired(&record, 999));
+        assert_eq!(None, unexpired(&record, 1_000));
+        // a body with the separator
+        assert_eq!(Some("a|b"), unexpired(&super::record("a|b", 10), 0));
+        // listings cached without their expiry
+        assert_eq!(None, unexpired("{\"files\":[]}", 0));
+    }
+
+    #[test]
+    fn a_drive_is_invalidated_on_its_own() {
+        let project_id = project_id();
+        let index_key = ListingCache::index_key(&project_id, &DriveProvider::Google);
+        let drive_key = ListingCache::drive_key(&project_id, &DriveProvider::Google, Some("0AF"));
+
+        let pipe = ListingCache::invalidate_pipe(&project_id, &DriveProvider::Google, Some("0AF"));
+        let commands: Vec<Vec<String>> = pipe.cmd_iter().map(args).collect();
+        assert_eq!(
+            vec![
+                vec!["DEL".to_string(), drive_key.clone()],
+                vec!["SREM".to_string(), index_key, drive_key],
+            ],
+            commands
+        );
+    }
+
+    #[test]
+    fn all_of_the_drives_are_invalidated_with_their_index() {
+        let project_id = project_id();
+        let index_key = ListingCache::index_key(&project_id, &DriveProvider::Google);
+        let drive_keys = vec![
+            ListingCache::drive_key(&project_id, &DriveProvider::Google, None),
+            ListingCache::drive_key(&project_id, &DriveProvider::Google, Some("0AF")),
+        ];
+
+        let cmd = ListingCache::invalidate_all_cmd(&index_key, drive_keys.clone());
+        let mut expected = vec!["DEL".to_string()];
+        expected.extend(drive_keys);
+        expected.push(index_key.clone());
+        assert_eq!(expected, args(&cmd));
+
+        // without cached drives, the index is deleted
+        assert_eq!(
+            vec!["DEL".to_string(), index_key.clone()],
+            args(&ListingCache::invalidate_all_cmd(&index_key, Vec::new()))
+        );
+    }
+}
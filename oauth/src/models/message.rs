@@ -6,6 +6,7 @@ use thiserror::Error;
 pub struct Message(pub Option<String>);
 
 impl fmt::Display for Message {
+    #[allow(clippy::recursive_format_impl, clippy::to_string_in_format_args)]
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}", self.to_string())
     }
@@ -1,13 +1,29 @@
 pub mod auth_failed_redirect;
 pub mod auth_redirect;
 pub mod auth_return;
+pub mod body_template;
+pub mod changes;
 pub mod drive_clients;
+pub mod drive_cursor;
+pub mod drive_error;
 pub mod drive_provider;
 pub mod drive_token;
+pub mod drives;
+pub mod file_filter;
 pub mod files;
+pub mod http_client;
+pub mod listing_cache;
 pub mod message;
 pub mod oauth_clients;
 pub mod oauth_provider;
+pub mod preview;
 pub mod project_id;
+pub mod refresh_tokens;
+pub mod schema;
+pub mod schema_cache;
+pub mod session_store;
+pub mod sqlite_session_store;
 pub mod user;
 pub mod user_registration;
+pub mod user_sessions;
+pub mod webhooks;
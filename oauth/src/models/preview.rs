@@ -0,0 +1,584 @@
+///
+/// Hosts the preview of a tabular file: the columns, their inferred types
+/// and the first rows.
+///
+/// 👉 CSV/TSV: the encoding and delimiter are detected
+/// 👉 Workbooks (XLSX, XLS, ODS): one sheet; Google Sheets are exported as XLSX
+///
+use calamine::{open_workbook_auto_from_rs, Data, Reader};
+use chrono::{DateTime as ChronoDateTime, Duration, NaiveDate, NaiveDateTime, NaiveTime};
+use encoding_rs::Encoding;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::Cursor;
+use strum_macros::Display;
+
+use crate::errors::AuthError;
+use crate::models::files::File;
+
+/// Google Sheets are exported using this format
+pub const XLSX: &str = "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet";
+const GOOGLE_SHEET: &str = "application/vnd.google-apps.spreadsheet";
+/// Delimiters considered when not implied by the format
+const DELIMITERS: [u8; 4] = [b',', b'\t', b';', b'|'];
+/// Number of lines used to detect the delimiter
+const SNIFF_LINES: usize = 20;
+
+const DATE_FORMATS: [&str; 3] = ["%Y-%m-%d", "%Y/%m/%d", "%m/%d/%Y"];
+const DATE_TIME_FORMATS: [&str; 3] = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"];
+
+#[derive(Debug, Default, Deserialize)]
+pub struct PreviewParams {
+    /// number of rows, excluding the header
+    pub rows: Option<usize>,
+    /// workbooks: the name of the sheet; the first sheet when not specified
+    pub sheet: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Display, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum Format {
+    Csv,
+    Tsv,
+    Workbook,
+    GoogleSheet,
+}
+impl Format {
+    pub fn from_file(file: &File) -> Option<Format> {
+        let extension = file
+            .name
+            .rsplit_once('.')
+            .map(|(_, extension)| extension.to_lowercase());
+
+        match (file.mime_type.as_str(), extension.as_deref()) {
+            (GOOGLE_SHEET, _) => Some(Format::GoogleSheet),
+            ("text/csv" | "application/csv", _) | (_, Some("csv")) => Some(Format::Csv),
+            ("text/tab-separated-values", _) | (_, Some("tsv" | "tab")) => Some(Format::Tsv),
+            (
+                XLSX
+                | "application/vnd.ms-excel"
+                | "application/vnd.oasis.opendocument.spreadsheet",
+                _,
+            )
+            | (_, Some("xlsx" | "xlsm" | "xlsb" | "xls" | "ods")) => Some(Format::Workbook),
+            // delimiter detection
+            ("text/plain", _) | (_, Some("txt")) => Some(Format::Csv),
+            _ => None,
+        }
+    }
+    /// Google Workspace files are exported using this mime type
+    pub fn export(&self) -> Option<&'static str> {
+        match self {
+            Format::GoogleSheet => Some(XLSX),
+            _ => None,
+        }
+    }
+    pub fn is_delimited(&self) -> bool {
+        matches!(self, Format::Csv | Format::Tsv)
+    }
+}
+
+///
+/// The type inferred for a value, or for the values of a column
+///
+#[derive(Debug, Clone, Copy, Display, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum DataType {
+    Empty,
+    Boolean,
+    Integer,
+    Number,
+    Date,
+    DateTime,
+    String,
+}
+impl DataType {
+    ///
+    /// The type of a text value, and the value as json
+    ///
+    /// 🔖 Numbers with leading zeros (e.g., zip codes) remain text
+    ///
+    pub fn infer(text: &str) -> (DataType, Value) {
+        let trimmed = text.trim();
+        if trimmed.is_empty() {
+            return (DataType::Empty, Value::Null);
+        }
+        let leading_zero =
+            trimmed.len() > 1 && trimmed.starts_with('0') && !trimmed.starts_with("0.");
+        if !leading_zero {
+            if let Ok(value) = trimmed.parse::<i64>() {
+                return (DataType::Integer, value.into());
+            }
+            if let Ok(value) = trimmed.parse::<f64>() {
+                if value.is_finite() {
+                    return (DataType::Number, value.into());
+                }
+            }
+        }
+        if trimmed.eq_ignore_ascii_case("true") {
+            return (DataType::Boolean, true.into());
+        }
+        if trimmed.eq_ignore_ascii_case("false") {
+            return (DataType::Boolean, false.into());
+        }
+        if let Some((data_type, _)) = date_format(trimmed) {
+            return (data_type, trimmed.into());
+        }
+        (DataType::String, text.into())
+    }
+    ///
+    /// The type able to represent values of both types
+    ///
+    pub fn unify(self, other: DataType) -> DataType {
+        match (self, other) {
+            (this, other) if this == other => this,
+            (DataType::Empty, other) | (other, DataType::Empty) => other,
+            (DataType::Integer, DataType::Number) | (DataType::Number, DataType::Integer) => {
+                DataType::Number
+            }
+            (DataType::Date, DataType::DateTime) | (DataType::DateTime, DataType::Date) => {
+                DataType::DateTime
+            }
+            _ => DataType::String,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Column {
+    pub name: String,
+    pub data_type: DataType,
+}
+
+///
+/// Preview implements Serialize (the returned/exported type)
+///
+#[derive(Debug, Clone, Serialize)]
+pub struct Preview {
+    pub file_id: String,
+    pub name: String,
+    pub format: Format,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delimiter: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sheet: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sheets: Vec<String>,
+    pub columns: Vec<Column>,
+    pub rows: Vec<Vec<Value>>,
+    /// the file has more rows than those included
+    pub has_more: bool,
+}
+impl Preview {
+    fn new(
+        file: &File,
+        format: Format,
+        header: Vec<String>,
+        rows: Vec<Vec<(DataType, Value)>>,
+    ) -> Self {
+        let width = rows
+            .iter()
+            .map(|row| row.len())
+            .chain(std::iter::once(header.len()))
+            .max()
+            .unwrap_or_default();
+
+        let columns = (0..width)
+            .map(|i| Column {
+                name: header
+                    .get(i)
+                    .filter(|name| !name.is_empty())
+                    .cloned()
+                    .unwrap_or_else(|| format!("column_{}", i + 1)),
+                data_type: rows
+                    .iter()
+                    .filter_map(|row| row.get(i))
+                    .fold(DataType::Empty, |acc, (data_type, _)| acc.unify(*data_type)),
+            })
+            .collect();
+
+        Preview {
+            file_id: file.id.clone(),
+            name: file.name.clone(),
+            format,
+            encoding: None,
+            delimiter: None,
+            sheet: None,
+            sheets: Vec::new(),
+            columns,
+            rows: rows
+                .into_iter()
+                .map(|row| row.into_iter().map(|(_, value)| value).collect())
+                .collect(),
+            has_more: false,
+        }
+    }
+    ///
+    /// CSV/TSV; `truncated` when the content is the start of the file
+    ///
+    pub fn delimited(
+        file: &File,
+        format: Format,
+        content: &[u8],
+        truncated: bool,
+        rows: usize,
+    ) -> Result<Preview, AuthError> {
+        let (text, encoding) = decode(content, truncated);
+        // the last line is incomplete
+        let text = if truncated {
+            text.rfind('\n').map_or("", |end| &text[..end])
+        } else {
+            &text
+        };
+        let delimiter = match format {
+            Format::Tsv => b'\t',
+            _ => sniff_delimiter(text),
+        };
+
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(delimiter)
+            .flexible(true)
+            .from_reader(text.as_bytes());
+        let header = reader
+            .headers()
+            .map_err(parse_error)?
+            .iter()
+            .map(|name| name.trim().to_string())
+            .collect();
+        let mut records = reader.records();
+        let sample = records
+            .by_ref()
+            .take(rows)
+            .map(|record| record.map(|record| record.iter().map(DataType::infer).collect()))
+            .collect::<Result<Vec<Vec<_>>, _>>()
+            .map_err(parse_error)?;
+        let has_more = truncated || records.next().is_some();
+
+        Ok(Preview {
+            encoding: Some(encoding.name().to_string()),
+            delimiter: Some((delimiter as char).to_string()),
+            has_more,
+            ..Preview::new(file, format, header, sample)
+        })
+    }
+    ///
+    /// XLSX, XLS, ODS; the first row is the header
+    ///
+    pub fn workbook(
+        file: &File,
+        format: Format,
+        content: Vec<u8>,
+        sheet: Option<&str>,
+        rows: usize,
+    ) -> Result<Preview, AuthError> {
+        let mut workbook = open_workbook_auto_from_rs(Cursor::new(content)).map_err(parse_error)?;
+        let sheets = workbook.sheet_names();
+        let sheet = match sheet {
+            Some(sheet) => sheets
+                .iter()
+                .find(|name| name.as_str() == sheet)
+                .cloned()
+                .ok_or_else(|| {
+                    AuthError::MissingParameter(format!("sheet not found: {}", sheet).into())
+                })?,
+            None => sheets
+                .first()
+                .cloned()
+                .ok_or_else(|| AuthError::UnsupportedMedia("The workbook has no sheets".into()))?,
+        };
+        let range = workbook.worksheet_range(&sheet).map_err(parse_error)?;
+
+        let mut range_rows = range.rows();
+        let header = range_rows
+            .next()
+            .map(|row| {
+                row.iter()
+                    .map(|cell| cell.to_string().trim().to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let sample = range_rows
+            .by_ref()
+            .take(rows)
+            .map(|row| row.iter().map(infer_cell).collect())
+            .collect();
+        let has_more = range_rows.next().is_some();
+
+        Ok(Preview {
+            sheet: Some(sheet),
+            sheets,
+            has_more,
+            ..Preview::new(file, format, header, sample)
+        })
+    }
+}
+///
+/// Date or DateTime, and the format of the text (chrono strftime, or rfc3339)
+///
+pub fn date_format(text: &str) -> Option<(DataType, &'static str)> {
+    let text = text.trim();
+    if let Some(format) = DATE_FORMATS
+        .iter()
+        .find(|format| NaiveDate::parse_from_str(text, format).is_ok())
+    {
+        return Some((DataType::Date, format));
+    }
+    if ChronoDateTime::parse_from_rfc3339(text).is_ok() {
+        return Some((DataType::DateTime, "rfc3339"));
+    }
+    DATE_TIME_FORMATS
+        .iter()
+        .find(|format| NaiveDateTime::parse_from_str(text, format).is_ok())
+        .map(|format| (DataType::DateTime, *format))
+}
+///
+/// The encoding is set by the byte order mark, or detected
+///
+fn decode(content: &[u8], truncated: bool) -> (String, &'static Encoding) {
+    let (encoding, bom) = Encoding::for_bom(content).unwrap_or_else(|| {
+        let mut detector = chardetng::EncodingDetector::new();
+        detector.feed(content, !truncated);
+        (detector.guess(None, true), 0)
+    });
+    let (text, _) = encoding.decode_without_bom_handling(&content[bom..]);
+    (text.into_owned(), encoding)
+}
+///
+/// The delimiter that occurs the same number of times on each of the first
+/// lines; otherwise the most frequent.
+///
+fn sniff_delimiter(text: &str) -> u8 {
+    let lines: Vec<&str> = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .take(SNIFF_LINES)
+        .collect();
+    let counts = |delimiter: u8| -> Vec<usize> {
+        lines
+            .iter()
+            .map(|line| line.bytes().filter(|byte| *byte == delimiter).count())
+            .collect()
+    };
+    let consistent = DELIMITERS
+        .iter()
+        .filter_map(|delimiter| {
+            let counts = counts(*delimiter);
+            match counts.first() {
+                Some(first) if *first > 0 && counts.iter().all(|count| count == first) => {
+                    Some((*delimiter, *first))
+                }
+                _ => None,
+            }
+        })
+        .max_by_key(|(_, count)| *count);
+
+    match consistent {
+        Some((delimiter, _)) => delimiter,
+        None => DELIMITERS
+            .iter()
+            .copied()
+            .max_by_key(|delimiter| counts(*delimiter).iter().sum::<usize>())
+            .filter(|delimiter| counts(*delimiter).iter().sum::<usize>() > 0)
+            .unwrap_or(b','),
+    }
+}
+fn infer_cell(cell: &Data) -> (DataType, Value) {
+    match cell {
+        Data::Int(value) => (DataType::Integer, (*value).into()),
+        // numbers are stored as floats
+        Data::Float(value) if value.fract() == 0.0 && value.abs() < 9.0e15 => {
+            (DataType::Integer, (*value as i64).into())
+        }
+        Data::Float(value) => (DataType::Number, (*value).into()),
+        Data::String(value) if value.trim().is_empty() => (DataType::Empty, Value::Null),
+        Data::String(value) => (DataType::String, value.clone().into()),
+        Data::Bool(value) => (DataType::Boolean, (*value).into()),
+        Data::DateTime(value) => match excel_datetime(value.as_f64()) {
+            Some(value) if value.time() == NaiveTime::from_hms(0, 0, 0) => {
+                (DataType::Date, value.date().to_string().into())
+            }
+            Some(value) => (
+                DataType::DateTime,
+                value.format("%Y-%m-%dT%H:%M:%S").to_string().into(),
+            ),
+            None => (DataType::String, cell.to_string().into()),
+        },
+        Data::DateTimeIso(value) => (DataType::DateTime, value.clone().into()),
+        Data::DurationIso(value) => (DataType::String, value.clone().into()),
+        Data::Error(_) | Data::Empty => (DataType::Empty, Value::Null),
+    }
+}
+///
+/// Excel stores dates as days since 1899-12-30 (the 1900 date system)
+///
+fn excel_datetime(value: f64) -> Option<NaiveDateTime> {
+    // Excel treats 1900 as a leap year
+    let value = if value >= 60.0 { value } else { value + 1.0 };
+    NaiveDate::from_ymd_opt(1899, 12, 30)?
+        .and_hms(0, 0, 0)
+        .checked_add_signed(Duration::milliseconds((value * 86_400_000.0).round() as i64))
+}
+fn parse_error<E: std::fmt::Display>(err: E) -> AuthError {
+    AuthError::UnsupportedMedia(format!("Failed to read the file: {}", err).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn csv_file() -> File {
+        File {
+            id: "id:1".to_string(),
+            name: "sales.csv".to_string(),
+            mime_type: "text/csv".to_string(),
+            ..File::default()
+        }
+    }
+
+    #[test]
+    fn the_consistent_delimiter_is_detected() {
+        assert_eq!(
+            b';',
+            sniff_delimiter("id;name;city\n1;Ann;Paris\n2;Bob;Lyon\n")
+        );
+        assert_eq!(b'|', sniff_delimiter("id|amount\n1|2,50\n2|3,75\n"));
+        assert_eq!(b'\t', sniff_delimiter("id\tname\n1\tAnn, Jr\n"));
+    }
+
+    #[test]
+    fn the_most_frequent_delimiter_is_used_otherwise() {
+        // ragged rows: no delimiter occurs the same number of times
+        assert_eq!(b',', sniff_delimiter("a,b,c\n1,2\n3,4,5,6\nx;y\n"));
+        // a single column
+        assert_eq!(b',', sniff_delimiter("name\nAnn\nBob\n"));
+        assert_eq!(b',', sniff_delimiter(""));
+    }
+
+    #[test]
+    fn text_values_are_typed() {
+        assert_eq!((DataType::Integer, json!(42)), DataType::infer(" 42 "));
+        assert_eq!((DataType::Number, json!(-1.5)), DataType::infer("-1.5"));
+        assert_eq!((DataType::Number, json!(0.25)), DataType::infer("0.25"));
+        assert_eq!((DataType::Boolean, json!(true)), DataType::infer("TRUE"));
+        assert_eq!(
+            (DataType::Date, json!("2022-01-31")),
+            DataType::infer("2022-01-31")
+        );
+        assert_eq!(
+            (DataType::DateTime, json!("2022-01-31 08:30:00")),
+            DataType::infer("2022-01-31 08:30:00")
+        );
+        assert_eq!((DataType::Empty, Value::Null), DataType::infer("  "));
+    }
+
+    #[test]
+    fn codes_and_non_finite_numbers_remain_text() {
+        assert_eq!((DataType::String, json!("02134")), DataType::infer("02134"));
+        assert_eq!((DataType::String, json!("NaN")), DataType::infer("NaN"));
+        assert_eq!((DataType::String, json!("inf")), DataType::infer("inf"));
+        assert_eq!(
+            (DataType::String, json!("2022-13-45")),
+            DataType::infer("2022-13-45")
+        );
+    }
+
+    #[test]
+    fn column_types_are_unified() {
+        assert_eq!(DataType::Number, DataType::Integer.unify(DataType::Number));
+        assert_eq!(DataType::Integer, DataType::Empty.unify(DataType::Integer));
+        assert_eq!(DataType::DateTime, DataType::Date.unify(DataType::DateTime));
+        assert_eq!(DataType::String, DataType::Boolean.unify(DataType::Integer));
+    }
+
+    #[test]
+    fn delimited_files_are_previewed() {
+        let content = b"id;amount;when\n1;2.5;2022-01-31\n2;;2022-02-01\n3;7;2022-02-02\n";
+        let preview = Preview::delimited(&csv_file(), Format::Csv, content, false, 2).unwrap();
+
+        assert_eq!(Some(";".to_string()), preview.delimiter);
+        assert_eq!(Some("UTF-8".to_string()), preview.encoding);
+        let types: Vec<_> = preview.columns.iter().map(|c| c.data_type).collect();
+        assert_eq!(
+            vec![DataType::Integer, DataType::Number, DataType::Date],
+            types
+        );
+        assert_eq!(
+            vec![json!(2), Value::Null, json!("2022-02-01")],
+            preview.rows[1]
+        );
+        assert!(preview.has_more);
+    }
+
+    #[test]
+    fn malformed_rows_are_previewed() {
+        // ragged rows, an unnamed column and an unterminated quote
+        let content = b"id,,name\n1,a\n2,b,Bob,extra\n3,c,\"Ann\n";
+        let preview = Preview::delimited(&csv_file(), Format::Csv, content, false, 10).unwrap();
+
+        let names: Vec<_> = preview.columns.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(vec!["id", "column_2", "name", "column_4"], names);
+        assert_eq!(3, preview.rows.len());
+        assert!(!preview.has_more);
+    }
+
+    #[test]
+    fn the_incomplete_last_line_of_a_truncated_file_is_dropped() {
+        let content = b"id,name\n1,Ann\n2,Bo";
+        let preview = Preview::delimited(&csv_file(), Format::Csv, content, true, 10).unwrap();
+        assert_eq!(1, preview.rows.len());
+        assert!(preview.has_more);
+    }
+
+    #[test]
+    fn legacy_encodings_are_decoded() {
+        // "café" in windows-1252
+        let content = b"name,city\ncaf\xe9,Paris\nna\xefve,Lyon\n";
+        let preview = Preview::delimited(&csv_file(), Format::Csv, content, false, 10).unwrap();
+        assert_eq!(Some("windows-1252".to_string()), preview.encoding);
+        assert_eq!(json!("café"), preview.rows[0][0]);
+    }
+
+    #[test]
+    fn a_workbook_that_cannot_be_read_is_unsupported() {
+        let err = Preview::workbook(
+            &csv_file(),
+            Format::Workbook,
+            b"id,name\n".to_vec(),
+            None,
+            10,
+        )
+        .unwrap_err();
+        assert!(matches!(err, AuthError::UnsupportedMedia(_)));
+    }
+
+    #[test]
+    fn formats_are_derived_from_the_mime_type_or_extension() {
+        let file = |name: &str, mime_type: &str| File {
+            name: name.to_string(),
+            mime_type: mime_type.to_string(),
+            ..File::default()
+        };
+        assert_eq!(
+            Some(Format::Csv),
+            Format::from_file(&file("a.CSV", "application/octet-stream"))
+        );
+        assert_eq!(
+            Some(Format::Tsv),
+            Format::from_file(&file("a", "text/tab-separated-values"))
+        );
+        assert_eq!(
+            Some(Format::Workbook),
+            Format::from_file(&file("a.xlsx", ""))
+        );
+        assert_eq!(
+            Some(Format::GoogleSheet),
+            Format::from_file(&file("a", GOOGLE_SHEET))
+        );
+        assert_eq!(None, Format::from_file(&file("a.pdf", "application/pdf")));
+    }
+}
@@ -9,6 +9,7 @@ use crate::errors::AuthError;
 pub struct ProjectId(Uuid);
 
 impl fmt::Display for ProjectId {
+    #[allow(clippy::to_string_in_format_args)]
     fn fmt(&self, f: &mut fmt::Formatter) -> std::fmt::Result {
         let ProjectId(uuid) = self;
         write!(f, "{}", uuid.to_string())
@@ -0,0 +1,188 @@
+use async_redis_session::{Decrypted, KeyRing, RedisTopology};
+///
+/// Hosts the drive refresh tokens in redis (project, provider) -> StoredToken
+///
+/// The refresh token is stored when the drive is authorized, and replaced
+/// when the provider rotates it. The latest access token is kept alongside
+/// so that concurrent requests reuse (rather than repeat) a refresh.
+///
+/// The tokens are encrypted with the session keys (see session_store); the
+/// tokens stored before the keys were configured stay readable.
+///
+use chrono::{DateTime, Duration, Utc};
+use oauth2::basic::BasicTokenResponse;
+use oauth2::TokenResponse;
+use redis::{Cmd, FromRedisValue};
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Options;
+use crate::errors::AuthError;
+use crate::models::drive_provider::DriveProvider;
+use crate::models::project_id::ProjectId;
+use crate::models::session_store::key_ring;
+
+const KEY_PREFIX: &str = "refresh-tokens/";
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct StoredToken {
+    pub refresh_token: String,
+    pub access_token: String,
+    pub expires_at: Option<DateTime<Utc>>,
+    /// the user that authorized the drive; only the owner gets the token
+    /// refreshed (tokens stored without an owner are never refreshed)
+    #[serde(default)]
+    pub owner: Option<String>,
+}
+impl StoredToken {
+    ///
+    /// None when the provider did not issue a refresh token (and there is no
+    /// previous one to keep)
+    ///
+    pub fn new(
+        token_response: &BasicTokenResponse,
+        previous: Option<String>,
+        owner: Option<String>,
+    ) -> Option<Self> {
+        let refresh_token = token_response
+            .refresh_token()
+            .map(|token| token.secret().clone())
+            .or(previous)?;
+        Some(StoredToken {
+            refresh_token,
+            access_token: token_response.access_token().secret().clone(),
+            expires_at: token_response
+                .expires_in()
+                .and_then(|expires_in| Duration::from_std(expires_in).ok())
+                .map(|expires_in| Utc::now() + expires_in),
+            owner,
+        })
+    }
+    pub fn is_current(&self) -> bool {
+        self.expires_at.map_or(true, |at| at > Utc::now())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RefreshTokens {
+    topology: RedisTopology,
+    key_ring: Option<KeyRing>,
+}
+impl RefreshTokens {
+    pub fn new(topology: RedisTopology, options: &Options) -> Result<Self, AuthError> {
+        Ok(RefreshTokens {
+            topology,
+            key_ring: key_ring(options.session_keys.expose_secret())?,
+        })
+    }
+    fn key(project_id: &ProjectId, drive_provider: &DriveProvider) -> String {
+        format!("{}{}/{}", KEY_PREFIX, project_id, drive_provider.to_path())
+    }
+    /// sends the command to the node that serves the key (Cluster), over the
+    /// connection shared by the stores
+    async fn query<T: FromRedisValue>(&self, key: &str, cmd: &Cmd) -> Result<T, AuthError> {
+        self.topology.query_for(key, cmd).await.map_err(store_error)
+    }
+    pub async fn get(
+        &self,
+        project_id: &ProjectId,
+        drive_provider: &DriveProvider,
+    ) -> Result<Option<StoredToken>, AuthError> {
+        let key = Self::key(project_id, drive_provider);
+        let record: Option<Vec<u8>> = self.query(&key, &Cmd::get(&key)).await?;
+        match record {
+            Some(record) => decode(self.key_ring.as_ref(), &key, record),
+            None => Ok(None),
+        }
+    }
+    pub async fn set(
+        &self,
+        project_id: &ProjectId,
+        drive_provider: &DriveProvider,
+        token: &StoredToken,
+    ) -> Result<(), AuthError> {
+        let key = Self::key(project_id, drive_provider);
+        let record = encode(self.key_ring.as_ref(), &key, token)?;
+        self.query(&key, &Cmd::set(&key, record)).await
+    }
+}
+fn encode(
+    key_ring: Option<&KeyRing>,
+    key: &str,
+    token: &StoredToken,
+) -> Result<Vec<u8>, AuthError> {
+    let record = serde_json::to_vec(token)?;
+    match key_ring {
+        Some(key_ring) => key_ring.encrypt(key, &record).map_err(crypto_error),
+        None => Ok(record),
+    }
+}
+/// None when the token was encrypted with a key that is no longer
+/// configured: the drive is authorized again
+fn decode(
+    key_ring: Option<&KeyRing>,
+    key: &str,
+    record: Vec<u8>,
+) -> Result<Option<StoredToken>, AuthError> {
+    let record = match key_ring {
+        Some(key_ring) => match key_ring.decrypt(key, record).map_err(crypto_error)? {
+            Decrypted::Record(record) => record,
+            Decrypted::UnknownKey => return Ok(None),
+        },
+        None => record,
+    };
+    Ok(Some(serde_json::from_slice(&record)?))
+}
+fn crypto_error(err: async_session::Error) -> AuthError {
+    AuthError::StoreError(err.to_string().into())
+}
+fn store_error(err: redis::RedisError) -> AuthError {
+    AuthError::StoreError(err.to_string().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "refresh-tokens/project/google";
+
+    fn token() -> StoredToken {
+        StoredToken {
+            refresh_token: "1//refresh".to_string(),
+            access_token: "ya29.access".to_string(),
+            expires_at: None,
+            owner: Some("google/1".to_string()),
+        }
+    }
+
+    #[test]
+    fn tokens_are_encrypted_with_the_session_keys() {
+        let key_ring = KeyRing::new(1, &[1; 32]).unwrap();
+        let record = encode(Some(&key_ring), KEY, &token()).unwrap();
+        assert!(!String::from_utf8_lossy(&record).contains("1//refresh"));
+
+        let decoded = decode(Some(&key_ring), KEY, record.clone())
+            .unwrap()
+            .unwrap();
+        assert_eq!("1//refresh", decoded.refresh_token);
+        assert_eq!(Some("google/1".to_string()), decoded.owner);
+
+        // the record belongs to its key
+        assert!(decode(Some(&key_ring), "refresh-tokens/project/dropbox", record).is_err());
+    }
+
+    #[test]
+    fn tokens_stored_before_the_keys_stay_readable() {
+        let record = encode(None, KEY, &token()).unwrap();
+        let key_ring = KeyRing::new(1, &[1; 32]).unwrap();
+        let decoded = decode(Some(&key_ring), KEY, record).unwrap().unwrap();
+        assert_eq!("ya29.access", decoded.access_token);
+    }
+
+    #[test]
+    fn tokens_of_a_retired_key_are_not_found() {
+        let record = encode(Some(&KeyRing::new(1, &[1; 32]).unwrap()), KEY, &token()).unwrap();
+        let rotated = KeyRing::new(2, &[2; 32]).unwrap();
+        assert!(decode(Some(&rotated), KEY, record).unwrap().is_none());
+    }
+}
@@ -0,0 +1,555 @@
+///
+/// Hosts the schema inferred for a drive file: the type of each field, and
+/// statistics used to plan a join (null rate, distinct count).
+///
+/// 👉 CSV/TSV, workbooks: inferred from a sample of rows (see Preview)
+/// 👉 Parquet: read from the footer metadata (the file is not sampled)
+///
+use parquet_format_safe::thrift::protocol::TCompactInputProtocol;
+use parquet_format_safe::{
+    ConvertedType, FieldRepetitionType, FileMetaData, LogicalType, SchemaElement, Type,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use strum_macros::Display;
+
+use crate::errors::AuthError;
+use crate::models::files::File;
+use crate::models::preview::{date_format, DataType, Preview};
+
+/// The last bytes of a Parquet file: the footer length (u32 LE) and the magic
+pub const PARQUET_TRAILER: usize = 8;
+const PARQUET_MAGIC: &[u8; 4] = b"PAR1";
+/// Larger footers are refused
+pub const MAX_FOOTER: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct SchemaParams {
+    /// workbooks: the name of the sheet; the first sheet when not specified
+    pub sheet: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Display, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum FieldType {
+    Integer,
+    Float,
+    Boolean,
+    Date,
+    DateTime,
+    String,
+}
+
+#[derive(Debug, Clone, Copy, Display, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum SchemaSource {
+    /// inferred from the first rows
+    Sample,
+    /// read from the Parquet footer
+    ParquetFooter,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Field {
+    pub name: String,
+    pub field_type: FieldType,
+    /// dates: the format of the values (chrono strftime, or rfc3339)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+    pub nullable: bool,
+    /// the share of null (empty) values, 0 to 1
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub null_rate: Option<f64>,
+    /// the estimated number of distinct values in the file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distinct_count: Option<u64>,
+}
+
+///
+/// Schema implements Serialize (the returned/exported type)
+///
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Schema {
+    pub file_id: String,
+    pub name: String,
+    pub source: SchemaSource,
+    /// the key used to cache the schema
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sheet: Option<String>,
+    /// Sample: the rows sampled; ParquetFooter: the rows in the file
+    pub rows: u64,
+    /// Sample: the sample includes all of the rows
+    pub complete: bool,
+    pub fields: Vec<Field>,
+}
+impl Schema {
+    ///
+    /// Infer the schema from the rows of a preview
+    ///
+    pub fn sample(file: &File, preview: &Preview) -> Schema {
+        let rows = preview.rows.len();
+        let fields = preview
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(i, column)| {
+                let values = preview
+                    .rows
+                    .iter()
+                    .map(|row| row.get(i).unwrap_or(&Value::Null));
+                infer_field(&column.name, values, rows, !preview.has_more)
+            })
+            .collect();
+
+        Schema {
+            file_id: file.id.clone(),
+            name: file.name.clone(),
+            source: SchemaSource::Sample,
+            content_hash: file.hashes.content_hash(),
+            sheet: preview.sheet.clone(),
+            rows: rows as u64,
+            complete: !preview.has_more,
+            fields,
+        }
+    }
+    ///
+    /// Read the schema from the Parquet footer: the metadata followed by the
+    /// trailer (see `footer_length`)
+    ///
+    pub fn parquet(file: &File, footer: &[u8]) -> Result<Schema, AuthError> {
+        let metadata = footer
+            .len()
+            .checked_sub(PARQUET_TRAILER)
+            .map(|end| &footer[..end])
+            .ok_or_else(|| parquet_error("the footer is incomplete"))?;
+        // the budget for allocations (counts 8 bytes per list element)
+        let mut protocol = TCompactInputProtocol::new(metadata, MAX_FOOTER * 8);
+        let metadata = FileMetaData::read_from_in_protocol(&mut protocol)
+            .map_err(|err| parquet_error(&err.to_string()))?;
+
+        let num_rows = metadata.num_rows.max(0) as u64;
+        let mut leaves = Vec::new();
+        if let Some((root, children)) = metadata.schema.split_first() {
+            let mut elements = children.iter();
+            for _ in 0..root.num_children.unwrap_or_default() {
+                collect_leaves(&mut elements, None, false, &mut leaves);
+            }
+        }
+
+        let fields = leaves
+            .into_iter()
+            .enumerate()
+            .map(|(i, (name, element, nullable))| {
+                let statistics = metadata
+                    .row_groups
+                    .iter()
+                    .map(|row_group| {
+                        row_group
+                            .columns
+                            .get(i)
+                            .and_then(|column| column.meta_data.as_ref())
+                            .and_then(|meta_data| meta_data.statistics.as_ref())
+                    })
+                    .collect::<Option<Vec<_>>>();
+                // statistics are optional; use them when every row group has them
+                let null_rate = statistics
+                    .as_ref()
+                    .and_then(|stats| {
+                        stats
+                            .iter()
+                            .map(|stat| stat.null_count)
+                            .sum::<Option<i64>>()
+                    })
+                    .filter(|_| num_rows > 0)
+                    .map(|nulls| nulls as f64 / num_rows as f64);
+                // the largest row group count is a lower bound
+                let distinct_count = statistics
+                    .as_ref()
+                    .and_then(|stats| stats.iter().map(|stat| stat.distinct_count).max())
+                    .flatten()
+                    .map(|count| count.max(0) as u64);
+
+                Field {
+                    name,
+                    field_type: parquet_type(element),
+                    format: None,
+                    nullable,
+                    null_rate,
+                    distinct_count,
+                }
+            })
+            .collect();
+
+        Ok(Schema {
+            file_id: file.id.clone(),
+            name: file.name.clone(),
+            source: SchemaSource::ParquetFooter,
+            content_hash: file.hashes.content_hash(),
+            sheet: None,
+            rows: num_rows,
+            complete: true,
+            fields,
+        })
+    }
+}
+pub fn is_parquet(file: &File) -> bool {
+    matches!(
+        file.mime_type.as_str(),
+        "application/vnd.apache.parquet" | "application/x-parquet"
+    ) || file.name.to_lowercase().ends_with(".parquet")
+}
+///
+/// The length of the footer (metadata and trailer) read from the trailer
+///
+pub fn footer_length(trailer: &[u8]) -> Result<usize, AuthError> {
+    match trailer
+        .len()
+        .checked_sub(PARQUET_TRAILER)
+        .map(|i| &trailer[i..])
+    {
+        Some([a, b, c, d, magic @ ..]) if magic == PARQUET_MAGIC => {
+            Ok(u32::from_le_bytes([*a, *b, *c, *d]) as usize + PARQUET_TRAILER)
+        }
+        _ => Err(parquet_error("not a Parquet file")),
+    }
+}
+///
+/// Depth first; nested fields are named by their path (e.g., `address.city`)
+///
+fn collect_leaves<'a>(
+    elements: &mut std::slice::Iter<'a, SchemaElement>,
+    parent: Option<&str>,
+    parent_nullable: bool,
+    leaves: &mut Vec<(String, &'a SchemaElement, bool)>,
+) {
+    if let Some(element) = elements.next() {
+        let name = match parent {
+            Some(parent) => format!("{}.{}", parent, element.name),
+            None => element.name.clone(),
+        };
+        let nullable =
+            parent_nullable || element.repetition_type != Some(FieldRepetitionType::REQUIRED);
+        match element.num_children {
+            Some(children) if children > 0 => {
+                for _ in 0..children {
+                    collect_leaves(elements, Some(&name), nullable, leaves);
+                }
+            }
+            _ => leaves.push((name, element, nullable)),
+        }
+    }
+}
+fn parquet_type(element: &SchemaElement) -> FieldType {
+    match (&element.logical_type, element.converted_type) {
+        (Some(LogicalType::DATE(_)), _) | (_, Some(ConvertedType::DATE)) => return FieldType::Date,
+        (Some(LogicalType::TIMESTAMP(_)), _)
+        | (_, Some(ConvertedType::TIMESTAMP_MILLIS | ConvertedType::TIMESTAMP_MICROS)) => {
+            return FieldType::DateTime
+        }
+        (Some(LogicalType::DECIMAL(_)), _) | (_, Some(ConvertedType::DECIMAL)) => {
+            return FieldType::Float
+        }
+        _ => {}
+    }
+    match element.type_ {
+        Some(Type::BOOLEAN) => FieldType::Boolean,
+        Some(Type::INT32 | Type::INT64) => FieldType::Integer,
+        // legacy timestamps
+        Some(Type::INT96) => FieldType::DateTime,
+        Some(Type::FLOAT | Type::DOUBLE) => FieldType::Float,
+        _ => FieldType::String,
+    }
+}
+///
+/// The type able to represent all of the values of a column; dates keep
+/// their format when all of the values share it.
+///
+fn infer_field<'a>(
+    name: &str,
+    values: impl Iterator<Item = &'a Value>,
+    rows: usize,
+    complete: bool,
+) -> Field {
+    let mut data_type = DataType::Empty;
+    let mut format: Option<Option<&'static str>> = None;
+    let mut nulls = 0;
+    let mut counts: HashMap<String, u64> = HashMap::new();
+
+    for value in values {
+        let (value_type, value_format) = match value {
+            Value::Null => (DataType::Empty, None),
+            Value::Bool(_) => (DataType::Boolean, None),
+            Value::Number(number) if number.is_f64() => (DataType::Number, None),
+            Value::Number(_) => (DataType::Integer, None),
+            Value::String(text) => match date_format(text) {
+                Some((data_type, format)) => (data_type, Some(format)),
+                None => (DataType::String, None),
+            },
+            _ => (DataType::String, None),
+        };
+        if value_type == DataType::Empty {
+            nulls += 1;
+            continue;
+        }
+        if value_format.is_some() {
+            format = match format {
+                None => Some(value_format),
+                Some(format) if format == value_format => Some(format),
+                Some(_) => Some(None),
+            };
+        }
+        data_type = data_type.unify(value_type);
+        *counts.entry(value.to_string()).or_default() += 1;
+    }
+
+    let field_type = match data_type {
+        DataType::Integer => FieldType::Integer,
+        DataType::Number => FieldType::Float,
+        DataType::Boolean => FieldType::Boolean,
+        DataType::Date => FieldType::Date,
+        DataType::DateTime => FieldType::DateTime,
+        DataType::Empty | DataType::String => FieldType::String,
+    };
+    let format = match field_type {
+        FieldType::Date | FieldType::DateTime => format.flatten().map(str::to_string),
+        _ => None,
+    };
+
+    Field {
+        name: name.to_string(),
+        field_type,
+        format,
+        nullable: nulls > 0 || !complete,
+        null_rate: (rows > 0).then(|| nulls as f64 / rows as f64),
+        distinct_count: Some(distinct_count(&counts, complete)),
+    }
+}
+///
+/// The number of distinct values in the file, estimated from the sample
+/// (Chao1: adds the values likely unseen given those seen once and twice)
+///
+fn distinct_count(counts: &HashMap<String, u64>, complete: bool) -> u64 {
+    let observed = counts.len() as u64;
+    if complete {
+        return observed;
+    }
+    let once = counts.values().filter(|count| **count == 1).count() as f64;
+    let twice = counts.values().filter(|count| **count == 2).count() as f64;
+    let unseen = if twice > 0.0 {
+        once * once / (2.0 * twice)
+    } else {
+        once * (once - 1.0).max(0.0) / 2.0
+    };
+    observed + unseen.round() as u64
+}
+fn parquet_error(message: &str) -> AuthError {
+    AuthError::UnsupportedMedia(format!("Failed to read the Parquet footer: {}", message).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::preview::Format;
+    use parquet_format_safe::thrift::protocol::TCompactOutputProtocol;
+    use serde_json::json;
+
+    fn file(name: &str) -> File {
+        File {
+            id: "id:1".to_string(),
+            name: name.to_string(),
+            ..File::default()
+        }
+    }
+    fn element(
+        name: &str,
+        type_: Option<Type>,
+        repetition: FieldRepetitionType,
+        children: Option<i32>,
+        converted: Option<ConvertedType>,
+    ) -> SchemaElement {
+        SchemaElement::new(
+            type_,
+            None,
+            repetition,
+            name.to_string(),
+            children,
+            converted,
+            None,
+            None,
+            None,
+            None,
+        )
+    }
+    /// the metadata followed by the trailer
+    fn footer(schema: Vec<SchemaElement>, num_rows: i64) -> Vec<u8> {
+        let metadata = FileMetaData::new(
+            1,
+            schema,
+            num_rows,
+            Vec::new(),
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        let mut bytes = Vec::new();
+        metadata
+            .write_to_out_protocol(&mut TCompactOutputProtocol::new(&mut bytes))
+            .unwrap();
+        let length = bytes.len() as u32;
+        bytes.extend_from_slice(&length.to_le_bytes());
+        bytes.extend_from_slice(PARQUET_MAGIC);
+        bytes
+    }
+
+    #[test]
+    fn the_footer_length_is_read_from_the_trailer() {
+        let mut tail = b"column data".to_vec();
+        tail.extend_from_slice(&1234u32.to_le_bytes());
+        tail.extend_from_slice(b"PAR1");
+        assert_eq!(1234 + PARQUET_TRAILER, footer_length(&tail).unwrap());
+    }
+
+    #[test]
+    fn files_that_are_not_parquet_are_refused() {
+        assert!(footer_length(b"id,name\n1,Ann\n").is_err());
+        assert!(footer_length(b"PAR1").is_err());
+        let mut tail = 10u32.to_le_bytes().to_vec();
+        tail.extend_from_slice(b"PAR2");
+        let err = footer_length(&tail).unwrap_err();
+        assert!(matches!(err, AuthError::UnsupportedMedia(_)));
+    }
+
+    #[test]
+    fn the_schema_is_read_from_the_parquet_footer() {
+        let footer = footer(
+            vec![
+                element("schema", None, FieldRepetitionType::REQUIRED, Some(3), None),
+                element(
+                    "id",
+                    Some(Type::INT64),
+                    FieldRepetitionType::REQUIRED,
+                    None,
+                    None,
+                ),
+                element(
+                    "created",
+                    Some(Type::INT64),
+                    FieldRepetitionType::OPTIONAL,
+                    None,
+                    Some(ConvertedType::TIMESTAMP_MILLIS),
+                ),
+                element(
+                    "address",
+                    None,
+                    FieldRepetitionType::OPTIONAL,
+                    Some(1),
+                    None,
+                ),
+                element(
+                    "city",
+                    Some(Type::BYTE_ARRAY),
+                    FieldRepetitionType::REQUIRED,
+                    None,
+                    Some(ConvertedType::UTF8),
+                ),
+            ],
+            42,
+        );
+        let schema = Schema::parquet(&file("sales.parquet"), &footer).unwrap();
+
+        assert_eq!(SchemaSource::ParquetFooter, schema.source);
+        assert_eq!(42, schema.rows);
+        let fields: Vec<_> = schema
+            .fields
+            .iter()
+            .map(|field| (field.name.as_str(), field.field_type, field.nullable))
+            .collect();
+        assert_eq!(
+            vec![
+                ("id", FieldType::Integer, false),
+                ("created", FieldType::DateTime, true),
+                // nullable when the group is optional
+                ("address.city", FieldType::String, true),
+            ],
+            fields
+        );
+    }
+
+    #[test]
+    fn a_corrupt_footer_is_unsupported() {
+        let mut footer = b"\xff\xff\xff\xff\xff".to_vec();
+        footer.extend_from_slice(&5u32.to_le_bytes());
+        footer.extend_from_slice(PARQUET_MAGIC);
+        let err = Schema::parquet(&file("sales.parquet"), &footer).unwrap_err();
+        assert!(matches!(err, AuthError::UnsupportedMedia(_)));
+        assert!(Schema::parquet(&file("sales.parquet"), b"PAR1").is_err());
+    }
+
+    #[test]
+    fn the_distinct_count_is_exact_for_complete_samples() {
+        let counts: HashMap<String, u64> = [("a", 1), ("b", 1), ("c", 2)]
+            .iter()
+            .map(|(value, count)| (value.to_string(), *count))
+            .collect();
+        assert_eq!(3, distinct_count(&counts, true));
+        // Chao1: 3 + 2² / (2 × 1)
+        assert_eq!(5, distinct_count(&counts, false));
+    }
+
+    #[test]
+    fn the_distinct_count_is_estimated_without_doubletons() {
+        let counts: HashMap<String, u64> = [("a", 1), ("b", 1), ("c", 1), ("d", 5)]
+            .iter()
+            .map(|(value, count)| (value.to_string(), *count))
+            .collect();
+        // 4 + 3 × 2 / 2
+        assert_eq!(7, distinct_count(&counts, false));
+        assert_eq!(0, distinct_count(&HashMap::new(), false));
+    }
+
+    #[test]
+    fn the_schema_is_inferred_from_a_sample() {
+        let content =
+            b"id,amount,when,note\n1,2,2022-01-31,\n2,2.5,2022/02/01,x\n2,3,2022-02-02,\n";
+        let preview =
+            Preview::delimited(&file("sales.csv"), Format::Csv, content, false, 10).unwrap();
+        let schema = Schema::sample(&file("sales.csv"), &preview);
+
+        assert!(schema.complete);
+        assert_eq!(3, schema.rows);
+        let (id, amount, when, note) = (
+            &schema.fields[0],
+            &schema.fields[1],
+            &schema.fields[2],
+            &schema.fields[3],
+        );
+        assert_eq!(FieldType::Integer, id.field_type);
+        assert_eq!(Some(2), id.distinct_count);
+        assert_eq!(FieldType::Float, amount.field_type);
+        // the values do not share a format
+        assert_eq!(
+            (FieldType::Date, None),
+            (when.field_type, when.format.clone())
+        );
+        assert_eq!(FieldType::String, note.field_type);
+        assert!(note.nullable);
+        assert_eq!(Some(2.0 / 3.0), note.null_rate);
+    }
+
+    #[test]
+    fn dates_keep_a_shared_format() {
+        let values = [json!("01/31/2022"), json!("02/01/2022"), Value::Null];
+        let field = infer_field("when", values.iter(), 3, false);
+        assert_eq!(FieldType::Date, field.field_type);
+        assert_eq!(Some("%m/%d/%Y".to_string()), field.format);
+        // the sample is not the whole file
+        assert!(field.nullable);
+    }
+}
@@ -0,0 +1,89 @@
+///
+/// Hosts the inferred schemas in redis (project, provider, content hash) -> Schema
+///
+/// The key is the content hash reported by the provider, so a schema is
+/// reused across renames and moves, and a changed file is inferred again.
+/// Files without a content hash (e.g., Google Sheets) are not cached.
+///
+use async_redis_session::RedisTopology;
+use redis::{Cmd, FromRedisValue};
+
+use crate::config::config_get;
+use crate::errors::AuthError;
+use crate::models::drive_provider::DriveProvider;
+use crate::models::project_id::ProjectId;
+use crate::models::schema::Schema;
+
+const KEY_PREFIX: &str = "schemas/";
+
+#[derive(Debug, Clone)]
+pub struct SchemaCache {
+    topology: RedisTopology,
+}
+impl SchemaCache {
+    pub fn new(topology: RedisTopology) -> Self {
+        SchemaCache { topology }
+    }
+    /// workbooks: one schema per sheet
+    fn key(
+        project_id: &ProjectId,
+        drive_provider: &DriveProvider,
+        content_hash: &str,
+        sheet: Option<&str>,
+    ) -> String {
+        format!(
+            "{}{}/{}/{}|{}",
+            KEY_PREFIX,
+            project_id,
+            drive_provider.to_path(),
+            content_hash,
+            sheet.unwrap_or_default()
+        )
+    }
+    fn ttl() -> Result<usize, AuthError> {
+        Ok(config_get()?.options.schema_cache_ttl)
+    }
+    /// sends the command to the node that serves the key (Cluster), over the
+    /// connection shared by the stores
+    async fn query<T: FromRedisValue>(&self, key: &str, cmd: &Cmd) -> Result<T, AuthError> {
+        self.topology.query_for(key, cmd).await.map_err(store_error)
+    }
+    pub async fn get(
+        &self,
+        project_id: &ProjectId,
+        drive_provider: &DriveProvider,
+        content_hash: &str,
+        sheet: Option<&str>,
+    ) -> Result<Option<Schema>, AuthError> {
+        if Self::ttl()? == 0 {
+            return Ok(None);
+        }
+        let key = Self::key(project_id, drive_provider, content_hash, sheet);
+        let record: Option<String> = self.query(&key, &Cmd::get(&key)).await?;
+        Ok(record
+            .map(|value| serde_json::from_str(&value))
+            .transpose()?)
+    }
+    pub async fn set(
+        &self,
+        project_id: &ProjectId,
+        drive_provider: &DriveProvider,
+        content_hash: &str,
+        sheet: Option<&str>,
+        schema: &Schema,
+    ) -> Result<(), AuthError> {
+        let ttl = Self::ttl()?;
+        if ttl == 0 {
+            return Ok(());
+        }
+        let key = Self::key(project_id, drive_provider, content_hash, sheet);
+        self.query(
+            &key,
+            &Cmd::set_ex(&key, serde_json::to_string(schema)?, ttl),
+        )
+        .await
+    }
+}
+fn store_error(err: redis::RedisError) -> AuthError {
+    AuthError::StoreError(err.to_string().into())
+}
@@ -0,0 +1,233 @@
+///
+/// Hosts the store of the auth sessions (the pkce and csrf validators kept
+/// between the two phases of the redirect flow), shared by the handlers.
+///
+/// The backend is selected by the `session_backend` option:
+///
+/// 👉 `redis` the RedisSessionStore (`redis_db`, pooled, optionally encrypted)
+/// 👉 `memory` an in-process store; sessions are lost on restart, and are not
+///    shared by the instances of the service (development, tests)
+/// 👉 `sqlite` a SQLite db (`session_db`); shared by the instances that run
+///    on one host (see SqliteSessionStore)
+///
+/// 🔖 async_session::SessionStore requires Clone (not object safe);
+///    AuthStore wraps a DynSessionStore to host either backend.
+///
+/// The sessions of a user (listed and revoked by the sessions endpoints) are
+/// indexed by the redis backend only; their last seen time is updated as
+/// they are loaded.
+///
+/// 📣 The redis backend publishes the sessions it destroys (logout, revoked,
+///    regenerated, expired) on `session_events_channel`; the tnc services
+///    that cache the validity of a session subscribe using
+///    RedisSessionStore::subscribe.
+///
+use async_redis_session::{KeyRing, RedisSessionStore, RedisTopology};
+use async_session::{async_trait, MemoryStore, Session, SessionStore};
+use secrecy::ExposeSecret;
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::config::Options;
+use crate::errors::AuthError;
+use crate::models::sqlite_session_store::SqliteSessionStore;
+use crate::models::user_sessions;
+
+const KEY_PREFIX: &str = "sessions/";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionBackend {
+    Redis,
+    Memory,
+    Sqlite,
+}
+impl FromStr for SessionBackend {
+    type Err = AuthError;
+    fn from_str(input: &str) -> Result<SessionBackend, Self::Err> {
+        match input {
+            "redis" => Ok(SessionBackend::Redis),
+            "memory" => Ok(SessionBackend::Memory),
+            "sqlite" => Ok(SessionBackend::Sqlite),
+            _ => Err(AuthError::ConfigError(
+                format!(
+                    "Unsupported session backend: {} (redis, memory, sqlite)",
+                    input
+                )
+                .into(),
+            )),
+        }
+    }
+}
+
+///
+/// The object safe subset of SessionStore
+///
+#[async_trait]
+pub trait DynSessionStore: fmt::Debug + Send + Sync {
+    async fn load_session(&self, cookie_value: String) -> async_session::Result<Option<Session>>;
+    async fn store_session(&self, session: Session) -> async_session::Result<Option<String>>;
+    async fn destroy_session(&self, session: Session) -> async_session::Result;
+    async fn clear_store(&self) -> async_session::Result;
+}
+#[async_trait]
+impl<T: SessionStore> DynSessionStore for T {
+    async fn load_session(&self, cookie_value: String) -> async_session::Result<Option<Session>> {
+        SessionStore::load_session(self, cookie_value).await
+    }
+    async fn store_session(&self, session: Session) -> async_session::Result<Option<String>> {
+        SessionStore::store_session(self, session).await
+    }
+    async fn destroy_session(&self, session: Session) -> async_session::Result {
+        SessionStore::destroy_session(self, session).await
+    }
+    async fn clear_store(&self) -> async_session::Result {
+        SessionStore::clear_store(self).await
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AuthStore {
+    store: Arc<dyn DynSessionStore>,
+    /// the same store; hosts the index of the user sessions
+    users: Option<RedisSessionStore>,
+}
+impl AuthStore {
+    pub fn new(options: &Options, redis: &RedisTopology) -> Result<Self, AuthError> {
+        match options.session_backend {
+            SessionBackend::Redis => {
+                // 🔖 the prefix tells the sessions apart from the other keys
+                //    of the db (e.g., expired listings are not sessions)
+                let store = RedisSessionStore::from_topology(redis.clone())
+                    .with_prefix(KEY_PREFIX)
+                    .with_pool_size(options.redis_pool_size.into());
+                let store = match key_ring(options.session_keys.expose_secret())? {
+                    Some(key_ring) => store.with_key_ring(key_ring),
+                    None => store,
+                };
+                let store = match options.session_events_channel.as_str() {
+                    "" => store,
+                    channel => store.with_events(channel),
+                };
+                Ok(AuthStore {
+                    store: Arc::new(store.clone()),
+                    users: Some(store),
+                })
+            }
+            SessionBackend::Memory => Ok(AuthStore::from_store(MemoryStore::new())),
+            SessionBackend::Sqlite => Ok(AuthStore::from_store(SqliteSessionStore::new(
+                &options.session_db,
+            )?)),
+        }
+    }
+    pub fn from_store(store: impl SessionStore) -> Self {
+        AuthStore {
+            store: Arc::new(store),
+            users: None,
+        }
+    }
+    fn users(&self) -> Result<&RedisSessionStore, AuthError> {
+        self.users.as_ref().ok_or_else(|| {
+            AuthError::UnsupportedBackend("The user sessions require the redis backend".into())
+        })
+    }
+    ///
+    /// Moves the session to a new id and returns the new cookie value; the
+    /// old id no longer loads (session fixation). The redis backend moves
+    /// the session in one transaction.
+    ///
+    pub async fn regenerate(&self, session: Session) -> Result<String, AuthError> {
+        let cookie_value = match &self.users {
+            Some(users) => users.regenerate(session).await,
+            None => {
+                let mut regenerated = session.clone();
+                regenerated.regenerate();
+                let cookie_value = self.store.store_session(regenerated).await;
+                self.store.destroy_session(session).await.and(cookie_value)
+            }
+        }
+        .map_err(|err| AuthError::WriteSessionError(err.to_string().into()))?;
+        cookie_value.ok_or_else(|| AuthError::MissingSession("Session expired".into()))
+    }
+    pub async fn user_sessions(&self, user_id: &str) -> Result<Vec<Session>, AuthError> {
+        self.users()?
+            .user_sessions(user_id)
+            .await
+            .map_err(|err| AuthError::ReadSessionError(err.to_string().into()))
+    }
+    pub async fn destroy_user_session(
+        &self,
+        user_id: &str,
+        session_id: &str,
+    ) -> Result<(), AuthError> {
+        let destroyed = self
+            .users()?
+            .destroy_user_session(user_id, session_id)
+            .await
+            .map_err(|err| AuthError::WriteSessionError(err.to_string().into()))?;
+        if destroyed {
+            Ok(())
+        } else {
+            Err(AuthError::SessionNotFound(session_id.to_string().into()))
+        }
+    }
+    pub async fn destroy_user_sessions(&self, user_id: &str) -> Result<usize, AuthError> {
+        self.users()?
+            .destroy_user_sessions(user_id)
+            .await
+            .map_err(|err| AuthError::WriteSessionError(err.to_string().into()))
+    }
+}
+#[async_trait]
+impl SessionStore for AuthStore {
+    /// the sessions of the users record when they were last seen
+    async fn load_session(&self, cookie_value: String) -> async_session::Result<Option<Session>> {
+        match self.store.load_session(cookie_value).await? {
+            Some(mut session) if user_sessions::is_stale(&session) => {
+                user_sessions::touch(&mut session)
+                    .map_err(|err| async_session::Error::msg(err.to_string()))?;
+                self.store.store_session(session.clone()).await?;
+                Ok(Some(session))
+            }
+            session => Ok(session),
+        }
+    }
+    async fn store_session(&self, session: Session) -> async_session::Result<Option<String>> {
+        self.store.store_session(session).await
+    }
+    async fn destroy_session(&self, session: Session) -> async_session::Result {
+        self.store.destroy_session(session).await
+    }
+    async fn clear_store(&self) -> async_session::Result {
+        self.store.clear_store().await
+    }
+}
+///
+/// The keys that encrypt the sessions: `id:key,id:key` (base64); the first
+/// key encrypts new sessions
+///
+pub(crate) fn key_ring(session_keys: &str) -> Result<Option<KeyRing>, AuthError> {
+    let invalid =
+        |message: String| AuthError::ConfigError(format!("session_keys: {}", message).into());
+    session_keys
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .try_fold(None, |key_ring: Option<KeyRing>, entry| {
+            let (id, key) = entry
+                .split_once(':')
+                .ok_or_else(|| invalid("expected id:key".to_string()))?;
+            let id = id
+                .parse()
+                .map_err(|_| invalid(format!("invalid key id {}", id)))?;
+            let key = base64::decode(key).map_err(|err| invalid(err.to_string()))?;
+            let key_ring = match key_ring {
+                Some(key_ring) => key_ring.with_key(id, &key),
+                None => KeyRing::new(id, &key),
+            }
+            .map_err(|err| invalid(err.to_string()))?;
+            Ok(Some(key_ring))
+        })
+}
@@ -0,0 +1,184 @@
+///
+/// The SQLite session backend (`session_backend = sqlite`): a single file
+/// shared by the instances of the service that run on one host.
+///
+/// 👉 `session_db` the sqlite url, e.g., `sqlite://sessions.db?mode=rwc`
+///
+/// The table is created on first use; expired sessions are not loaded,
+/// and are deleted as sessions are stored.
+///
+/// 🔖 The layout of async-sqlx-session (id, expires, session), so the
+///    sessions can be read by its SqliteSessionStore.
+///
+use async_session::{async_trait, serde_json, Result, Session, SessionStore};
+use chrono::Utc;
+use sqlx::sqlite::{SqlitePool, SqlitePoolOptions};
+use std::sync::Arc;
+use tokio::sync::OnceCell;
+
+use crate::errors::AuthError;
+
+const TABLE: &str = "async_sessions";
+
+#[derive(Debug, Clone)]
+pub struct SqliteSessionStore {
+    pool: SqlitePool,
+    /// the table was created
+    migrated: Arc<OnceCell<()>>,
+}
+impl SqliteSessionStore {
+    /// the connections are opened on first use
+    pub fn new(url: &str) -> std::result::Result<Self, AuthError> {
+        let pool = SqlitePoolOptions::new()
+            .connect_lazy(url)
+            .map_err(|err| AuthError::ConfigError(format!("session_db: {}", err).into()))?;
+        Ok(SqliteSessionStore::from_pool(pool))
+    }
+    pub fn from_pool(pool: SqlitePool) -> Self {
+        SqliteSessionStore {
+            pool,
+            migrated: Arc::new(OnceCell::new()),
+        }
+    }
+    async fn migrate(&self) -> Result {
+        self.migrated
+            .get_or_try_init(|| async {
+                sqlx::query(&format!(
+                    "CREATE TABLE IF NOT EXISTS {table} (
+                        id TEXT PRIMARY KEY NOT NULL,
+                        expires INTEGER NULL,
+                        session TEXT NOT NULL
+                    )",
+                    table = TABLE
+                ))
+                .execute(&self.pool)
+                .await
+                .map(|_| ())
+            })
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SessionStore for SqliteSessionStore {
+    async fn load_session(&self, cookie_value: String) -> Result<Option<Session>> {
+        self.migrate().await?;
+        let id = Session::id_from_cookie_value(&cookie_value)?;
+        let record: Option<(String,)> = sqlx::query_as(&format!(
+            "SELECT session FROM {} WHERE id = ? AND (expires IS NULL OR expires > ?)",
+            TABLE
+        ))
+        .bind(&id)
+        .bind(Utc::now().timestamp())
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(record
+            .map(|(session,)| serde_json::from_str(&session))
+            .transpose()?)
+    }
+
+    async fn store_session(&self, session: Session) -> Result<Option<String>> {
+        self.migrate().await?;
+        let now = Utc::now().timestamp();
+        sqlx::query(&format!(
+            "DELETE FROM {} WHERE expires IS NOT NULL AND expires <= ?",
+            TABLE
+        ))
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+
+        let expires = session.expiry().map(|expiry| expiry.timestamp());
+        if expires.map_or(false, |expires| expires <= now) {
+            return Ok(None);
+        }
+        sqlx::query(&format!(
+            "INSERT INTO {} (id, expires, session) VALUES (?, ?, ?)
+             ON CONFLICT(id) DO UPDATE SET
+                expires = excluded.expires,
+                session = excluded.session",
+            TABLE
+        ))
+        .bind(session.id())
+        .bind(expires)
+        .bind(serde_json::to_string(&session)?)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(session.into_cookie_value())
+    }
+
+    async fn destroy_session(&self, session: Session) -> Result {
+        self.migrate().await?;
+        sqlx::query(&format!("DELETE FROM {} WHERE id = ?", TABLE))
+            .bind(session.id())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn clear_store(&self) -> Result {
+        self.migrate().await?;
+        sqlx::query(&format!("DELETE FROM {}", TABLE))
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// one connection: each connection opens its own in-memory db
+    async fn test_store() -> SqliteSessionStore {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        SqliteSessionStore::from_pool(pool)
+    }
+
+    #[tokio::test]
+    async fn storing_loading_and_destroying_a_session() -> Result {
+        let store = test_store().await;
+        let mut session = Session::new();
+        session.insert("key", "value")?;
+        let cookie = store.store_session(session).await?.unwrap();
+
+        let mut session = store.load_session(cookie.clone()).await?.unwrap();
+        assert_eq!(Some("value".to_string()), session.get::<String>("key"));
+
+        session.insert("key", "other")?;
+        assert!(store.store_session(session.clone()).await?.is_none());
+        let loaded = store.load_session(cookie.clone()).await?.unwrap();
+        assert_eq!(Some("other".to_string()), loaded.get::<String>("key"));
+
+        store.destroy_session(session).await?;
+        assert!(store.load_session(cookie).await?.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn expired_sessions_are_not_loaded() -> Result {
+        let store = test_store().await;
+        let mut session = Session::new();
+        session.expire_in(Duration::from_secs(1));
+        let cookie = store.store_session(session).await?.unwrap();
+        assert!(store.load_session(cookie.clone()).await?.is_some());
+
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert!(store.load_session(cookie).await?.is_none());
+
+        // deleted as another session is stored
+        store.store_session(Session::new()).await?;
+        let (count,): (i64,) = sqlx::query_as(&format!("SELECT COUNT(*) FROM {}", TABLE))
+            .fetch_one(&store.pool)
+            .await?;
+        assert_eq!(1, count);
+        Ok(())
+    }
+}
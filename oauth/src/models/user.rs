@@ -31,6 +31,7 @@ use crate::models::oauth_provider::OauthProvider;
 ///
 /// Staging struct for User
 ///
+#[allow(clippy::empty_line_after_doc_comments)]
 #[derive(Debug, Deserialize, Serialize)]
 pub struct RawUser {
     pub id: Uuid,
@@ -50,6 +51,7 @@ impl Default for RawUser {
 }
 impl RawUser {}
 
+#[allow(dead_code)]
 #[derive(Debug, Deserialize)]
 pub struct User {
     pub id: Uuid,
@@ -82,9 +84,13 @@ pub struct RawFromGoogle {
     #[serde(default = "OauthProvider::google")]
     pub provider: OauthProvider,
     pub email: String,
+    #[allow(dead_code)]
     pub verified_email: bool,
+    #[allow(dead_code)]
     pub given_name: Option<String>,
+    #[allow(dead_code)]
     pub family_name: Option<String>,
+    #[allow(dead_code)]
     pub locale: Option<String>,
 }
 impl From<RawFromGoogle> for RawUser {
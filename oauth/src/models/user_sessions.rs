@@ -0,0 +1,91 @@
+///
+/// Hosts the metadata of a user's sessions, returned by the sessions
+/// endpoints (the session data is not returned).
+///
+/// The session is bound to the user once the user is authenticated (see
+/// login_authorized); the user id is `<auth agent>/<auth id>`.
+///
+use async_redis_session::USER_ID_KEY;
+use async_session::Session;
+use chrono::{DateTime, TimeZone, Utc};
+use serde::Serialize;
+
+use crate::errors::AuthError;
+use crate::models::user_registration::UserRegistration;
+
+/// session data entries (seconds since the epoch, and the header value)
+pub const CREATED_AT_KEY: &str = "created_at";
+pub const LAST_SEEN_KEY: &str = "last_seen";
+pub const USER_AGENT_KEY: &str = "user_agent";
+/// seconds between the writes of the last seen time (each load would
+/// otherwise write the session)
+const LAST_SEEN_RESOLUTION: i64 = 60;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionInfo {
+    pub id: String,
+    pub created_at: Option<DateTime<Utc>>,
+    pub last_seen: Option<DateTime<Utc>>,
+    pub user_agent: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    /// the session used to make the request
+    pub current: bool,
+}
+impl SessionInfo {
+    pub fn new(session: &Session, current_id: &str) -> Self {
+        let time = |key| {
+            session
+                .get::<i64>(key)
+                .map(|seconds| Utc.timestamp(seconds, 0))
+        };
+        SessionInfo {
+            id: session.id().to_string(),
+            created_at: time(CREATED_AT_KEY),
+            last_seen: time(LAST_SEEN_KEY),
+            user_agent: session.get(USER_AGENT_KEY),
+            expires_at: session.expiry().copied(),
+            current: session.id() == current_id,
+        }
+    }
+}
+
+pub fn user_id(user_registration: &UserRegistration) -> String {
+    format!(
+        "{}/{}",
+        user_registration.auth_agent, user_registration.auth_id
+    )
+}
+///
+/// Bind the session to the user; the session is stored by the caller
+///
+pub fn bind(
+    session: &mut Session,
+    user_id: &str,
+    user_agent: Option<&str>,
+) -> Result<(), AuthError> {
+    let write = |err: async_session::serde_json::Error| {
+        AuthError::WriteSessionError(format!("Writing the session user: {}", err).into())
+    };
+    session.insert(USER_ID_KEY, user_id).map_err(write)?;
+    if let Some(user_agent) = user_agent {
+        session.insert(USER_AGENT_KEY, user_agent).map_err(write)?;
+    }
+    touch(session)
+}
+///
+/// The session is bound to a user, and was last seen over a minute ago
+///
+pub fn is_stale(session: &Session) -> bool {
+    session.get::<String>(USER_ID_KEY).is_some()
+        && session.get::<i64>(LAST_SEEN_KEY).map_or(true, |last_seen| {
+            Utc::now().timestamp() - last_seen >= LAST_SEEN_RESOLUTION
+        })
+}
+///
+/// Record the time the session was last used
+///
+pub fn touch(session: &mut Session) -> Result<(), AuthError> {
+    session
+        .insert(LAST_SEEN_KEY, Utc::now().timestamp())
+        .map_err(|err| AuthError::WriteSessionError(format!("Writing last seen: {}", err).into()))
+}
@@ -0,0 +1,237 @@
+///
+/// Hosts the webhook subscriptions (provider -> project) in redis, and
+/// publishes the resulting change events for the downstream tnc services.
+///
+/// 👉 subscription id -> Subscription (Google channel, MSGraph subscription)
+/// 👉 (project, provider) -> Subscription (used to renew)
+/// 👉 (provider, account id) -> projects (DropBox notifies by account)
+/// 👉 renewals -> subscription ids by expiry (see due)
+///
+use async_redis_session::RedisTopology;
+use chrono::{DateTime, Utc};
+use redis::{Cmd, FromRedisValue};
+use serde::{Deserialize, Serialize};
+
+use crate::config::config_get;
+use crate::errors::AuthError;
+use crate::models::drive_provider::DriveProvider;
+use crate::models::project_id::ProjectId;
+
+const KEY_PREFIX: &str = "webhooks/";
+/// Seconds; an instance of the service claims a renewal for this long
+const RENEWAL_LEASE: usize = 5 * 60;
+
+///
+/// A registration with the provider to receive change notifications
+///
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Subscription {
+    pub id: String,
+    pub project_id: ProjectId,
+    pub drive_provider: DriveProvider,
+    /// shared secret echoed by the provider with each notification
+    pub client_state: String,
+    /// Google: the channel resource id (required to stop the channel)
+    #[serde(default)]
+    pub resource_id: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+///
+/// Published on the drive events channel
+///
+#[derive(Debug, Clone, Serialize)]
+pub struct DriveEvent {
+    pub project_id: ProjectId,
+    pub drive_provider: DriveProvider,
+    pub received_at: DateTime<Utc>,
+}
+impl DriveEvent {
+    pub fn new(project_id: ProjectId, drive_provider: DriveProvider) -> Self {
+        DriveEvent {
+            project_id,
+            drive_provider,
+            received_at: Utc::now(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Webhooks {
+    topology: RedisTopology,
+}
+impl Webhooks {
+    pub fn new(topology: RedisTopology) -> Self {
+        Webhooks { topology }
+    }
+    fn subscription_key(id: &str) -> String {
+        format!("{}subscriptions/{}", KEY_PREFIX, id)
+    }
+    fn project_key(project_id: &ProjectId, drive_provider: &DriveProvider) -> String {
+        format!(
+            "{}projects/{}/{}",
+            KEY_PREFIX,
+            project_id,
+            drive_provider.to_path()
+        )
+    }
+    fn account_key(drive_provider: &DriveProvider, account_id: &str) -> String {
+        format!(
+            "{}accounts/{}/{}",
+            KEY_PREFIX,
+            drive_provider.to_path(),
+            account_id
+        )
+    }
+    fn renewals_key() -> String {
+        format!("{}renewals", KEY_PREFIX)
+    }
+    fn renewing_key(id: &str) -> String {
+        format!("{}renewing/{}", KEY_PREFIX, id)
+    }
+    /// sends the command to the node that serves the key (Cluster), over the
+    /// connection shared by the stores
+    async fn query<T: FromRedisValue>(&self, key: &str, cmd: &Cmd) -> Result<T, AuthError> {
+        self.topology.query_for(key, cmd).await.map_err(store_error)
+    }
+    pub async fn get(&self, id: &str) -> Result<Option<Subscription>, AuthError> {
+        let key = Self::subscription_key(id);
+        let record: Option<String> = self.query(&key, &Cmd::get(&key)).await?;
+        Ok(record
+            .map(|value| serde_json::from_str(&value))
+            .transpose()?)
+    }
+    pub async fn get_for_project(
+        &self,
+        project_id: &ProjectId,
+        drive_provider: &DriveProvider,
+    ) -> Result<Option<Subscription>, AuthError> {
+        let key = Self::project_key(project_id, drive_provider);
+        let record: Option<String> = self.query(&key, &Cmd::get(&key)).await?;
+        Ok(record
+            .map(|value| serde_json::from_str(&value))
+            .transpose()?)
+    }
+    ///
+    /// Store the subscription under both keys; replaces the previous
+    /// subscription for the project.
+    ///
+    /// The keys may be served by different nodes (Cluster), so they are set
+    /// in turn: the subscription first, then the project's.
+    ///
+    pub async fn set(&self, subscription: &Subscription) -> Result<(), AuthError> {
+        let value = serde_json::to_string(subscription)?;
+
+        if let Some(previous) = self
+            .get_for_project(&subscription.project_id, &subscription.drive_provider)
+            .await?
+            .filter(|previous| previous.id != subscription.id)
+        {
+            let key = Self::subscription_key(&previous.id);
+            let _: () = self.query(&key, &Cmd::del(&key)).await?;
+            let renewals = Self::renewals_key();
+            let _: () = self
+                .query(&renewals, &Cmd::zrem(&renewals, &previous.id))
+                .await?;
+        }
+
+        let seconds = subscription
+            .expires_at
+            .map(|expires_at| (expires_at - Utc::now()).num_seconds().max(1) as usize);
+        for key in [
+            Self::subscription_key(&subscription.id),
+            Self::project_key(&subscription.project_id, &subscription.drive_provider),
+        ] {
+            let cmd = match seconds {
+                Some(seconds) => Cmd::set_ex(&key, &value, seconds),
+                None => Cmd::set(&key, &value),
+            };
+            let _: () = self.query(&key, &cmd).await?;
+        }
+        if let Some(expires_at) = subscription.expires_at {
+            let renewals = Self::renewals_key();
+            let _: () = self
+                .query(
+                    &renewals,
+                    &Cmd::zadd(&renewals, &subscription.id, expires_at.timestamp()),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+    ///
+    /// ⏰ The subscriptions that expire before `before`, claimed for renewal
+    /// (the instances of the service renew each subscription once)
+    ///
+    pub async fn due(&self, before: DateTime<Utc>) -> Result<Vec<Subscription>, AuthError> {
+        let renewals = Self::renewals_key();
+        let ids: Vec<String> = self
+            .query(
+                &renewals,
+                &Cmd::zrangebyscore(&renewals, "-inf", before.timestamp()),
+            )
+            .await?;
+        let mut due = Vec::new();
+        for id in ids {
+            match self.get(&id).await? {
+                Some(subscription) => {
+                    let key = Self::renewing_key(&id);
+                    let claimed: Option<String> = self
+                        .query(
+                            &key,
+                            redis::cmd("SET")
+                                .arg(&key)
+                                .arg(1)
+                                .arg("NX")
+                                .arg("EX")
+                                .arg(RENEWAL_LEASE),
+                        )
+                        .await?;
+                    if claimed.is_some() {
+                        due.push(subscription);
+                    }
+                }
+                // expired, or replaced
+                None => {
+                    let _: () = self.query(&renewals, &Cmd::zrem(&renewals, &id)).await?;
+                }
+            }
+        }
+        Ok(due)
+    }
+    pub async fn add_account(
+        &self,
+        drive_provider: &DriveProvider,
+        account_id: &str,
+        project_id: &ProjectId,
+    ) -> Result<(), AuthError> {
+        let key = Self::account_key(drive_provider, account_id);
+        self.query(&key, &Cmd::sadd(&key, project_id.to_string()))
+            .await
+    }
+    pub async fn projects_for_account(
+        &self,
+        drive_provider: &DriveProvider,
+        account_id: &str,
+    ) -> Result<Vec<ProjectId>, AuthError> {
+        let key = Self::account_key(drive_provider, account_id);
+        let members: Vec<String> = self.query(&key, &Cmd::smembers(&key)).await?;
+        members.into_iter().map(ProjectId::try_from).collect()
+    }
+    ///
+    /// 📣 Publish the event on the configured channel
+    ///
+    pub async fn publish(&self, event: &DriveEvent) -> Result<(), AuthError> {
+        let channel = config_get()?.options.drive_events_channel.clone();
+        tracing::debug!("\n📣 {}:\n{:#?}\n", &channel, event);
+        // Cluster: messages are broadcast to the subscribers of all nodes
+        self.query(
+            &channel,
+            &Cmd::publish(&channel, serde_json::to_string(event)?),
+        )
+        .await
+    }
+}
+fn store_error(err: redis::RedisError) -> AuthError {
+    AuthError::StoreError(err.to_string().into())
+}
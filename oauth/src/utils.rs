@@ -5,6 +5,7 @@ use std::fmt::Display;
 use std::str::FromStr;
 
 use serde::{Deserialize, Deserializer, Serializer};
+use url::Url;
 
 pub fn deserialize_number_from_string<'de, T, D>(deserializer: D) -> Result<T, D::Error>
 where
@@ -25,7 +26,30 @@ where
     }
 }
 
+pub fn deserialize_option_number_from_string<'de, T, D>(
+    deserializer: D,
+) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr + serde::Deserialize<'de>,
+    <T as FromStr>::Err: Display,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrInt<T> {
+        String(String),
+        Number(T),
+    }
+
+    match Option::<StringOrInt<T>>::deserialize(deserializer)? {
+        Some(StringOrInt::String(s)) => s.parse::<T>().map(Some).map_err(serde::de::Error::custom),
+        Some(StringOrInt::Number(i)) => Ok(Some(i)),
+        None => Ok(None),
+    }
+}
+
 // Url to String and back
+#[allow(dead_code)]
 fn url_to_str<S>(url: &Option<Url>, serializer: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
@@ -37,6 +61,7 @@ where
     };
     serializer.serialize_str(value)
 }
+#[allow(dead_code)]
 fn str_to_url<'de, D>(deserializer: D) -> Result<Option<Url>, D::Error>
 where
     D: Deserializer<'de>,